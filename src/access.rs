@@ -0,0 +1,55 @@
+use crate::{DecodePolicy, Error, MappedFile, TextSource};
+
+/// The lookups shared by every way of reading a file,
+/// so code can be generic over whether the file is memory mapped or buffered
+pub trait UnicodeAccess {
+    /// Returns the character at the given index.
+    /// Returns an error if the index is out of bounds.
+    fn unicode_at(&mut self, index: usize) -> Result<char, Error>;
+
+    /// Returns the number of characters in the file.
+    fn char_count(&mut self) -> Result<usize, Error>;
+
+    /// Returns the number of lines in the file.
+    fn line_count(&mut self) -> Result<usize, Error>;
+
+    /// Returns a copy of the text of a line without its trailing newline.
+    /// Invalid bytes are replaced with U+FFFD if the file decodes lossily.
+    /// Returns an error if the line is out of bounds.
+    fn line_string(&mut self, line_number: usize) -> Result<String, Error>;
+}
+
+impl<S: TextSource> UnicodeAccess for MappedFile<S> {
+    fn unicode_at(&mut self, index: usize) -> Result<char, Error> {
+        MappedFile::unicode_at(self, index)
+    }
+
+    fn char_count(&mut self) -> Result<usize, Error> {
+        MappedFile::char_count(self)
+    }
+
+    fn line_count(&mut self) -> Result<usize, Error> {
+        MappedFile::line_count(self)
+    }
+
+    fn line_string(&mut self, line_number: usize) -> Result<String, Error> {
+        self.owned_line(line_number)
+    }
+}
+
+impl<S: TextSource> MappedFile<S> {
+    /// Copies the text of a line without its terminator, replacing invalid bytes unless the file decodes strictly
+    pub(crate) fn owned_line(&self, line_number: usize) -> Result<String, Error> {
+        match self.decode_policy() {
+            DecodePolicy::Strict => self.line_at(line_number).map(str::to_owned),
+            // Replacements can't be borrowed from the file so the line has to be copied anyway
+            DecodePolicy::Lossy | DecodePolicy::Bytes => {
+                let (text, _) = self.line_parts(line_number)?;
+                let start = self.with_lines(|lines| lines.at(line_number))?;
+                self.map.with_bytes(text, |bytes| {
+                    Ok(self.decode_policy.decode(bytes, start)?.into_owned())
+                })?
+            }
+        }
+    }
+}
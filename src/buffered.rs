@@ -0,0 +1,29 @@
+use {
+    crate::{BufferedSource, Error, MappedFile},
+    std::fs::File,
+};
+
+/// A file read through `seek`/`read` with a cache of recently used blocks,
+/// for inputs that shouldn't be memory mapped (NFS, FUSE, security policies...),
+/// indexed the same way as a mapped file
+pub type BufferedFile = MappedFile<BufferedSource>;
+
+impl BufferedFile {
+    /// Creates a new BufferedFile from a File with a cache of 64 blocks of 64 KiB
+    /// possibly returning an error
+    pub fn buffered(file: File) -> Result<BufferedFile, Error> {
+        Ok(MappedFile::from_source(BufferedSource::new(file)?))
+    }
+
+    /// Creates a new BufferedFile from a File caching at most `max_blocks` blocks of `block_size` bytes
+    /// possibly returning an error
+    pub fn with_cache(
+        file: File,
+        block_size: usize,
+        max_blocks: usize,
+    ) -> Result<BufferedFile, Error> {
+        Ok(MappedFile::from_source(BufferedSource::with_cache(
+            file, block_size, max_blocks,
+        )?))
+    }
+}
@@ -0,0 +1,223 @@
+use {
+    crate::{
+        mapping::MapOptions, Access, DecodePolicy, Error, LineEndings, MappedFile, Mapping,
+        DEFAULT_CHECKPOINT_INTERVAL,
+    },
+    std::{fs::File, path::Path},
+};
+
+/// Options for opening a MappedFile
+#[derive(Clone, Debug)]
+pub struct MappedFileBuilder {
+    /// Which characters end a line
+    line_endings: LineEndings,
+    /// Whether to index the whole file when it's opened instead of as lookups reach it
+    eager: bool,
+    /// How the file is mapped
+    map_options: MapOptions,
+    /// How the mapped pages will be accessed
+    access: Access,
+    /// How many characters apart checkpoints are recorded, if at all
+    checkpoint_interval: Option<usize>,
+    /// What to do with bytes that aren't valid utf8
+    decode_policy: DecodePolicy,
+    /// Whether line starts are stored as deltas
+    compact_index: bool,
+    /// Whether to build rank samples for going between character indices and byte offsets
+    char_map: bool,
+    /// How many bytes the line starts can take up before some are evicted, if there's a limit
+    index_memory_limit: Option<usize>,
+    /// Whether lookups check the file hasn't changed first
+    check_stale: bool,
+    /// Whether reads check the file hasn't been truncated first
+    guarded: bool,
+    /// Whether opening fails if the file looks like it's in another encoding
+    require_utf8: bool,
+}
+
+impl Default for MappedFileBuilder {
+    fn default() -> MappedFileBuilder {
+        MappedFileBuilder {
+            line_endings: LineEndings::default(),
+            eager: false,
+            map_options: MapOptions::default(),
+            access: Access::default(),
+            checkpoint_interval: Some(DEFAULT_CHECKPOINT_INTERVAL),
+            decode_policy: DecodePolicy::default(),
+            compact_index: true,
+            char_map: false,
+            index_memory_limit: None,
+            check_stale: false,
+            guarded: false,
+            require_utf8: false,
+        }
+    }
+}
+
+impl MappedFileBuilder {
+    /// Creates a builder with the same defaults as `MappedFile::new`
+    pub fn new() -> MappedFileBuilder {
+        MappedFileBuilder::default()
+    }
+
+    /// Sets which characters end a line
+    pub fn line_endings(mut self, line_endings: LineEndings) -> MappedFileBuilder {
+        self.line_endings = line_endings;
+        self
+    }
+
+    /// Sets whether to index the whole file when it's opened instead of as lookups reach it
+    pub fn eager(mut self, eager: bool) -> MappedFileBuilder {
+        self.eager = eager;
+        self
+    }
+
+    /// Sets whether to fault in every page of the mapping when it's created (`MAP_POPULATE` on Linux)
+    pub fn populate(mut self, populate: bool) -> MappedFileBuilder {
+        self.map_options.populate = populate;
+        self
+    }
+
+    /// Sets whether to copy the whole file into memory of its own when it's opened instead of mapping it,
+    /// so nothing writing to or truncating the file afterwards can change what's read.
+    /// It takes as much memory as the file is long, but see `MappedFile::reload` for picking up changes on purpose
+    pub fn private_copy(mut self, copy: bool) -> MappedFileBuilder {
+        self.map_options.copy = copy;
+        self
+    }
+
+    /// Sets the hint given to the OS about how the mapped pages will be accessed
+    pub fn access(mut self, access: Access) -> MappedFileBuilder {
+        self.access = access;
+        self
+    }
+
+    /// Sets how many characters apart checkpoints are recorded, so lookups into files
+    /// with few or no newlines don't have to scan from the start of a huge line.
+    /// `None` turns them off and only line starts are cached.
+    pub fn checkpoint_interval(mut self, interval: Option<usize>) -> MappedFileBuilder {
+        self.checkpoint_interval = interval;
+        self
+    }
+
+    /// Sets what to do with bytes that aren't valid utf8
+    pub fn decode_policy(mut self, decode_policy: DecodePolicy) -> MappedFileBuilder {
+        self.decode_policy = decode_policy;
+        self
+    }
+
+    /// Sets whether line starts are stored as deltas from one another, which is on by default,
+    /// see `MappedFile::set_compact_index`
+    pub fn compact_index(mut self, compact: bool) -> MappedFileBuilder {
+        self.compact_index = compact;
+        self
+    }
+
+    /// Sets whether to build rank and select samples over the file when it's opened,
+    /// see `MappedFile::build_char_map`
+    pub fn char_map(mut self, char_map: bool) -> MappedFileBuilder {
+        self.char_map = char_map;
+        self
+    }
+
+    /// Sets how many bytes the cached line starts can take up before some are evicted,
+    /// see `MappedFile::set_index_memory_limit`
+    pub fn index_memory_limit(mut self, limit: Option<usize>) -> MappedFileBuilder {
+        self.index_memory_limit = limit;
+        self
+    }
+
+    /// Sets whether lookups check the file hasn't changed since it was opened,
+    /// see `MappedFile::set_check_stale`
+    pub fn check_stale(mut self, check: bool) -> MappedFileBuilder {
+        self.check_stale = check;
+        self
+    }
+
+    /// Sets whether reads from the mapping check the file hasn't been truncated first,
+    /// see `Mapping::set_guarded`
+    pub fn guarded(mut self, guarded: bool) -> MappedFileBuilder {
+        self.guarded = guarded;
+        self
+    }
+
+    /// Sets whether to copy the whole file into memory backed by huge pages when it's opened, on Linux,
+    /// which makes scanning a very large file cheaper on the TLB.
+    /// It implies `private_copy`, and uses the reserved pool if there is one or else transparent huge pages.
+    /// Whether it worked can be checked with `MappedFile::huge_pages`; elsewhere it only makes a private copy
+    pub fn huge_pages(mut self, huge_pages: bool) -> MappedFileBuilder {
+        self.map_options.huge_pages = huge_pages;
+        self
+    }
+
+    /// Sets whether to lock the whole file into memory when it's opened, see `MappedFile::lock`
+    pub fn lock(mut self, lock: bool) -> MappedFileBuilder {
+        self.map_options.lock = lock;
+        self
+    }
+
+    /// Sets whether a UTF-8 byte order mark at the start of the file is skipped, which it is by default,
+    /// so character and line offsets match what other tools give.
+    /// With it off the mark is read as a U+FEFF at index 0
+    pub fn skip_bom(mut self, skip: bool) -> MappedFileBuilder {
+        self.map_options.keep_bom = !skip;
+        self
+    }
+
+    /// Sets whether opening fails with `Error::UnsupportedEncoding` if the file looks like it's in an encoding
+    /// other than UTF-8, like UTF-16 from Windows tools, rather than failing later on the first invalid byte.
+    /// See `MappedFile::detect_encoding`
+    pub fn require_utf8(mut self, require: bool) -> MappedFileBuilder {
+        self.require_utf8 = require;
+        self
+    }
+
+    /// Opens a MappedFile from a File with these options
+    pub fn open(self, file: File) -> Result<MappedFile, Error> {
+        let map = Mapping::open(file, self.map_options)?;
+        self.open_mapping(map)
+    }
+
+    /// Opens a MappedFile from a path with these options,
+    /// remembering the path so `MappedFile::reload` can open it again
+    pub fn open_path(self, path: impl AsRef<Path>) -> Result<MappedFile, Error> {
+        let map = Mapping::open_path(path.as_ref(), self.map_options)?;
+        self.open_mapping(map)
+    }
+
+    /// Sets up a MappedFile over a mapping with these options
+    fn open_mapping(self, mut map: Mapping) -> Result<MappedFile, Error> {
+        map.advise(self.access)?;
+        map.set_guarded(self.guarded);
+
+        let mut file = MappedFile::from_source(map);
+        file.set_line_endings(self.line_endings);
+        file.set_checkpoint_interval(self.checkpoint_interval);
+        file.set_decode_policy(self.decode_policy);
+        file.set_compact_index(self.compact_index);
+        file.set_index_memory_limit(self.index_memory_limit);
+        file.set_check_stale(self.check_stale);
+
+        if self.require_utf8 {
+            let encoding = file.detect_encoding()?;
+            if !encoding.is_utf8() {
+                return Err(Error::UnsupportedEncoding(encoding));
+            }
+        }
+        if self.eager {
+            file.build_index()?;
+        }
+        if self.char_map {
+            file.build_char_map()?;
+        }
+
+        Ok(file)
+    }
+}
+
+impl MappedFile {
+    /// Creates a builder for opening a MappedFile with options
+    pub fn builder() -> MappedFileBuilder {
+        MappedFileBuilder::new()
+    }
+}
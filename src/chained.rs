@@ -0,0 +1,173 @@
+use {
+    crate::{Error, MappedFile, Mapping, TextSource, UnicodeAccess},
+    std::ops::Range,
+};
+
+/// Several files read one after another as if they were one, like a log and the files it was rotated into.
+/// Characters and lines are counted across all of them, and a file that doesn't end with a newline
+/// carries its last line on into the next file.
+/// Each file is indexed on its own, so a lookup only has to count the characters and lines of the files before it
+pub struct ChainedFile<S: TextSource = Mapping> {
+    files: Vec<MappedFile<S>>,
+}
+
+impl<S: TextSource> ChainedFile<S> {
+    /// Chains files together in the order given
+    pub fn new(files: Vec<MappedFile<S>>) -> ChainedFile<S> {
+        ChainedFile { files }
+    }
+
+    /// Returns the files in the chain
+    pub fn files(&self) -> &[MappedFile<S>] {
+        &self.files
+    }
+
+    /// Gives back the files in the chain
+    pub fn into_files(self) -> Vec<MappedFile<S>> {
+        self.files
+    }
+
+    /// Returns the number of characters in every file put together
+    pub fn char_count(&self) -> Result<usize, Error> {
+        self.files.iter().map(MappedFile::char_count).sum()
+    }
+
+    /// Returns the number of lines in every file put together,
+    /// where the last line of each file and the first line of the next are the same line
+    pub fn line_count(&self) -> Result<usize, Error> {
+        let mut lines = 1;
+        for file in &self.files {
+            lines += file.line_count()? - 1;
+        }
+        Ok(lines)
+    }
+
+    /// Finds which file the character at the given index is in,
+    /// returning the number of the file and the index of the character within it.
+    /// The index one past the last character is in the last file.
+    /// Returns an error if the index is out of bounds.
+    pub fn locate(&self, index: usize) -> Result<(usize, usize), Error> {
+        let mut start = 0;
+        for (number, file) in self.files.iter().enumerate() {
+            let chars = file.char_count()?;
+            if index < start + chars {
+                return Ok((number, index - start));
+            }
+            start += chars;
+        }
+
+        match self.files.len() {
+            count if index == start && count > 0 => {
+                Ok((count - 1, self.files[count - 1].char_count()?))
+            }
+            _ => Err(Error::OutOfBounds { index, len: start }),
+        }
+    }
+
+    /// Returns the indices of the characters of a file within the chain.
+    /// Returns an error if there's no such file.
+    pub fn file_range(&self, number: usize) -> Result<Range<usize>, Error> {
+        if number >= self.files.len() {
+            return Err(Error::OutOfBounds {
+                index: number,
+                len: self.files.len(),
+            });
+        }
+
+        let mut start = 0;
+        for file in &self.files[..number] {
+            start += file.char_count()?;
+        }
+        Ok(start..start + self.files[number].char_count()?)
+    }
+
+    /// Returns the character at the given index.
+    /// Returns an error if the index is out of bounds.
+    pub fn unicode_at(&self, index: usize) -> Result<char, Error> {
+        let (number, index) = self.locate(index)?;
+        self.files[number].unicode_at(index)
+    }
+
+    /// Returns the character at the given index, or `None` if it's out of bounds or can't be decoded
+    pub fn get(&self, index: usize) -> Option<char> {
+        self.unicode_at(index).ok()
+    }
+
+    /// Copies the characters in a range, which can cross from one file into the next.
+    /// Returns an error if the range is out of bounds.
+    pub fn string_range(&self, range: Range<usize>) -> Result<String, Error> {
+        let len = self.char_count()?;
+        if range.start > range.end || range.end > len {
+            return Err(Error::OutOfBounds {
+                index: range.end.max(range.start),
+                len,
+            });
+        }
+
+        let mut text = String::new();
+        let mut start = 0;
+        for file in &self.files {
+            let chars = file.char_count()?;
+            let (from, to) = (range.start.max(start), range.end.min(start + chars));
+            if from < to {
+                file.write_range_to(from - start..to - start, &mut text)?;
+            }
+            start += chars;
+        }
+        Ok(text)
+    }
+
+    /// Copies the text of a line without its terminator, joining the pieces of it in different files.
+    /// Invalid bytes are replaced with U+FFFD if the file they're in decodes lossily.
+    /// Returns an error if the line is out of bounds.
+    pub fn line_string(&self, line_number: usize) -> Result<String, Error> {
+        let mut first_line = 0;
+        for (number, file) in self.files.iter().enumerate() {
+            let lines = file.line_count()?;
+            let line = line_number - first_line;
+            if line >= lines {
+                first_line += lines - 1;
+                continue;
+            }
+
+            let mut text = file.owned_line(line)?;
+            // The last line carries on into the next file, and through any that are only part of it
+            if line == lines - 1 {
+                for next in &self.files[number + 1..] {
+                    text.push_str(&next.owned_line(0)?);
+                    if next.line_count()? > 1 {
+                        break;
+                    }
+                }
+            }
+            return Ok(text);
+        }
+
+        // Without any files the chain is empty, which is still one empty line
+        match line_number {
+            0 if self.files.is_empty() => Ok(String::new()),
+            _ => Err(Error::OutOfBounds {
+                index: line_number,
+                len: first_line + 1,
+            }),
+        }
+    }
+}
+
+impl<S: TextSource> UnicodeAccess for ChainedFile<S> {
+    fn unicode_at(&mut self, index: usize) -> Result<char, Error> {
+        ChainedFile::unicode_at(self, index)
+    }
+
+    fn char_count(&mut self) -> Result<usize, Error> {
+        ChainedFile::char_count(self)
+    }
+
+    fn line_count(&mut self) -> Result<usize, Error> {
+        ChainedFile::line_count(self)
+    }
+
+    fn line_string(&mut self, line_number: usize) -> Result<String, Error> {
+        ChainedFile::line_string(self, line_number)
+    }
+}
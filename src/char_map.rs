@@ -0,0 +1,159 @@
+use crate::{
+    decode_str, is_continuation_byte, stretch_end, CharPosition, Error, TextSource, SCAN_STEP,
+};
+
+/// How many bytes each rank sample covers
+const BLOCK_LEN: usize = 512;
+
+/// How many characters apart select samples are recorded.
+/// Characters are at most 4 bytes, so the characters between two samples span at most 5 blocks
+const SELECT_INTERVAL: usize = 512;
+
+/// Converts between character indices and byte offsets anywhere in a valid utf8 file
+/// without going through lines, in constant time.
+/// Whether a byte starts a character can be read straight off the file, so rather than a bitvector
+/// only the number of characters before every `BLOCK_LEN` bytes is kept, about 0.125 bits a byte,
+/// along with which block every `SELECT_INTERVAL`th character is in, at most as much again.
+/// Going from a byte offset counts through at most one block, and going from a character index
+/// looks through the at most 5 rank samples between two select samples first
+#[derive(Clone, Debug)]
+pub(crate) struct CharMap {
+    /// The number of characters that start before each block
+    ranks: Vec<usize>,
+    /// The block every `SELECT_INTERVAL`th character starts in
+    selects: Vec<usize>,
+    /// The number of characters in the file
+    chars: usize,
+    /// The number of bytes in the file
+    len: usize,
+}
+
+impl CharMap {
+    /// Counts the characters in each block of the file, checking it's all valid utf8 on the way
+    /// since only then does every character start at a byte that isn't a continuation.
+    /// Returns an error if the file isn't valid utf8.
+    pub(crate) fn build(source: &impl TextSource) -> Result<CharMap, Error> {
+        let len = source.len();
+        let mut ranks = Vec::with_capacity(len.div_ceil(BLOCK_LEN));
+        let mut selects = Vec::new();
+        let mut chars = 0;
+
+        let mut byte_position = 0;
+        while byte_position < len {
+            let window_end = (byte_position + SCAN_STEP).min(len);
+            let range = byte_position..(window_end + 4).min(len);
+            let end = source.with_bytes(range, |bytes| {
+                let end = stretch_end(bytes, window_end - byte_position, window_end == len);
+                decode_str(
+                    &bytes[..end],
+                    CharPosition {
+                        byte_position,
+                        char_position: chars,
+                    },
+                )?;
+
+                // Blocks are counted separately from the stretches so they line up across the whole file
+                for (offset, &byte) in bytes[..end].iter().enumerate() {
+                    if (byte_position + offset).is_multiple_of(BLOCK_LEN) {
+                        ranks.push(chars);
+                    }
+                    if !is_continuation_byte(byte) {
+                        if chars % SELECT_INTERVAL == 0 {
+                            selects.push((byte_position + offset) / BLOCK_LEN);
+                        }
+                        chars += 1;
+                    }
+                }
+                Ok::<_, Error>(end)
+            })??;
+            byte_position += end;
+        }
+
+        Ok(CharMap {
+            ranks,
+            selects,
+            chars,
+            len,
+        })
+    }
+
+    /// Returns the number of characters in the file
+    pub(crate) fn chars(&self) -> usize {
+        self.chars
+    }
+
+    /// Roughly how many bytes the rank and select samples take up
+    pub(crate) fn memory_bytes(&self) -> usize {
+        (self.ranks.len() + self.selects.len()) * std::mem::size_of::<usize>()
+    }
+
+    /// Returns the index of the character starting at a byte offset,
+    /// which has to be the start of a character or the end of the file
+    pub(crate) fn rank(&self, source: &impl TextSource, byte: usize) -> Result<usize, Error> {
+        if byte >= self.len {
+            return Ok(self.chars);
+        }
+
+        let block = byte / BLOCK_LEN;
+        let block_start = block * BLOCK_LEN;
+        let starts = source.with_bytes(block_start..byte, |bytes| {
+            bytes
+                .iter()
+                .filter(|&&byte| !is_continuation_byte(byte))
+                .count()
+        })?;
+        Ok(self.ranks[block] + starts)
+    }
+
+    /// Returns the position of the character at an index.
+    /// The index one past the last character resolves to the end of the file.
+    /// Returns an error if the index is out of bounds or the file has changed since the map was built.
+    pub(crate) fn select(
+        &self,
+        source: &impl TextSource,
+        index: usize,
+    ) -> Result<CharPosition, Error> {
+        if index >= self.chars {
+            if index == self.chars {
+                return Ok(CharPosition {
+                    byte_position: self.len,
+                    char_position: index,
+                });
+            }
+            return Err(Error::OutOfBounds {
+                index,
+                len: self.chars,
+            });
+        }
+
+        // The select samples either side of the index narrow it down to a few blocks,
+        // and the last of those with fewer characters before it than the index holds the character
+        let sample = index / SELECT_INTERVAL;
+        let first = self.selects[sample];
+        let last = self
+            .selects
+            .get(sample + 1)
+            .copied()
+            .unwrap_or(self.ranks.len() - 1);
+        let block = first + self.ranks[first..=last].partition_point(|&rank| rank <= index) - 1;
+        let block_start = block * BLOCK_LEN;
+        let block_end = (block_start + BLOCK_LEN).min(self.len);
+
+        let remaining = index - self.ranks[block];
+        let offset = source.with_bytes(block_start..block_end, |bytes| {
+            bytes
+                .iter()
+                .enumerate()
+                .filter(|&(_, &byte)| !is_continuation_byte(byte))
+                .nth(remaining)
+                .map(|(offset, _)| offset)
+        })?;
+
+        // The ranks say the character starts in this block, so it can only be missing if the file changed
+        let offset = offset.ok_or(Error::FileChanged)?;
+        Ok(CharPosition {
+            byte_position: block_start + offset,
+            char_position: index,
+        })
+    }
+}
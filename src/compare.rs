@@ -0,0 +1,77 @@
+use crate::{DecodePolicy, MappedFile, TextSource, SCAN_STEP};
+
+impl<S: TextSource> MappedFile<S> {
+    /// Checks the whole file decodes to exactly the given characters, reading it a character at a time
+    fn eq_chars(&self, other: impl Iterator<Item = char>) -> bool {
+        let mut cursor = self.cursor();
+        // The cursor stops early at invalid utf8, so it has to have made it to the end
+        cursor.by_ref().eq(other) && cursor.position().byte_position == self.map.len()
+    }
+
+    /// Checks the whole file decodes to exactly the given text
+    fn eq_str(&self, other: &str) -> bool {
+        match self.decode_policy {
+            // Valid text can only be equal to exactly the same bytes
+            DecodePolicy::Strict => {
+                self.map.len() == other.len()
+                    && other
+                        .as_bytes()
+                        .chunks(SCAN_STEP)
+                        .enumerate()
+                        .all(|(i, chunk)| {
+                            let start = i * SCAN_STEP;
+                            self.map
+                                .with_bytes(start..start + chunk.len(), |bytes| bytes == chunk)
+                                .unwrap_or(false)
+                        })
+            }
+            DecodePolicy::Lossy | DecodePolicy::Bytes => self.eq_chars(other.chars()),
+        }
+    }
+}
+
+/// A file is equal to text if it decodes to exactly that text.
+/// A file that isn't valid utf8 is never equal to anything unless it decodes lossily
+impl<S: TextSource> PartialEq<str> for MappedFile<S> {
+    fn eq(&self, other: &str) -> bool {
+        self.eq_str(other)
+    }
+}
+
+impl<S: TextSource> PartialEq<&str> for MappedFile<S> {
+    fn eq(&self, other: &&str) -> bool {
+        self.eq_str(other)
+    }
+}
+
+impl<S: TextSource> PartialEq<String> for MappedFile<S> {
+    fn eq(&self, other: &String) -> bool {
+        self.eq_str(other)
+    }
+}
+
+impl<S: TextSource> PartialEq<MappedFile<S>> for str {
+    fn eq(&self, other: &MappedFile<S>) -> bool {
+        other.eq_str(self)
+    }
+}
+
+impl<S: TextSource> PartialEq<MappedFile<S>> for &str {
+    fn eq(&self, other: &MappedFile<S>) -> bool {
+        other.eq_str(self)
+    }
+}
+
+impl<S: TextSource> PartialEq<MappedFile<S>> for String {
+    fn eq(&self, other: &MappedFile<S>) -> bool {
+        other.eq_str(self)
+    }
+}
+
+/// Two files are equal if they both decode to the same text, however they're read
+impl<S: TextSource, T: TextSource> PartialEq<MappedFile<T>> for MappedFile<S> {
+    fn eq(&self, other: &MappedFile<T>) -> bool {
+        let mut chars = other.cursor();
+        self.eq_chars(chars.by_ref()) && chars.position().byte_position == other.map.len()
+    }
+}
@@ -0,0 +1,202 @@
+use crate::{CharPosition, Error, MappedFile, Mapping, TextSource};
+
+/// A cursor borrowing a file that moves backwards and forwards a character at a time,
+/// so stepping around near where it already is never has to search the index.
+/// Reading forwards and backwards stops at invalid utf8 unless decoding lossily
+pub struct Cursor<'a, S: TextSource = Mapping> {
+    file: &'a MappedFile<S>,
+    /// Where the next character will be read from
+    position: CharPosition,
+}
+
+impl<S: TextSource> MappedFile<S> {
+    /// Creates a new cursor over the file at the start
+    pub fn cursor(&self) -> Cursor<'_, S> {
+        self.cursor_at(CharPosition {
+            byte_position: 0,
+            char_position: 0,
+        })
+    }
+
+    /// Creates a new cursor at a position that's already been found
+    pub(crate) fn cursor_at(&self, position: CharPosition) -> Cursor<'_, S> {
+        Cursor {
+            file: self,
+            position,
+        }
+    }
+}
+
+impl<'a, S: TextSource> Cursor<'a, S> {
+    /// Returns where the cursor is, which is the position of the character `next` would read
+    pub fn position(&self) -> CharPosition {
+        self.position
+    }
+
+    /// Returns the character after the cursor without moving it
+    pub fn peek(&self) -> Option<char> {
+        self.char_after().map(|(c, _)| c)
+    }
+
+    /// Moves the cursor back over the character before it and returns it,
+    /// or `None` at the start of the file
+    pub fn prev(&mut self) -> Option<char> {
+        let byte_position = self.position.byte_position;
+        let window_start = byte_position.saturating_sub(4);
+        let decode_policy = self.file.decode_policy;
+        let (c, start) = self
+            .file
+            .map
+            .with_bytes(window_start..byte_position, |window| {
+                decode_policy.decode_previous(window)
+            })
+            .ok()??;
+
+        self.position = CharPosition {
+            byte_position: window_start + start,
+            char_position: self.position.char_position - 1,
+        };
+        Some(c)
+    }
+
+    /// Moves the cursor to the character at the given index.
+    /// The index one past the last character is allowed, leaving nothing left to read.
+    /// Returns an error if the index is out of bounds.
+    pub fn seek_to_char(&mut self, index: usize) -> Result<(), Error> {
+        self.position = self.file.position_at(index)?;
+        Ok(())
+    }
+
+    /// Moves the cursor to a zero-based line and column, counted in characters, see `MappedFile::position_of`.
+    /// Returns an error if the line or column is out of bounds.
+    pub fn seek_to_line_col(&mut self, line: usize, column: usize) -> Result<(), Error> {
+        self.position = self.file.position_of(line, column)?;
+        Ok(())
+    }
+
+    /// Returns the file the cursor is over
+    pub fn file(&self) -> &'a MappedFile<S> {
+        self.file
+    }
+
+    /// Decodes the character after the cursor along with its length in bytes
+    fn char_after(&self) -> Option<(char, usize)> {
+        let map = &self.file.map;
+        let byte_position = self.position.byte_position;
+        let end = (byte_position + 4).min(map.len());
+        map.with_bytes(byte_position..end, |bytes| {
+            self.file.decode_policy.decode_next(bytes)
+        })
+        .ok()?
+    }
+}
+
+/// Reads the characters from the cursor onwards
+impl<S: TextSource> Iterator for Cursor<'_, S> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let (c, width) = self.char_after()?;
+        self.position = CharPosition {
+            byte_position: self.position.byte_position + width,
+            char_position: self.position.char_position + 1,
+        };
+        Some(c)
+    }
+}
+
+/// A clone borrows the same file, starting wherever this one is
+impl<S: TextSource> Clone for Cursor<'_, S> {
+    fn clone(&self) -> Self {
+        Cursor {
+            file: self.file,
+            position: self.position,
+        }
+    }
+}
+
+/// Iterating over a borrowed file reads its characters from the start with a cursor
+impl<'a, S: TextSource> IntoIterator for &'a MappedFile<S> {
+    type Item = char;
+    type IntoIter = Cursor<'a, S>;
+
+    fn into_iter(self) -> Cursor<'a, S> {
+        self.cursor()
+    }
+}
+
+/// Reads the characters of a file it owns, see `MappedFile::into_iter`
+pub struct IntoChars<S: TextSource = Mapping> {
+    file: MappedFile<S>,
+    /// Where the next character will be read from
+    position: CharPosition,
+    /// Whether an error has been returned, after which there's nothing more to read
+    failed: bool,
+}
+
+/// Iterating over an owned file reads its characters from the start, giving an error and then stopping
+/// at invalid utf8 rather than just stopping, unless decoding lossily
+impl<S: TextSource> IntoIterator for MappedFile<S> {
+    type Item = Result<char, Error>;
+    type IntoIter = IntoChars<S>;
+
+    fn into_iter(self) -> IntoChars<S> {
+        IntoChars {
+            file: self,
+            position: CharPosition {
+                byte_position: 0,
+                char_position: 0,
+            },
+            failed: false,
+        }
+    }
+}
+
+impl<S: TextSource> IntoChars<S> {
+    /// Returns where the iterator is, which is the position of the next character it will read
+    pub fn position(&self) -> CharPosition {
+        self.position
+    }
+
+    /// Gives back the file being read
+    pub fn into_inner(self) -> MappedFile<S> {
+        self.file
+    }
+}
+
+impl<S: TextSource> Iterator for IntoChars<S> {
+    type Item = Result<char, Error>;
+
+    fn next(&mut self) -> Option<Result<char, Error>> {
+        let map = &self.file.map;
+        let byte_position = self.position.byte_position;
+        if self.failed || byte_position >= map.len() {
+            return None;
+        }
+
+        let end = (byte_position + 4).min(map.len());
+        let decode_policy = self.file.decode_policy;
+        let decoded =
+            match map.with_bytes(byte_position..end, |bytes| decode_policy.decode_next(bytes)) {
+                Ok(decoded) => decoded,
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e.into()));
+                }
+            };
+        match decoded {
+            Some((c, width)) => {
+                self.position = CharPosition {
+                    byte_position: byte_position + width,
+                    char_position: self.position.char_position + 1,
+                };
+                Some(Ok(c))
+            }
+            // Decoding it the usual way says what's wrong with it
+            None => {
+                self.failed = true;
+                Some(self.file.char_at(self.position))
+            }
+        }
+    }
+}
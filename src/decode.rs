@@ -0,0 +1,206 @@
+use {
+    crate::{decode_str, stretch_end, CharPosition, Error, MappedFile, TextSource, SCAN_STEP},
+    std::{borrow::Cow, ops::Range},
+};
+
+/// What to do with bytes that aren't valid utf8
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DecodePolicy {
+    /// Invalid bytes are an error
+    #[default]
+    Strict,
+    /// Each invalid sequence reads as a single U+FFFD REPLACEMENT CHARACTER,
+    /// split up the same way as `String::from_utf8_lossy`
+    Lossy,
+    /// Each invalid byte is a unit of its own, for files with binary mixed in with the text.
+    /// It reads as U+FFFD where a character is wanted, and as the byte itself from `MappedFile::unit_at`
+    Bytes,
+}
+
+impl DecodePolicy {
+    /// Iterates over the characters in some bytes that start at a known position in the file,
+    /// along with their byte offsets and lengths.
+    /// Returns an error up front if the bytes aren't valid and replacements aren't allowed.
+    pub(crate) fn char_indices(
+        self,
+        bytes: &[u8],
+        start: CharPosition,
+    ) -> Result<impl Iterator<Item = (usize, char, usize)> + '_, Error> {
+        if self == DecodePolicy::Strict {
+            decode_str(bytes, start)?;
+        }
+        Ok(lossy_char_indices(bytes, self == DecodePolicy::Bytes))
+    }
+
+    /// Counts the characters in some bytes that start at a known position in the file,
+    /// with each invalid sequence counting as one if they're allowed
+    pub(crate) fn count_chars(self, bytes: &[u8], start: CharPosition) -> Result<usize, Error> {
+        match self {
+            DecodePolicy::Strict => Ok(decode_str(bytes, start)?.chars().count()),
+            DecodePolicy::Lossy => Ok(bytes
+                .utf8_chunks()
+                .map(|chunk| {
+                    chunk.valid().chars().count() + usize::from(!chunk.invalid().is_empty())
+                })
+                .sum()),
+            DecodePolicy::Bytes => Ok(bytes
+                .utf8_chunks()
+                .map(|chunk| chunk.valid().chars().count() + chunk.invalid().len())
+                .sum()),
+        }
+    }
+
+    /// Decodes some bytes that start at a known position in the file, borrowing them if they're valid
+    /// and otherwise replacing what isn't.
+    /// Returns an error if the bytes aren't valid and replacements aren't allowed
+    pub(crate) fn decode<'a>(
+        self,
+        bytes: &'a [u8],
+        start: CharPosition,
+    ) -> Result<Cow<'a, str>, Error> {
+        match self {
+            DecodePolicy::Strict => Ok(Cow::Borrowed(decode_str(bytes, start)?)),
+            DecodePolicy::Lossy => Ok(String::from_utf8_lossy(bytes)),
+            DecodePolicy::Bytes => match crate::str_from_utf8(bytes) {
+                Ok(text) => Ok(Cow::Borrowed(text)),
+                Err(_) => Ok(Cow::Owned(
+                    self.char_indices(bytes, start)?
+                        .map(|(_, c, _)| c)
+                        .collect(),
+                )),
+            },
+        }
+    }
+
+    /// Decodes the first character of some bytes along with its length in bytes,
+    /// or `None` if there isn't one or it's invalid and replacements aren't allowed
+    pub(crate) fn decode_next(self, bytes: &[u8]) -> Option<(char, usize)> {
+        match crate::decode_char(bytes) {
+            Some(decoded) => Some(decoded),
+            None if self == DecodePolicy::Lossy => lossy_char_indices(bytes, false)
+                .next()
+                .map(|(_, c, width)| (c, width)),
+            None if self == DecodePolicy::Bytes && !bytes.is_empty() => {
+                Some((char::REPLACEMENT_CHARACTER, 1))
+            }
+            None => None,
+        }
+    }
+
+    /// Decodes the last character of some bytes along with where it starts,
+    /// or `None` if there isn't one or it's invalid and replacements aren't allowed
+    pub(crate) fn decode_previous(self, bytes: &[u8]) -> Option<(char, usize)> {
+        let start = crate::previous_char_start(bytes, bytes.len());
+        match start.and_then(|start| Some((start, crate::decode_char(&bytes[start..])?))) {
+            // Make sure the bytes we stepped back over were exactly one character
+            Some((start, (c, width))) if start + width == bytes.len() => Some((c, start)),
+            _ if self == DecodePolicy::Lossy => lossy_char_indices(bytes, false)
+                .last()
+                .map(|(start, c, _)| (c, start)),
+            _ if self == DecodePolicy::Bytes && !bytes.is_empty() => {
+                Some((char::REPLACEMENT_CHARACTER, bytes.len() - 1))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Iterates over the characters in some bytes along with their byte offsets and lengths,
+/// giving U+FFFD for each invalid sequence, or for each invalid byte if `split` is set
+fn lossy_char_indices(
+    bytes: &[u8],
+    split: bool,
+) -> impl Iterator<Item = (usize, char, usize)> + '_ {
+    let mut offset = 0;
+    bytes.utf8_chunks().flat_map(move |chunk| {
+        let start = offset;
+        offset += chunk.valid().len() + chunk.invalid().len();
+
+        let valid = chunk
+            .valid()
+            .char_indices()
+            .map(move |(byte_offset, c)| (start + byte_offset, c, c.len_utf8()));
+        let invalid_start = start + chunk.valid().len();
+        let (replacements, width) = match (chunk.invalid().len(), split) {
+            (0, _) => (0, 0),
+            (len, true) => (len, 1),
+            (len, false) => (1, len),
+        };
+        let invalid =
+            (0..replacements).map(move |i| (invalid_start + i, char::REPLACEMENT_CHARACTER, width));
+        valid.chain(invalid)
+    })
+}
+
+/// A run of bytes that isn't valid utf8, found by `MappedFile::validate`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidRegion {
+    /// The invalid bytes
+    pub bytes: Range<usize>,
+    /// The line the bytes are on
+    pub line: usize,
+    /// The index of the character they would be replaced by when decoding lossily
+    pub char_position: usize,
+}
+
+impl<S: TextSource> MappedFile<S> {
+    /// Checks the whole file is valid utf8, reporting every invalid sequence along with
+    /// where it would be in the file if it was decoded lossily.
+    /// This doesn't touch the cache so it works whatever the decode policy is.
+    /// Returns `Error::InvalidRegions` listing them if there are any,
+    /// or any other error if the file can't be read, like `Error::Truncated`.
+    pub fn validate(&self) -> Result<(), Error> {
+        // A strict index that reached the end has already seen every byte
+        if self.decode_policy == DecodePolicy::Strict && self.index().end_position.is_some() {
+            return Ok(());
+        }
+
+        let mut regions = Vec::new();
+        let mut byte_position = 0;
+        let mut char_position = 0;
+        let mut line = 0;
+
+        let len = self.map.len();
+        while byte_position < len {
+            let window_end = (byte_position + SCAN_STEP).min(len);
+            let range = byte_position..(window_end + 4).min(len);
+            let end = self.map.with_bytes(range, |bytes| {
+                let end = stretch_end(bytes, window_end - byte_position, window_end == len);
+
+                let mut offset = 0;
+                for chunk in bytes[..end].utf8_chunks() {
+                    for (byte_offset, c) in chunk.valid().char_indices() {
+                        let next_offset = offset + byte_offset + c.len_utf8();
+                        if self
+                            .line_endings
+                            .ends_line(c, bytes.get(next_offset).copied())
+                        {
+                            line += 1;
+                        }
+                        char_position += 1;
+                    }
+                    offset += chunk.valid().len();
+
+                    if !chunk.invalid().is_empty() {
+                        let start = byte_position + offset;
+                        regions.push(InvalidRegion {
+                            bytes: start..start + chunk.invalid().len(),
+                            line,
+                            char_position,
+                        });
+                        offset += chunk.invalid().len();
+                        char_position += 1;
+                    }
+                }
+                end
+            })?;
+            byte_position += end;
+        }
+
+        if regions.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::InvalidRegions(regions))
+        }
+    }
+}
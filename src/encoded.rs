@@ -0,0 +1,575 @@
+use {
+    crate::{DecodePolicy, Error, LineEndings, Mapping, TextSource, UnicodeAccess, SCAN_STEP},
+    std::{
+        fs::File,
+        ops::Range,
+        sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    },
+};
+
+/// How many characters apart positions are recorded, so a lookup never decodes more than this many
+const CHECKPOINT_INTERVAL: usize = 1024;
+
+/// The most code units a single character takes up in any encoding, which is never more than its bytes
+const MAX_CHAR_UNITS: usize = 4;
+
+/// An encoding other than UTF-8 that an `EncodedFile` can be read in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextEncoding {
+    /// UTF-16 with the low byte of each code unit first
+    Utf16Le,
+    /// UTF-16 with the high byte of each code unit first
+    Utf16Be,
+    /// UTF-32 with the lowest byte of each code unit first
+    Utf32Le,
+    /// UTF-32 with the highest byte of each code unit first
+    Utf32Be,
+    /// Any other encoding `encoding_rs` knows, like Shift_JIS, GBK or windows-1252, read a byte at a time.
+    /// It has to be one that doesn't carry state from one character to the next, see `TextEncoding::for_encoding`
+    #[cfg(feature = "encodings")]
+    Legacy(&'static encoding_rs::Encoding),
+}
+
+impl TextEncoding {
+    /// Picks how to read a file in an encoding from `encoding_rs`, like one found with `Encoding::for_label`.
+    /// Returns `None` for UTF-8, which is read with a `MappedFile`, and for encodings that can't be read
+    /// from the middle, like ISO-2022-JP, whose escape sequences change what the bytes after them mean
+    #[cfg(feature = "encodings")]
+    pub fn for_encoding(encoding: &'static encoding_rs::Encoding) -> Option<TextEncoding> {
+        use encoding_rs::{ISO_2022_JP, REPLACEMENT, UTF_16BE, UTF_16LE, UTF_8};
+        match encoding {
+            encoding if encoding == UTF_16LE => Some(TextEncoding::Utf16Le),
+            encoding if encoding == UTF_16BE => Some(TextEncoding::Utf16Be),
+            encoding if encoding == UTF_8 || encoding == ISO_2022_JP || encoding == REPLACEMENT => {
+                None
+            }
+            encoding => Some(TextEncoding::Legacy(encoding)),
+        }
+    }
+
+    /// Returns how many bytes each code unit takes up
+    pub fn unit_size(self) -> usize {
+        match self {
+            TextEncoding::Utf16Le | TextEncoding::Utf16Be => 2,
+            TextEncoding::Utf32Le | TextEncoding::Utf32Be => 4,
+            #[cfg(feature = "encodings")]
+            TextEncoding::Legacy(_) => 1,
+        }
+    }
+
+    /// Returns whether every character is one code unit, so a character's index is where it is
+    /// and looking one up doesn't need the index
+    pub fn is_fixed_width(self) -> bool {
+        match self {
+            TextEncoding::Utf32Le | TextEncoding::Utf32Be => true,
+            #[cfg(feature = "encodings")]
+            TextEncoding::Legacy(encoding) => encoding.is_single_byte(),
+            _ => false,
+        }
+    }
+
+    /// Returns the byte order mark a file in this encoding can start with
+    fn bom(self) -> &'static [u8] {
+        match self {
+            TextEncoding::Utf16Le => b"\xFF\xFE",
+            TextEncoding::Utf16Be => b"\xFE\xFF",
+            TextEncoding::Utf32Le => b"\xFF\xFE\0\0",
+            TextEncoding::Utf32Be => b"\0\0\xFE\xFF",
+            #[cfg(feature = "encodings")]
+            TextEncoding::Legacy(_) => b"",
+        }
+    }
+
+    /// Decodes some bytes that start on a code unit, giving the byte offset and length of each character,
+    /// or `None` in place of the character where the bytes aren't valid.
+    /// Bytes left over at the end that don't make a whole code unit are one invalid character
+    fn decode(self, bytes: &[u8]) -> Vec<(usize, Option<char>, usize)> {
+        let mut chars = Vec::new();
+        let mut offset = 0;
+        match self {
+            TextEncoding::Utf16Le | TextEncoding::Utf16Be => {
+                let units = bytes.chunks_exact(2).map(|unit| match self {
+                    TextEncoding::Utf16Le => u16::from_le_bytes([unit[0], unit[1]]),
+                    _ => u16::from_be_bytes([unit[0], unit[1]]),
+                });
+                for c in char::decode_utf16(units) {
+                    let c = c.ok();
+                    let width = c.map_or(2, |c| c.len_utf16() * 2);
+                    chars.push((offset, c, width));
+                    offset += width;
+                }
+            }
+            TextEncoding::Utf32Le | TextEncoding::Utf32Be => {
+                for unit in bytes.chunks_exact(4) {
+                    let unit = [unit[0], unit[1], unit[2], unit[3]];
+                    let unit = match self {
+                        TextEncoding::Utf32Le => u32::from_le_bytes(unit),
+                        _ => u32::from_be_bytes(unit),
+                    };
+                    chars.push((offset, char::from_u32(unit), 4));
+                    offset += 4;
+                }
+            }
+            #[cfg(feature = "encodings")]
+            TextEncoding::Legacy(encoding) => {
+                offset = decode_legacy(encoding, bytes, &mut chars);
+            }
+        }
+        if offset < bytes.len() {
+            chars.push((offset, None, bytes.len() - offset));
+        }
+        chars
+    }
+}
+
+/// Decodes bytes in an encoding from `encoding_rs` into `chars`, feeding them in one at a time
+/// so it's known which bytes each character came from, and returns how many bytes were used.
+/// A sequence cut off at the end is one invalid character, and the few Big5 sequences
+/// that decode to two characters give the second one no bytes of its own
+#[cfg(feature = "encodings")]
+fn decode_legacy(
+    encoding: &'static encoding_rs::Encoding,
+    bytes: &[u8],
+    chars: &mut Vec<(usize, Option<char>, usize)>,
+) -> usize {
+    use encoding_rs::DecoderResult;
+
+    let mut decoder = encoding.new_decoder_without_bom_handling();
+    let mut output = [0; 16];
+    // Where the character being decoded started, and how far the decoder has read
+    let (mut start, mut read) = (0, 0);
+    while read < bytes.len() {
+        let last = read + 1 == bytes.len();
+        let (result, consumed, written) =
+            decoder.decode_to_utf8_without_replacement(&bytes[read..read + 1], &mut output, last);
+        read += consumed;
+        match result {
+            DecoderResult::Malformed(_, after) => {
+                // Bytes read after the invalid sequence are decoded again from scratch
+                read -= after as usize;
+                chars.push((start, None, read - start));
+                start = read;
+                decoder = encoding.new_decoder_without_bom_handling();
+            }
+            _ if written > 0 => {
+                let decoded = std::str::from_utf8(&output[..written]).unwrap_or_default();
+                for (i, c) in decoded.chars().enumerate() {
+                    chars.push(match i {
+                        0 => (start, Some(c), read - start),
+                        _ => (read, Some(c), 0),
+                    });
+                }
+                start = read;
+            }
+            _ => {}
+        }
+    }
+    start
+}
+
+/// Where a character is in an `EncodedFile`, counted in code units rather than bytes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct UnitPosition {
+    unit: usize,
+    char: usize,
+}
+
+/// Everything an `EncodedFile` has learned about where its characters and lines are
+#[derive(Debug)]
+struct UnitIndex {
+    /// Where each line starts, starting with the first
+    lines: Vec<UnitPosition>,
+    /// Positions recorded every `CHECKPOINT_INTERVAL` characters, starting with the first
+    checkpoints: Vec<UnitPosition>,
+    /// How far the file has been scanned, everything before it is in the index
+    frontier: UnitPosition,
+    /// The position just past the last character, once the whole file has been scanned
+    end: Option<UnitPosition>,
+}
+
+/// What scanning a stretch of an `EncodedFile` found, to be added to the index
+struct Stretch {
+    /// Where each line that starts in the stretch starts
+    lines: Vec<UnitPosition>,
+    /// The checkpoints in the stretch
+    checkpoints: Vec<UnitPosition>,
+    /// Where the stretch ends, which is where the next one starts
+    end: UnitPosition,
+    /// Whether the stretch ends at the end of the file
+    at_end: bool,
+}
+
+impl UnitIndex {
+    /// Creates an index knowing only where the file starts
+    fn new() -> UnitIndex {
+        UnitIndex {
+            lines: vec![UnitPosition::default()],
+            checkpoints: vec![UnitPosition::default()],
+            frontier: UnitPosition::default(),
+            end: None,
+        }
+    }
+
+    /// Finds the closest recorded position at or before a character index
+    fn nearest(&self, index: usize) -> UnitPosition {
+        let before = |positions: &[UnitPosition]| {
+            positions[positions.partition_point(|position| position.char <= index) - 1]
+        };
+        let (line, checkpoint) = (before(&self.lines), before(&self.checkpoints));
+        match line.char > checkpoint.char {
+            true => line,
+            false => checkpoint,
+        }
+    }
+}
+
+/// A file in an encoding other than UTF-8, like UTF-16 from Windows tools, that's read where it is
+/// without being transcoded first. Characters are looked up by index the same way as in a `MappedFile`,
+/// with the index recording where lines start and a checkpoint every 1024 characters in code units.
+/// In UTF-32 and single byte encodings a character's index is where it is, so only lines need the index.
+/// A byte order mark for the encoding at the start of the file is skipped
+pub struct EncodedFile<S: TextSource = Mapping> {
+    /// The bytes of the file
+    pub map: S,
+    /// What the bytes are encoded as
+    encoding: TextEncoding,
+    /// Where the text starts, after any byte order mark
+    start: usize,
+    /// Which characters end a line
+    line_endings: LineEndings,
+    /// What to do with bytes that aren't valid in the encoding
+    decode_policy: DecodePolicy,
+    /// What's been found so far, grown by lookups behind a shared reference
+    index: RwLock<UnitIndex>,
+}
+
+impl EncodedFile {
+    /// Maps a file that's in the given encoding
+    pub fn open(file: File, encoding: TextEncoding) -> Result<EncodedFile, Error> {
+        Ok(EncodedFile::from_source(Mapping::new(file)?, encoding))
+    }
+}
+
+impl<S: TextSource> EncodedFile<S> {
+    /// Reads the bytes of any source as text in the given encoding
+    pub fn from_source(source: S, encoding: TextEncoding) -> EncodedFile<S> {
+        let bom = encoding.bom();
+        let start = match source.with_bytes(0..bom.len().min(source.len()), |bytes| bytes == bom) {
+            Ok(true) => bom.len(),
+            _ => 0,
+        };
+        EncodedFile {
+            map: source,
+            encoding,
+            start,
+            line_endings: LineEndings::default(),
+            decode_policy: DecodePolicy::default(),
+            index: RwLock::new(UnitIndex::new()),
+        }
+    }
+
+    /// Returns what the file is encoded as
+    pub fn encoding(&self) -> TextEncoding {
+        self.encoding
+    }
+
+    /// Returns whether the file starts with a byte order mark, which is skipped
+    pub fn has_bom(&self) -> bool {
+        self.start > 0
+    }
+
+    /// Changes which characters end a line, throwing away the lines found so far
+    pub fn set_line_endings(&mut self, line_endings: LineEndings) {
+        self.line_endings = line_endings;
+        *self.index_mut() = UnitIndex::new();
+    }
+
+    /// Changes what happens to bytes that aren't valid in the encoding, throwing away everything found so far.
+    /// Decoding lossily reads each invalid code unit as U+FFFD
+    pub fn set_decode_policy(&mut self, decode_policy: DecodePolicy) {
+        self.decode_policy = decode_policy;
+        *self.index_mut() = UnitIndex::new();
+    }
+
+    /// Returns the character at the given index.
+    /// Returns an error if the index is out of bounds or the file isn't valid before it.
+    pub fn unicode_at(&self, index: usize) -> Result<char, Error> {
+        let position = self.position_at(index)?;
+        let chars = self.decode_units(position.unit, position.unit + MAX_CHAR_UNITS)?;
+        match chars.first() {
+            Some(&(_, c, _)) => self.check(c, position),
+            None => Err(Error::OutOfBounds {
+                index,
+                len: position.char,
+            }),
+        }
+    }
+
+    /// Returns the character at the given index, or `None` if it's out of bounds or can't be decoded
+    pub fn get(&self, index: usize) -> Option<char> {
+        self.unicode_at(index).ok()
+    }
+
+    /// Returns the byte offset in the file of the character at the given index.
+    /// The index one past the last character resolves to the length of the file.
+    /// Returns an error if the index is out of bounds.
+    pub fn byte_offset_of(&self, index: usize) -> Result<usize, Error> {
+        let position = self.position_at(index)?;
+        Ok((self.start + position.unit * self.encoding.unit_size()).min(self.map.len()))
+    }
+
+    /// Returns the number of characters in the file, scanning the rest of it the first time.
+    /// Returns an error if the file isn't valid in its encoding.
+    pub fn char_count(&self) -> Result<usize, Error> {
+        if self.encoding.is_fixed_width() {
+            return Ok(self.unit_len());
+        }
+        Ok(self.scan_to_end()?.char)
+    }
+
+    /// Returns the number of lines in the file, scanning the rest of it the first time.
+    /// Returns an error if the file isn't valid in its encoding.
+    pub fn line_count(&self) -> Result<usize, Error> {
+        self.scan_to_end()?;
+        Ok(self.index().lines.len())
+    }
+
+    /// Decodes the text of a line without its terminator.
+    /// Returns an error if the line is out of bounds or isn't valid in the encoding.
+    pub fn line_string(&self, line_number: usize) -> Result<String, Error> {
+        // The start of the next line is where this one ends
+        self.scan_while(|index| index.lines.len() <= line_number + 1)?;
+        let (start, next) = {
+            let index = self.index();
+            let start = index
+                .lines
+                .get(line_number)
+                .copied()
+                .ok_or(Error::OutOfBounds {
+                    index: line_number,
+                    len: index.lines.len(),
+                })?;
+            (start, index.lines.get(line_number + 1).copied())
+        };
+
+        let end = next.or(self.index().end).unwrap();
+        let mut text = self.decode_between(start, end)?;
+        if next.is_some() {
+            let last = text.pop().unwrap();
+            let previous = text.ends_with('\r').then_some(b'\r');
+            if self.line_endings.terminator(last, previous).char_len() == 2 {
+                text.pop();
+            }
+        }
+        Ok(text)
+    }
+
+    /// Decodes the characters in a range.
+    /// Returns an error if the range is out of bounds or isn't valid in the encoding.
+    pub fn string_range(&self, range: Range<usize>) -> Result<String, Error> {
+        if range.start > range.end {
+            return Err(Error::OutOfBounds {
+                index: range.start,
+                len: range.end,
+            });
+        }
+        let end = self.position_at(range.end)?;
+        let start = self.position_at(range.start)?;
+        self.decode_between(start, end)
+    }
+
+    /// Finds where the character at the given index is, scanning as far as it if it hasn't been yet.
+    /// The index one past the last character resolves to the end of the file
+    fn position_at(&self, index: usize) -> Result<UnitPosition, Error> {
+        if self.encoding.is_fixed_width() {
+            let len = self.unit_len();
+            if index > len {
+                return Err(Error::OutOfBounds { index, len });
+            }
+            return Ok(UnitPosition {
+                unit: index,
+                char: index,
+            });
+        }
+
+        self.scan_while(|unit_index| unit_index.frontier.char <= index)?;
+        let nearest = {
+            let unit_index = self.index();
+            match unit_index.end {
+                Some(end) if index == end.char => return Ok(end),
+                Some(end) if index > end.char => {
+                    return Err(Error::OutOfBounds {
+                        index,
+                        len: end.char,
+                    })
+                }
+                _ => unit_index.nearest(index),
+            }
+        };
+
+        // Every character up to the frontier is valid, so the ones after the nearest position can just be counted
+        let skip = index - nearest.char;
+        let chars = self.decode_units(nearest.unit, nearest.unit + (skip + 1) * MAX_CHAR_UNITS)?;
+        let (offset, _, _) = chars[skip];
+        Ok(UnitPosition {
+            unit: nearest.unit + offset / self.encoding.unit_size(),
+            char: index,
+        })
+    }
+
+    /// Returns how many code units there are after any byte order mark, counting a partial one at the end
+    fn unit_len(&self) -> usize {
+        (self.map.len() - self.start).div_ceil(self.encoding.unit_size())
+    }
+
+    /// Decodes the code units in a range, cut off at the end of the file,
+    /// giving each character's byte offset from the start of the range and its length in bytes
+    fn decode_units(
+        &self,
+        start: usize,
+        end: usize,
+    ) -> Result<Vec<(usize, Option<char>, usize)>, Error> {
+        let size = self.encoding.unit_size();
+        let len = self.map.len();
+        let range = (self.start + start * size).min(len)..(self.start + end * size).min(len);
+        Ok(self
+            .map
+            .with_bytes(range, |bytes| self.encoding.decode(bytes))?)
+    }
+
+    /// Decodes the characters between two positions
+    fn decode_between(&self, start: UnitPosition, end: UnitPosition) -> Result<String, Error> {
+        let mut text = String::new();
+        for (char, (offset, c, _)) in self
+            .decode_units(start.unit, end.unit)?
+            .into_iter()
+            .enumerate()
+        {
+            let position = UnitPosition {
+                unit: start.unit + offset / self.encoding.unit_size(),
+                char: start.char + char,
+            };
+            text.push(self.check(c, position)?);
+        }
+        Ok(text)
+    }
+
+    /// Gives back a decoded character, or what to do about it not being valid
+    fn check(&self, c: Option<char>, position: UnitPosition) -> Result<char, Error> {
+        match (c, self.decode_policy) {
+            (Some(c), _) => Ok(c),
+            (None, DecodePolicy::Lossy | DecodePolicy::Bytes) => Ok(char::REPLACEMENT_CHARACTER),
+            (None, DecodePolicy::Strict) => Err(Error::InvalidEncoding {
+                byte_offset: self.start + position.unit * self.encoding.unit_size(),
+                char_index: position.char,
+            }),
+        }
+    }
+
+    /// Scans the rest of the file if it hasn't been already, returning where it ends
+    fn scan_to_end(&self) -> Result<UnitPosition, Error> {
+        self.scan_while(|_| true)?;
+        Ok(self.index().end.unwrap())
+    }
+
+    /// Scans a stretch at a time for as long as the condition holds and there's more of the file.
+    /// Each stretch is decoded without holding the lock, so lookups can carry on meanwhile
+    fn scan_while(&self, condition: impl Fn(&UnitIndex) -> bool) -> Result<(), Error> {
+        loop {
+            let from = {
+                let index = self.index();
+                if index.end.is_some() || !condition(&index) {
+                    return Ok(());
+                }
+                index.frontier
+            };
+            let stretch = self.scan_step(from)?;
+
+            let mut index = self.index_mut();
+            // Someone else might have scanned the same stretch while we were, so go again from wherever they got to
+            if index.frontier != from {
+                continue;
+            }
+            index.lines.extend(stretch.lines);
+            index.checkpoints.extend(stretch.checkpoints);
+            index.frontier = stretch.end;
+            if stretch.at_end {
+                index.end = Some(stretch.end);
+            }
+        }
+    }
+
+    /// Decodes about `SCAN_STEP` bytes past a position, finding the line starts and checkpoints in them
+    fn scan_step(&self, from: UnitPosition) -> Result<Stretch, Error> {
+        let size = self.encoding.unit_size();
+        let len = self.map.len();
+        let window_start = (self.start + from.unit * size).min(len);
+        let window_end = (window_start + SCAN_STEP).min(len);
+        let at_end = window_end == len;
+        let chars = self.map.with_bytes(window_start..window_end, |bytes| {
+            self.encoding.decode(bytes)
+        })?;
+
+        // The last character might be cut off, and the one after it decides whether a \r ends a line,
+        // so it's left for the next stretch
+        let keep = match at_end {
+            true => chars.len(),
+            false => chars.len().saturating_sub(1),
+        };
+        let mut stretch = Stretch {
+            lines: Vec::new(),
+            checkpoints: Vec::new(),
+            end: from,
+            at_end,
+        };
+        for (i, &(offset, c, width)) in chars[..keep].iter().enumerate() {
+            let c = self.check(c, stretch.end)?;
+            let position = UnitPosition {
+                unit: from.unit + (offset + width).div_ceil(size),
+                char: stretch.end.char + 1,
+            };
+
+            let next = chars.get(i + 1).and_then(|&(_, next, _)| next);
+            if self
+                .line_endings
+                .ends_line(c, next.filter(|&c| c == '\n').map(|_| b'\n'))
+            {
+                stretch.lines.push(position);
+            }
+            if position.char.is_multiple_of(CHECKPOINT_INTERVAL) {
+                stretch.checkpoints.push(position);
+            }
+            stretch.end = position;
+        }
+
+        Ok(stretch)
+    }
+
+    /// Locks the index to read it
+    fn index(&self) -> RwLockReadGuard<'_, UnitIndex> {
+        self.index.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the index to grow it
+    fn index_mut(&self) -> RwLockWriteGuard<'_, UnitIndex> {
+        self.index.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<S: TextSource> UnicodeAccess for EncodedFile<S> {
+    fn unicode_at(&mut self, index: usize) -> Result<char, Error> {
+        EncodedFile::unicode_at(self, index)
+    }
+
+    fn char_count(&mut self) -> Result<usize, Error> {
+        EncodedFile::char_count(self)
+    }
+
+    fn line_count(&mut self) -> Result<usize, Error> {
+        EncodedFile::line_count(self)
+    }
+
+    fn line_string(&mut self, line_number: usize) -> Result<String, Error> {
+        EncodedFile::line_string(self, line_number)
+    }
+}
@@ -0,0 +1,118 @@
+use {
+    crate::{str_from_utf8, Error, MappedFile, TextSource, SCAN_STEP},
+    std::fmt,
+};
+
+/// What a file's bytes look like they're encoded as, see `MappedFile::detect_encoding`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DetectedEncoding {
+    /// UTF-8, starting with a byte order mark if `bom` is set
+    Utf8 { bom: bool },
+    /// UTF-16 with the low byte of each code unit first
+    Utf16Le { bom: bool },
+    /// UTF-16 with the high byte of each code unit first
+    Utf16Be { bom: bool },
+    /// UTF-32 with the lowest byte of each code unit first
+    Utf32Le { bom: bool },
+    /// UTF-32 with the highest byte of each code unit first
+    Utf32Be { bom: bool },
+    /// Not a Unicode encoding, most likely a legacy single byte one like Latin-1, or not text at all
+    Other,
+}
+
+impl DetectedEncoding {
+    /// Returns whether the file is UTF-8, which is the only encoding that can be read directly
+    pub fn is_utf8(self) -> bool {
+        matches!(self, DetectedEncoding::Utf8 { .. })
+    }
+
+    /// Returns how many bytes the byte order mark at the start of the file takes up, if it has one
+    pub fn bom_len(self) -> usize {
+        match self {
+            DetectedEncoding::Utf8 { bom: true } => 3,
+            DetectedEncoding::Utf16Le { bom: true } | DetectedEncoding::Utf16Be { bom: true } => 2,
+            DetectedEncoding::Utf32Le { bom: true } | DetectedEncoding::Utf32Be { bom: true } => 4,
+            _ => 0,
+        }
+    }
+
+    /// Works out the encoding of the start of a file, first from a byte order mark
+    /// and failing that from where zero bytes turn up and whether it's valid utf8.
+    /// `at_end` says whether the bytes go all the way to the end of the file
+    fn sniff(bytes: &[u8], at_end: bool) -> DetectedEncoding {
+        // UTF-32 LE has to be checked before UTF-16 LE since its mark starts the same way
+        match bytes {
+            [0xEF, 0xBB, 0xBF, ..] => return DetectedEncoding::Utf8 { bom: true },
+            [0xFF, 0xFE, 0, 0, ..] => return DetectedEncoding::Utf32Le { bom: true },
+            [0, 0, 0xFE, 0xFF, ..] => return DetectedEncoding::Utf32Be { bom: true },
+            [0xFF, 0xFE, ..] => return DetectedEncoding::Utf16Le { bom: true },
+            [0xFE, 0xFF, ..] => return DetectedEncoding::Utf16Be { bom: true },
+            _ => {}
+        }
+
+        // Mostly ASCII text in a wide encoding has zero bytes in the same places in every code unit
+        let mut zeros = [0; 4];
+        for quad in bytes.chunks_exact(4) {
+            for (count, &byte) in zeros.iter_mut().zip(quad) {
+                *count += usize::from(byte == 0);
+            }
+        }
+        let quads = bytes.len() / 4;
+        let mostly = |count: usize| quads > 0 && count * 10 >= quads * 9;
+        let rarely = |count: usize| count * 10 <= quads;
+        let [a, b, c, d] = zeros;
+        if mostly(c) && mostly(d) && rarely(a) {
+            return DetectedEncoding::Utf32Le { bom: false };
+        }
+        if mostly(a) && mostly(b) && rarely(d) {
+            return DetectedEncoding::Utf32Be { bom: false };
+        }
+        // Non-Latin text has far fewer zeros, so a quarter of the code units is enough
+        let (even, odd) = (a + c, b + d);
+        if odd * 4 >= quads * 2 && rarely(even) && odd > 0 {
+            return DetectedEncoding::Utf16Le { bom: false };
+        }
+        if even * 4 >= quads * 2 && rarely(odd) && even > 0 {
+            return DetectedEncoding::Utf16Be { bom: false };
+        }
+
+        match str_from_utf8(bytes) {
+            Ok(_) => DetectedEncoding::Utf8 { bom: false },
+            // The sample might stop partway through a character
+            Err(e) if e.error_len().is_none() && !at_end => DetectedEncoding::Utf8 { bom: false },
+            Err(_) => DetectedEncoding::Other,
+        }
+    }
+}
+
+impl fmt::Display for DetectedEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            DetectedEncoding::Utf8 { .. } => "UTF-8",
+            DetectedEncoding::Utf16Le { .. } => "UTF-16LE",
+            DetectedEncoding::Utf16Be { .. } => "UTF-16BE",
+            DetectedEncoding::Utf32Le { .. } => "UTF-32LE",
+            DetectedEncoding::Utf32Be { .. } => "UTF-32BE",
+            DetectedEncoding::Other => "an unknown encoding",
+        };
+        write!(f, "{}", name)?;
+        if self.bom_len() > 0 {
+            write!(f, " with a byte order mark")?;
+        }
+        Ok(())
+    }
+}
+
+impl<S: TextSource> MappedFile<S> {
+    /// Works out what the file is encoded as from a byte order mark if it starts with one,
+    /// or else from the first 64K or so of bytes, so a UTF-16 file can be told apart from one that's broken.
+    /// A UTF-8 byte order mark that was skipped when the file was opened isn't seen, see `MappedFile::has_bom`.
+    /// Returns an error if the file can't be read.
+    pub fn detect_encoding(&self) -> Result<DetectedEncoding, Error> {
+        let len = self.map.len();
+        let end = SCAN_STEP.min(len);
+        Ok(self
+            .map
+            .with_bytes(0..end, |bytes| DetectedEncoding::sniff(bytes, end == len))?)
+    }
+}
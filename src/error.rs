@@ -0,0 +1,164 @@
+use {
+    crate::{is_continuation_byte, CharPosition, DetectedEncoding, InvalidRegion},
+    std::{fmt, io, str::Utf8Error},
+};
+
+/// How many bytes either side of an invalid sequence are kept in `Error::InvalidUtf8`
+const CONTEXT_BYTES: usize = 8;
+
+/// Everything that can go wrong opening or reading a file
+#[derive(Debug)]
+pub enum Error {
+    /// The file couldn't be read
+    Io(io::Error),
+    /// The file couldn't be memory mapped, or read instead
+    Mmap(io::Error),
+    /// The bytes starting at the offset aren't valid utf8
+    InvalidUtf8 {
+        /// Where the invalid bytes start in the file
+        byte_offset: usize,
+        /// The index the character starting there would have, which is the number of characters before it
+        char_index: usize,
+        /// Up to 8 bytes either side of where the invalid bytes start, for showing what went wrong
+        context: Vec<u8>,
+    },
+    /// The index is past the end of something that is `len` long,
+    /// whether that's characters in the file, lines, columns in a line or bytes
+    OutOfBounds { index: usize, len: usize },
+    /// A saved index was made by a different version or for a file that has changed since,
+    /// so using it would give the wrong characters
+    StaleIndex(&'static str),
+    /// The file has been changed since it was opened, so what's been read from it can't be trusted
+    FileChanged,
+    /// The file got shorter while it was being read, so the bytes that were wanted aren't there anymore
+    Truncated,
+    /// Locking `bytes` bytes into memory would go over the `limit` on locked memory (`RLIMIT_MEMLOCK`)
+    LockLimit { bytes: usize, limit: u64 },
+    /// The bytes starting at the offset aren't valid in the encoding of an `EncodedFile`
+    InvalidEncoding {
+        /// Where the invalid bytes start in the file
+        byte_offset: usize,
+        /// The index the character starting there would have, which is the number of characters before it
+        char_index: usize,
+    },
+    /// The file looks like it's in an encoding other than UTF-8, see `MappedFileBuilder::require_utf8`
+    UnsupportedEncoding(DetectedEncoding),
+    /// Every run of bytes in the file that isn't valid utf8, found by `MappedFile::validate`
+    InvalidRegions(Vec<InvalidRegion>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "couldn't read the file: {}", e),
+            Error::Mmap(e) => write!(f, "couldn't map the file: {}", e),
+            Error::InvalidUtf8 {
+                byte_offset,
+                char_index,
+                context,
+            } => write!(
+                f,
+                "invalid utf8 at byte {} (character {}) near \"{}\"",
+                byte_offset,
+                char_index,
+                context.escape_ascii()
+            ),
+            Error::OutOfBounds { index, len } => {
+                write!(f, "index {} is out of bounds for length {}", index, len)
+            }
+            Error::StaleIndex(reason) => {
+                write!(f, "the saved index doesn't match the file: {}", reason)
+            }
+            Error::FileChanged => write!(f, "the file has changed since it was opened"),
+            Error::Truncated => write!(f, "the file was truncated while it was being read"),
+            Error::LockLimit { bytes, limit } => write!(
+                f,
+                "couldn't lock {} bytes into memory, locked memory is limited to {} bytes (RLIMIT_MEMLOCK)",
+                bytes, limit
+            ),
+            Error::InvalidEncoding {
+                byte_offset,
+                char_index,
+            } => write!(
+                f,
+                "invalid text at byte {} (character {})",
+                byte_offset, char_index
+            ),
+            Error::UnsupportedEncoding(encoding) => {
+                write!(f, "the file looks like {} rather than UTF-8", encoding)
+            }
+            Error::InvalidRegions(regions) => write!(
+                f,
+                "the file isn't valid utf8 in {} places, the first at byte {}",
+                regions.len(),
+                regions.first().map_or(0, |region| region.bytes.start)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) | Error::Mmap(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        match error.get_ref() {
+            Some(inner) if inner.is::<Truncated>() => Error::Truncated,
+            _ => Error::Io(error),
+        }
+    }
+}
+
+/// Marks an `io::Error` from a source as being because the file was truncated,
+/// so it comes out as `Error::Truncated` rather than `Error::Io`
+#[derive(Debug)]
+pub(crate) struct Truncated;
+
+impl Truncated {
+    /// Creates the error a source returns when the bytes it was asked for have been truncated away
+    pub(crate) fn error() -> io::Error {
+        io::Error::new(io::ErrorKind::UnexpectedEof, Truncated)
+    }
+}
+
+impl fmt::Display for Truncated {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the file was truncated")
+    }
+}
+
+impl std::error::Error for Truncated {}
+
+impl Error {
+    /// Turns an error from decoding bytes that start at a known position in the file
+    /// into one pointing at the bad byte
+    pub(crate) fn invalid_utf8(start: CharPosition, bytes: &[u8], error: Utf8Error) -> Error {
+        let valid = error.valid_up_to();
+        let chars = bytes[..valid]
+            .iter()
+            .filter(|&&byte| !is_continuation_byte(byte))
+            .count();
+
+        Error::InvalidUtf8 {
+            byte_offset: start.byte_position + valid,
+            char_index: start.char_position + chars,
+            context: bytes
+                [valid.saturating_sub(CONTEXT_BYTES)..(valid + CONTEXT_BYTES).min(bytes.len())]
+                .to_vec(),
+        }
+    }
+}
+
+/// The old name for `Error`, from when it was only returned by lookups
+#[deprecated(note = "use `Error` instead")]
+pub type IndexError = Error;
+
+/// The old name for `Error`, from when opening had its own error type
+#[deprecated(note = "use `Error` instead")]
+pub type OpenError = Error;
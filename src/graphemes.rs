@@ -0,0 +1,128 @@
+use {
+    crate::{decode_str, Error, MappedFile, TextSource},
+    unicode_segmentation::{Graphemes, UnicodeSegmentation},
+};
+
+impl<S: TextSource> MappedFile<S> {
+    /// Scans forward through the lines until the grapheme index of the start of the given line is known
+    /// or the end of the file is reached.
+    fn index_graphemes_until(&self, line: usize) -> Result<(), Error> {
+        while self.index().grapheme_line_starts.len() <= line {
+            let known = self.index().grapheme_line_starts.len() - 1;
+
+            // The last line can't tell us where a following line starts
+            self.index_lines_until(known + 1)?;
+            if self.index().line_ending_positions.len() <= known + 1 {
+                break;
+            }
+
+            // A grapheme never continues past a newline so lines can be counted on their own
+            let span = self.line_span(known)?;
+            let line_start = self.with_lines(|lines| lines.at(known))?;
+            let count = decode_str(self.map.bytes(span)?, line_start)?
+                .graphemes(true)
+                .count();
+
+            let mut index = self.index_mut();
+            // Someone else might have counted this line while we were
+            if index.grapheme_line_starts.len() == known + 1 {
+                let start = index.grapheme_line_starts[known];
+                index.grapheme_line_starts.push(start + count);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finds the line containing the grapheme at the given index along with the grapheme index of its start.
+    fn grapheme_line(&self, index: usize) -> Result<(usize, usize), Error> {
+        // Keep indexing until we know of a line starting after the index
+        loop {
+            let (known, last_start) = {
+                let cache = self.index();
+                let known = cache.grapheme_line_starts.len();
+                (known, cache.grapheme_line_starts[known - 1])
+            };
+            if last_start > index {
+                break;
+            }
+
+            self.index_graphemes_until(known)?;
+            if self.index().grapheme_line_starts.len() == known {
+                break;
+            }
+        }
+
+        let cache = self.index();
+        let line = match cache.grapheme_line_starts.binary_search(&index) {
+            Ok(line) => line,
+            Err(line) => line - 1,
+        };
+
+        Ok((line, cache.grapheme_line_starts[line]))
+    }
+
+    /// Returns the grapheme cluster (user-perceived character) at the given grapheme index.
+    /// Returns an error if the index is out of bounds or the line isn't valid utf8.
+    pub fn grapheme_at(&self, index: usize) -> Result<&str, Error> {
+        let (line, line_start) = self.grapheme_line(index)?;
+        let span = self.line_span(line)?;
+
+        let start = self.with_lines(|lines| lines.at(line))?;
+        let text = decode_str(self.map.bytes(span)?, start)?;
+        text.graphemes(true)
+            .nth(index - line_start)
+            .ok_or_else(|| Error::OutOfBounds {
+                index,
+                len: line_start + text.graphemes(true).count(),
+            })
+    }
+
+    /// Returns an iterator over the grapheme clusters starting at the given grapheme index.
+    /// A grapheme never continues past a newline, so the file is split a line at a time.
+    /// The iterator is empty if the index is out of bounds,
+    /// and stops with an error at the first line that isn't valid utf8.
+    pub fn graphemes_from(&self, index: usize) -> impl Iterator<Item = Result<&str, Error>> + '_ {
+        // The line to split next and how many of its graphemes to skip
+        let mut next = Some(
+            self.grapheme_line(index)
+                .map(|(line, line_start)| (line, index - line_start)),
+        );
+
+        std::iter::from_fn(move || {
+            let (line, skip) = match next.take()? {
+                Ok(next) => next,
+                Err(e) => return Some(Err(e)),
+            };
+            let graphemes = self.line_graphemes(line).transpose()?;
+            if graphemes.is_ok() {
+                next = Some(Ok((line + 1, 0)));
+            }
+            Some(graphemes.map(|graphemes| graphemes.skip(skip)))
+        })
+        .flat_map(|graphemes| {
+            let (graphemes, error) = match graphemes {
+                Ok(graphemes) => (Some(graphemes), None),
+                Err(e) => (None, Some(e)),
+            };
+            graphemes
+                .into_iter()
+                .flatten()
+                .map(Ok)
+                .chain(error.map(Err))
+        })
+    }
+
+    /// Splits a line into grapheme clusters, or gives `None` past the last line
+    fn line_graphemes(&self, line: usize) -> Result<Option<Graphemes<'_>>, Error> {
+        let span = match self.line_span(line) {
+            Ok(span) => span,
+            Err(Error::OutOfBounds { .. }) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let start = self.with_lines(|lines| lines.at(line))?;
+        Ok(Some(
+            decode_str(self.map.bytes(span)?, start)?.graphemes(true),
+        ))
+    }
+}
@@ -0,0 +1,49 @@
+use crate::{CharPosition, LineRef, MappedFile, TextSource};
+
+/// A line with a match in it along with the lines around it, see `MappedFile::grep`
+#[derive(Clone, Debug)]
+pub struct GrepMatch<'a> {
+    /// Where the first match in the line starts
+    pub position: CharPosition,
+    /// The line with the match in it
+    pub line: LineRef<'a>,
+    /// The lines before it, as many as were asked for unless the file starts first
+    pub before: Vec<LineRef<'a>>,
+    /// The lines after it, as many as were asked for unless the file ends first
+    pub after: Vec<LineRef<'a>>,
+}
+
+impl<S: TextSource> MappedFile<S> {
+    /// Iterates over the lines with some text in them in order, like `grep -B before -A after`,
+    /// giving each one once along with the lines around it.
+    /// Lines of context can be given again with a later match if they're close together.
+    /// Stops early at a line that isn't valid utf8
+    pub fn grep<'a>(
+        &'a self,
+        pattern: &'a str,
+        context_before: usize,
+        context_after: usize,
+    ) -> impl Iterator<Item = GrepMatch<'a>> + 'a {
+        let mut next_line = 0;
+        std::iter::from_fn(move || {
+            let from = self.position_of(next_line, 0).ok()?;
+            let position = self.find(pattern, from.char_position).ok()??;
+            let (line, _) = self.line_col_of(position.char_position).ok()?;
+            // Any other matches in the line are skipped
+            next_line = line + 1;
+
+            let after = (line + 1..line + 1 + context_after)
+                .map_while(|line| self.line_ref(line).ok())
+                .collect();
+            Some(GrepMatch {
+                position,
+                line: self.line_ref(line).ok()?,
+                before: (line.saturating_sub(context_before)..line)
+                    .map(|line| self.line_ref(line))
+                    .collect::<Result<_, _>>()
+                    .ok()?,
+                after,
+            })
+        })
+    }
+}
@@ -0,0 +1,87 @@
+use {
+    crate::{CharPosition, Error, MappedFile, Mapping, TextSource},
+    std::{ops::Deref, sync::Arc},
+};
+
+/// A cheap handle onto a shared file with its own cursor for reading characters in order.
+/// Every handle shares the same mapping and index, so whatever one of them indexes the rest can use.
+/// All of the file's lookups can be used through the handle as well
+pub struct FileHandle<S: TextSource = Mapping> {
+    file: Arc<MappedFile<S>>,
+    /// Where the next character will be read from
+    position: CharPosition,
+}
+
+impl<S: TextSource> MappedFile<S> {
+    /// Creates a new handle onto the file with its cursor at the start
+    pub fn handle(self: &Arc<Self>) -> FileHandle<S> {
+        FileHandle {
+            file: Arc::clone(self),
+            position: CharPosition {
+                byte_position: 0,
+                char_position: 0,
+            },
+        }
+    }
+}
+
+impl<S: TextSource> FileHandle<S> {
+    /// Returns the index of the character the cursor is at
+    pub fn position(&self) -> usize {
+        self.position.char_position
+    }
+
+    /// Moves the cursor to the character at the given index.
+    /// The index one past the last character is allowed, leaving nothing left to read.
+    /// Returns an error if the index is out of bounds.
+    pub fn seek(&mut self, index: usize) -> Result<(), Error> {
+        self.position = self.file.position_at(index)?;
+        Ok(())
+    }
+
+    /// Returns the file the handle is onto
+    pub fn file(&self) -> &Arc<MappedFile<S>> {
+        &self.file
+    }
+}
+
+/// Reads the characters from the cursor onwards, stopping early at invalid utf8
+/// unless decoding lossily
+impl<S: TextSource> Iterator for FileHandle<S> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let map = &self.file.map;
+        let end = (self.position.byte_position + 4).min(map.len());
+        let decode_policy = self.file.decode_policy;
+        let (c, width) = map
+            .with_bytes(self.position.byte_position..end, |bytes| {
+                decode_policy.decode_next(bytes)
+            })
+            .ok()??;
+
+        self.position = CharPosition {
+            byte_position: self.position.byte_position + width,
+            char_position: self.position.char_position + 1,
+        };
+        Some(c)
+    }
+}
+
+/// A clone shares the file but gets its own cursor, starting wherever this one is
+impl<S: TextSource> Clone for FileHandle<S> {
+    fn clone(&self) -> Self {
+        FileHandle {
+            file: Arc::clone(&self.file),
+            position: self.position,
+        }
+    }
+}
+
+impl<S: TextSource> Deref for FileHandle<S> {
+    type Target = MappedFile<S>;
+
+    fn deref(&self) -> &MappedFile<S> {
+        &self.file
+    }
+}
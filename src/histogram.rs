@@ -0,0 +1,45 @@
+use {
+    crate::{Error, MappedFile, TextSource},
+    std::{collections::BTreeMap, ops::Range},
+};
+
+impl<S: TextSource> MappedFile<S> {
+    /// Counts how many times each character appears in a range of characters.
+    /// Invalid bytes are counted as U+FFFD if the file decodes lossily.
+    /// Returns an error if the range is out of bounds or isn't valid utf8.
+    pub fn char_histogram(&self, range: Range<usize>) -> Result<BTreeMap<char, u64>, Error> {
+        self.histogram(range, |c| c)
+    }
+
+    /// Counts how many characters in a range of characters are in each Unicode general category,
+    /// keyed by the category's two letter abbreviation like "Lu" or "Cc".
+    /// Returns an error if the range is out of bounds or isn't valid utf8.
+    #[cfg(feature = "categories")]
+    pub fn category_histogram(
+        &self,
+        range: Range<usize>,
+    ) -> Result<BTreeMap<&'static str, u64>, Error> {
+        use unicode_general_category::get_general_category;
+        self.histogram(range, |c| get_general_category(c).abbreviation())
+    }
+
+    /// Counts the characters in a range by whatever key they're sorted into
+    fn histogram<K: Ord>(
+        &self,
+        range: Range<usize>,
+        mut key: impl FnMut(char) -> K,
+    ) -> Result<BTreeMap<K, u64>, Error> {
+        let (start, end) = self.range_positions(range)?;
+
+        let mut counts = BTreeMap::new();
+        self.read_stretches(start, end.byte_position, |bytes, position| {
+            let mut chars = 0;
+            for (_, c, _) in self.decode_policy.char_indices(bytes, position)? {
+                *counts.entry(key(c)).or_insert(0) += 1;
+                chars += 1;
+            }
+            Ok(chars)
+        })?;
+        Ok(counts)
+    }
+}
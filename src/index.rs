@@ -0,0 +1,206 @@
+use crate::{char_map::CharMap, line_starts::LineStarts, CharPosition, FileStats};
+
+/// Everything learned about the file while looking things up in it,
+/// kept apart from the file's settings so it can grow behind a shared reference
+#[derive(Clone, Debug)]
+pub(crate) struct Index {
+    /// The cache of line ending positions, stored as the position just past each newline
+    /// so that every entry is the start of a line
+    pub(crate) line_ending_positions: LineStarts,
+    /// Positions recorded every `checkpoint_interval` characters regardless of lines,
+    /// so lookups into long lines don't have to scan from the start of the line
+    pub(crate) checkpoints: Vec<CharPosition>,
+    /// The position just past the last character, once the whole file has been indexed
+    pub(crate) end_position: Option<CharPosition>,
+    /// Rank samples for going between character indices and byte offsets without lines, if they've been built
+    pub(crate) char_map: Option<CharMap>,
+    /// The counts worked out by `MappedFile::stats`, once they have been
+    pub(crate) stats: Option<FileStats>,
+    /// How many bytes from the start of the file are known to be ASCII,
+    /// so the index of every character before it is its byte offset
+    pub(crate) ascii_prefix: usize,
+
+    /// The grapheme index of the start of each cached line
+    #[cfg(feature = "graphemes")]
+    pub(crate) grapheme_line_starts: Vec<usize>,
+}
+
+impl Index {
+    /// Creates an index knowing only where the file starts,
+    /// with the line starts delta compressed if `compact` is set
+    pub(crate) fn new(compact: bool) -> Index {
+        Index {
+            // First line starts at the start of the file
+            // Mainly so I can just get the last element regardless of whether i have encountered any line endings yet
+            line_ending_positions: LineStarts::new(compact),
+            checkpoints: Vec::new(),
+            end_position: None,
+            char_map: None,
+            stats: None,
+            ascii_prefix: 0,
+            #[cfg(feature = "graphemes")]
+            grapheme_line_starts: vec![0],
+        }
+    }
+
+    /// Throws away everything but the start of the file.
+    /// The char map and ASCII prefix are kept since they're only about valid text,
+    /// which every setting decodes the same way
+    pub(crate) fn clear(&mut self) {
+        // Knowing where the file ends means every line has been indexed, so that has to go too
+        self.line_ending_positions.truncate(1);
+        self.checkpoints.clear();
+        self.end_position = None;
+        self.stats = None;
+        #[cfg(feature = "graphemes")]
+        self.grapheme_line_starts.truncate(1);
+    }
+
+    /// Forgets every line start after the first `len`, along with anything else found past the last one kept,
+    /// so that part of the file is scanned again when it's next needed.
+    /// The start of the file is always kept
+    pub(crate) fn truncate_lines(&mut self, len: usize) {
+        let lines = self.line_ending_positions.len();
+        self.line_ending_positions.truncate(len.max(1));
+        let last = self.line_ending_positions.last();
+
+        // Everything before the furthest position has to be cached, so checkpoints can't be past the lines
+        let checkpoints = self.checkpoints.len();
+        let kept = self
+            .checkpoints
+            .partition_point(|checkpoint| checkpoint.char_position <= last.char_position);
+        self.checkpoints.truncate(kept);
+        self.checkpoints.shrink_to_fit();
+
+        if self.line_ending_positions.len() < lines || kept < checkpoints {
+            self.end_position = None;
+        }
+        #[cfg(feature = "graphemes")]
+        {
+            let len = self.line_ending_positions.len();
+            self.grapheme_line_starts.truncate(len);
+            self.grapheme_line_starts.shrink_to_fit();
+        }
+    }
+
+    /// Forgets where the file ends and anything found past the first `lines` line starts or past `byte`,
+    /// so scanning carries on from there when the file has grown
+    pub(crate) fn reopen(&mut self, lines: usize, byte: usize) {
+        self.line_ending_positions.truncate(lines.max(1));
+        let kept = self
+            .checkpoints
+            .partition_point(|checkpoint| checkpoint.byte_position <= byte);
+        self.checkpoints.truncate(kept);
+        self.end_position = None;
+        self.stats = None;
+        #[cfg(feature = "graphemes")]
+        self.grapheme_line_starts
+            .truncate(self.line_ending_positions.len());
+    }
+
+    /// Roughly how many bytes everything in the index takes up
+    pub(crate) fn memory_bytes(&self) -> usize {
+        let position = std::mem::size_of::<CharPosition>();
+        let mut bytes = self.line_ending_positions.memory() + self.checkpoints.len() * position;
+        if let Some(char_map) = &self.char_map {
+            bytes += char_map.memory_bytes();
+        }
+        #[cfg(feature = "graphemes")]
+        {
+            bytes += self.grapheme_line_starts.len() * std::mem::size_of::<usize>();
+        }
+        bytes
+    }
+
+    /// Returns the furthest position we've indexed up to,
+    /// every line start before it is in the cache
+    pub(crate) fn frontier(&self) -> CharPosition {
+        let last_line = self.line_ending_positions.last();
+        match self.checkpoints.last() {
+            Some(&checkpoint) if checkpoint.char_position > last_line.char_position => checkpoint,
+            _ => last_line,
+        }
+    }
+
+    /// Finds the closest cached position (line start or checkpoint) at or before the index,
+    /// along with the next cached position after it if there is one
+    pub(crate) fn nearest_cached(&self, index: usize) -> (CharPosition, Option<CharPosition>) {
+        let (line, next_line) = self
+            .line_ending_positions
+            .around(|position| position.char_position <= index);
+        let (checkpoint, next_checkpoint) =
+            around(&self.checkpoints, index, |position| position.char_position);
+
+        let start = match checkpoint {
+            Some(checkpoint) if checkpoint.char_position > line.unwrap().char_position => {
+                checkpoint
+            }
+            // The first line start is the start of the file so there is always one before the index
+            _ => line.unwrap(),
+        };
+        let next = match (next_line, next_checkpoint) {
+            (Some(line), Some(checkpoint)) if checkpoint.char_position < line.char_position => {
+                Some(checkpoint)
+            }
+            (Some(line), _) => Some(line),
+            (None, checkpoint) => checkpoint,
+        };
+
+        (start, next)
+    }
+
+    /// Finds the closest cached position at or before the byte offset
+    pub(crate) fn nearest_cached_byte(&self, byte: usize) -> CharPosition {
+        let (line_start, _) = self
+            .line_ending_positions
+            .around(|position| position.byte_position <= byte);
+        let (checkpoint, _) = around(&self.checkpoints, byte, |position| position.byte_position);
+        match checkpoint {
+            Some(checkpoint) if checkpoint.byte_position > line_start.unwrap().byte_position => {
+                checkpoint
+            }
+            _ => line_start.unwrap(),
+        }
+    }
+
+    /// Records the start of a line unless it's already known
+    pub(crate) fn push_line_start(&mut self, position: CharPosition) {
+        let last = self.line_ending_positions.last();
+        if last.char_position < position.char_position {
+            self.line_ending_positions.push(position);
+        }
+    }
+
+    /// Records a checkpoint if the position lands on the interval and is past the last one,
+    /// returning whether it did
+    pub(crate) fn record_checkpoint(
+        &mut self,
+        interval: Option<usize>,
+        position: CharPosition,
+    ) -> bool {
+        let due = match interval {
+            Some(interval) => position.char_position.is_multiple_of(interval),
+            None => false,
+        };
+        let past_last = self
+            .checkpoints
+            .last()
+            .is_none_or(|last| last.char_position < position.char_position);
+
+        if due && past_last {
+            self.checkpoints.push(position);
+        }
+        due && past_last
+    }
+}
+
+/// Finds the last position at or before the key in a sorted list, and the first one after it
+fn around(
+    positions: &[CharPosition],
+    key: usize,
+    by: impl Fn(&CharPosition) -> usize,
+) -> (Option<CharPosition>, Option<CharPosition>) {
+    let after = positions.partition_point(|position| by(position) <= key);
+    let before = after.checked_sub(1).map(|slot| positions[slot]);
+    (before, positions.get(after).cloned())
+}
@@ -1,6 +1,96 @@
+mod access;
+mod buffered;
+mod builder;
+mod chained;
+mod char_map;
+mod compare;
+mod cursor;
+mod decode;
+mod encoded;
+mod encoding;
+mod error;
+#[cfg(feature = "graphemes")]
+mod graphemes;
+mod grep;
+mod handle;
+mod histogram;
+mod index;
+mod line_endings;
+mod line_starts;
+mod mapping;
+#[cfg(feature = "nom")]
+mod nom_input;
+#[cfg(feature = "parallel")]
+mod parallel;
+mod persist;
+mod position;
+#[cfg(feature = "regex")]
+mod regex_search;
+mod search;
+#[cfg(feature = "segmentation")]
+mod segmentation;
+mod snapshot;
+mod source;
+mod stats;
+mod tabs;
+mod unit;
+mod view;
+#[cfg(feature = "watch")]
+mod watch;
+#[cfg(feature = "width")]
+mod width;
+mod windowed;
+
+pub use {
+    access::UnicodeAccess,
+    buffered::BufferedFile,
+    builder::MappedFileBuilder,
+    chained::ChainedFile,
+    cursor::{Cursor, IntoChars},
+    decode::{DecodePolicy, InvalidRegion},
+    encoded::{EncodedFile, TextEncoding},
+    encoding::DetectedEncoding,
+    error::Error,
+    grep::GrepMatch,
+    handle::FileHandle,
+    line_endings::{LineEndings, LineTerminator},
+    mapping::{Access, Backend, HugePages, Mapping},
+    position::PositionEncoding,
+    search::{CaseSensitivity, SearchOptions},
+    snapshot::{Frozen, Snapshot},
+    source::{BufferedSource, TextSource},
+    stats::FileStats,
+    unit::Unit,
+    view::{FileView, Region},
+    windowed::Windowed,
+};
+
+#[allow(deprecated)]
+pub use error::{IndexError, OpenError};
+#[cfg(feature = "nom")]
+pub use nom_input::NomInput;
+#[cfg(feature = "parallel")]
+pub use parallel::DEFAULT_PARALLEL_CHUNK_SIZE;
+#[cfg(feature = "regex")]
+pub use regex_search::RegexMatch;
+#[cfg(feature = "watch")]
+pub use watch::{Change, WatchedFile};
+
 use {
-    memmap::{Mmap, MmapOptions},
-    std::{fs::File, str::Utf8Error},
+    char_map::CharMap,
+    index::Index,
+    line_starts::{Evicted, LineStarts},
+    mapping::MapOptions,
+    std::{
+        borrow::Cow,
+        fmt,
+        fs::File,
+        io::{self, BufRead, Read, Seek, Write},
+        ops::{self, Range},
+        path::Path,
+        str::Utf8Error,
+        sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    },
 };
 
 #[derive(Clone, Copy, Debug)]
@@ -12,116 +102,1737 @@ pub struct CharPosition {
     pub char_position: usize,
 }
 
-/// A Memory Mapped File
-pub struct MappedFile {
-    /// The file that the memory map is mapped to
-    pub file: File,
-    /// The memory map of the file
-    pub map: Mmap,
+/// What was found when indexing a whole file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IndexStats {
+    /// The number of bytes in the file
+    pub bytes: usize,
+    /// The number of characters in the file
+    pub chars: usize,
+    /// The number of lines in the file
+    pub lines: usize,
+    /// The number of checkpoints recorded within lines
+    pub checkpoints: usize,
+}
 
-    /// The cache of line ending positions
-    pub line_ending_positions: Vec<CharPosition>,
+/// How far a file has been indexed so far, see `MappedFile::index_stats`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IndexProgress {
+    /// The number of bytes in the file
+    pub bytes: usize,
+    /// How many bytes from the start of the file have been indexed
+    pub indexed_bytes: usize,
+    /// How many characters have been indexed
+    pub indexed_chars: usize,
+    /// The number of lines cached, including any evicted to keep under the memory limit
+    pub lines: usize,
+    /// The number of checkpoints recorded within lines
+    pub checkpoints: usize,
+    /// Roughly how many bytes the cached line starts take up
+    pub line_memory: usize,
+    /// Whether the whole file has been indexed
+    pub complete: bool,
+    /// Whether `build_char_map` has been called
+    pub char_map: bool,
 }
 
-#[derive(Debug)]
-pub enum IndexError {
-    /// The index is outside of the bounds of the file
-    OutOfBounds,
-    /// The index is not a valid utf8 character
-    InvalidChar(Utf8Error),
+/// What was added to the end of a file, see `MappedFile::refresh`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Appended {
+    /// The indices of the characters that were added
+    pub chars: Range<usize>,
+    /// The numbers of the lines with characters that were added,
+    /// starting with what was the last line unless nothing was added at all
+    pub lines: Range<usize>,
+}
+
+/// A line borrowed from a file along with where it is, see `MappedFile::lines`
+#[derive(Clone, Copy, Debug)]
+pub struct LineRef<'a> {
+    /// The zero-based line number
+    pub line: usize,
+    /// Where the line starts
+    pub start: CharPosition,
+    /// The text of the line without its terminator
+    pub text: &'a str,
+}
+
+/// A Memory Mapped File, or any other source of text that can be indexed the same way.
+/// Lookups only need a shared reference, so a file over a `Sync` source can be shared between threads
+pub struct MappedFile<S: TextSource = Mapping> {
+    /// The memory map of the file
+    pub map: S,
+
+    /// The line starts and checkpoints found so far, which lookups grow as they go
+    /// so they only need a shared reference.
+    /// Stretches are decoded without holding the lock, it's only taken to record what was found
+    index: RwLock<Index>,
+    /// How many characters apart checkpoints are recorded, if at all
+    checkpoint_interval: Option<usize>,
+    /// Which characters end a line
+    line_endings: LineEndings,
+    /// What to do with bytes that aren't valid utf8
+    decode_policy: DecodePolicy,
+    /// Whether to check the file hasn't changed before each lookup
+    check_stale: bool,
 }
 
+/// How many bytes are scanned at a time when jumping between terminators with memchr
+const SCAN_STEP: usize = 64 * 1024;
+
+/// How many characters apart lookups in a batch can be for the next to be walked to from the last
+const BATCH_WALK: usize = 1024;
+
+/// How many characters apart checkpoints are recorded by default
+pub const DEFAULT_CHECKPOINT_INTERVAL: usize = 64 * 1024;
+
 impl MappedFile {
     /// Creates a new MappedFile from a File
+    /// falling back to reading it into memory if it can't be mapped (see `backend`)
     /// possibly returning an error
-    pub fn new(file: File) -> Result<MappedFile, String> {
-        let map = unsafe { MmapOptions::new().map(&file).map_err(|e| e.to_string())? };
-        Ok(MappedFile {
-            file,
-            map,
-            // First line ending is the start of the file
-            // Mainly so I can just get the last element regardless of whether i have encountered any line endings yet
-            line_ending_positions: vec![CharPosition {
-                char_position: 0,
-                byte_position: 0,
-            }],
+    pub fn new(file: File) -> Result<MappedFile, Error> {
+        Ok(MappedFile::from_source(Mapping::new(file)?))
+    }
+
+    /// Creates a new MappedFile from a stream such as stdin
+    /// by spilling it to an anonymous temporary file and mapping that
+    /// possibly returning an error
+    pub fn from_reader(mut reader: impl Read) -> Result<MappedFile, Error> {
+        let mut file = tempfile::tempfile()?;
+        io::copy(&mut reader, &mut file)?;
+        file.flush()?;
+
+        MappedFile::new(file)
+    }
+
+    /// Creates a new MappedFile from the file at a path, remembering the path so `reload` can open it again
+    /// possibly returning an error
+    pub fn open_path(path: impl AsRef<Path>) -> Result<MappedFile, Error> {
+        Ok(MappedFile::from_source(Mapping::open_path(
+            path.as_ref(),
+            MapOptions::default(),
+        )?))
+    }
+
+    /// Returns how the bytes of the file are being accessed
+    pub fn backend(&self) -> Backend {
+        self.map.backend()
+    }
+
+    /// Returns whether the file starts with a UTF-8 byte order mark that's being skipped,
+    /// so character 0 and byte 0 are the ones after it, see `MappedFileBuilder::skip_bom`
+    pub fn has_bom(&self) -> bool {
+        self.map.has_bom()
+    }
+
+    /// Locks the whole file into memory so lookups never have to wait for the disk,
+    /// trading memory for predictable latency, see `Mapping::lock`.
+    /// Returns `Error::LockLimit` if the file is bigger than the process is allowed to lock.
+    pub fn lock(&self) -> Result<(), Error> {
+        self.map.lock(0..self.map.len())
+    }
+
+    /// Locks the part of the file between two byte offsets into memory, see `Mapping::lock`.
+    /// Returns `Error::LockLimit` if it's more than the process is allowed to lock,
+    /// or `Error::OutOfBounds` if the range goes past the end of the file.
+    pub fn lock_range(&self, bytes: Range<usize>) -> Result<(), Error> {
+        self.map.lock(bytes)
+    }
+
+    /// Unlocks the whole file so it can be paged out again
+    pub fn unlock(&self) -> Result<(), Error> {
+        self.map.unlock(0..self.map.len())
+    }
+
+    /// Returns whether the file's memory is backed by huge pages, see `MappedFileBuilder::huge_pages`
+    pub fn huge_pages(&self) -> HugePages {
+        self.map.huge_pages()
+    }
+
+    /// Starts reading the part of the file between two byte offsets into memory ahead of time,
+    /// like the region around what's on screen before it's scrolled to, see `Mapping::prefetch`.
+    /// It takes byte offsets since finding where characters are would mean reading the pages.
+    /// See `MappedFileBuilder::populate` for reading the whole file in when it's opened.
+    /// Returns an error if the OS rejects the request.
+    pub fn prefetch(&self, bytes: Range<usize>) -> Result<(), Error> {
+        Ok(self.map.prefetch(bytes)?)
+    }
+
+    /// Tells the OS how the file is about to be looked things up in, see `Mapping::advise`,
+    /// like `Access::Random` before jumping around a large file that isn't cached yet
+    /// so the pages around each lookup aren't read ahead for nothing.
+    /// Returns an error if the OS rejects the hint.
+    pub fn advise(&self, access: Access) -> Result<(), Error> {
+        Ok(self.map.advise(access)?)
+    }
+
+    /// Picks up whatever has been appended to the file since it was opened or last refreshed,
+    /// like `tail -f`, indexing only the new bytes and returning which characters and lines are new.
+    /// A character that was only half written before counts as new once the rest of it arrives.
+    /// The same file is followed even if it's replaced at its path, see `reload` for that,
+    /// and the file is assumed to only ever be appended to.
+    /// Returns `Error::FileChanged` if the file has shrunk, or an error if it can't be mapped again
+    /// or isn't valid utf8.
+    pub fn refresh(&mut self) -> Result<Appended, Error> {
+        let len = self.map.len();
+        if !self.map.is_stale()? {
+            let (chars, lines) = (self.char_count()?, self.line_count()?);
+            return Ok(Appended {
+                chars: chars..chars,
+                lines: lines..lines,
+            });
+        }
+
+        self.map.remap()?;
+        if self.map.len() < len {
+            return Err(Error::FileChanged);
+        }
+
+        // The last few bytes might have been half a character or a \r before a \n,
+        // so anything found in them has to be found again
+        let settled = len.saturating_sub(4);
+        let kept = self.with_lines(|lines| {
+            lines.partition_point(|position| position.byte_position <= settled)
+        })?;
+        let index = self.index.get_mut().unwrap_or_else(PoisonError::into_inner);
+        index.reopen(kept, settled);
+        index.char_map = None;
+
+        // Finding the character the old end is in snaps back to the start of any half written one
+        let start = self.char_index_at_byte(len)?.char_position;
+        let chars = start..self.char_count()?;
+        let lines = if chars.is_empty() {
+            let lines = self.line_count()?;
+            lines..lines
+        } else {
+            self.line_col_of(start)?.0..self.line_count()?
+        };
+        Ok(Appended { chars, lines })
+    }
+
+    /// Maps the file again after it has been changed or replaced, see `Mapping::reload`,
+    /// throwing away everything that was indexed since it may no longer be right.
+    /// The settings are kept, but the char map has to be built again if it's wanted.
+    /// Returns an error if the file can't be opened or mapped again.
+    pub fn reload(&mut self) -> Result<(), Error> {
+        self.map.reload()?;
+
+        let index = self.index.get_mut().unwrap_or_else(PoisonError::into_inner);
+        index.clear();
+        index.char_map = None;
+        index.ascii_prefix = 0;
+        Ok(())
+    }
+}
+
+/// Text held in memory, indexed the same way as a mapped file
+pub type InMemoryFile = MappedFile<Vec<u8>>;
+
+impl InMemoryFile {
+    /// Creates a new MappedFile over bytes that are already in memory
+    pub fn from_bytes(bytes: Vec<u8>) -> InMemoryFile {
+        MappedFile::from_source(bytes)
+    }
+}
+
+impl From<Vec<u8>> for InMemoryFile {
+    fn from(bytes: Vec<u8>) -> Self {
+        MappedFile::from_bytes(bytes)
+    }
+}
+
+impl From<String> for InMemoryFile {
+    fn from(text: String) -> Self {
+        MappedFile::from_bytes(text.into_bytes())
+    }
+}
+
+impl<'a> From<&'a str> for MappedFile<&'a str> {
+    fn from(text: &'a str) -> Self {
+        MappedFile::from_source(text)
+    }
+}
+
+impl<S: TextSource> MappedFile<S> {
+    /// Creates a new MappedFile reading from any source of text
+    pub fn from_source(source: S) -> MappedFile<S> {
+        MappedFile {
+            map: source,
+            index: RwLock::new(Index::new(true)),
+            checkpoint_interval: Some(DEFAULT_CHECKPOINT_INTERVAL),
+            line_endings: LineEndings::default(),
+            decode_policy: DecodePolicy::default(),
+            check_stale: false,
+        }
+    }
+
+    /// Locks the index to read it.
+    /// Locks are kept short so nothing is still reading the index when a scan needs to grow it
+    fn index(&self) -> RwLockReadGuard<'_, Index> {
+        // Nothing panics halfway through changing the index, so it's still fine to use
+        self.index.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the index to grow it
+    fn index_mut(&self) -> RwLockWriteGuard<'_, Index> {
+        self.index.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns a copy of the line starts cached so far, the first always being the start of the file.
+    /// Any lines evicted to keep under the memory limit are scanned again first.
+    /// Returns an error if they can't be read again.
+    pub fn line_ending_positions(&self) -> Result<Vec<CharPosition>, Error> {
+        let mut positions = Vec::new();
+        // A page at a time, since with a tight limit bringing one back can evict another
+        let pages = self.index().line_ending_positions.page_count();
+        for page in 0..pages {
+            positions.extend(self.with_lines(|lines| Ok(lines.page(page)?.collect::<Vec<_>>()))?);
+        }
+        Ok(positions)
+    }
+
+    /// Returns an iterator over the position just past each line terminator, which is where the next line starts,
+    /// the same as `line_ending_positions` without the start of the file.
+    /// The file is indexed as the iterator goes, rather than all at once.
+    /// The iterator stops early if part of the file can't be decoded.
+    pub fn newline_positions(&self) -> impl Iterator<Item = CharPosition> + '_ {
+        (1..).map_while(move |line| {
+            self.index_lines_until(line).ok()?;
+            self.with_lines(|lines| lines.get(line)).ok()?
         })
     }
 
-    fn find_with_cache(&mut self, index: usize) -> Result<char, IndexError> {
-        for window in self.line_ending_positions.windows(2) {
-            let (last, current) = (window[0], window[1]);
+    /// Runs a lookup on the cached line starts, scanning the lines of whichever page it needed again
+    /// if they were evicted to keep under the memory limit
+    fn with_lines<T>(
+        &self,
+        lookup: impl Fn(&LineStarts) -> Result<T, Evicted>,
+    ) -> Result<T, Error> {
+        loop {
+            let looked_up = lookup(&self.index().line_ending_positions);
+            match looked_up {
+                Ok(found) => return Ok(found),
+                Err(Evicted(page)) => self.restore_page(page)?,
+            }
+        }
+    }
 
-            // Check if the index is in the current window
-            if last.char_position < index && index <= current.char_position {
-                // If it is, we locate it in the map
-                return self.find_nth_in_str(
-                    index - last.char_position,
-                    last,
-                    Some(current),
-                );
+    /// Finds the line starts of an evicted page again and puts them back in the cache.
+    /// Every line start up to the next page is already known, so it's just those in between
+    fn restore_page(&self, page: usize) -> Result<(), Error> {
+        let bounds = self.index().line_ending_positions.evicted_bounds(page);
+        let (start, end) = match bounds {
+            Some(bounds) => bounds,
+            None => return Ok(()),
+        };
+
+        // One byte past the page tells whether a \r at the end of it is half of a \r\n
+        let text = end.byte_position - start.byte_position;
+        let range = start.byte_position..(end.byte_position + 1).min(self.map.len());
+        let lines = self.map.with_bytes(range, |bytes| {
+            let mut lines = vec![start];
+            let chars = self.decode_policy.char_indices(&bytes[..text], start)?;
+            for (char_index, (byte_offset, c, width)) in chars.enumerate() {
+                let next = byte_offset + width;
+                if next < text && self.line_endings.ends_line(c, bytes.get(next).copied()) {
+                    lines.push(CharPosition {
+                        byte_position: start.byte_position + next,
+                        char_position: start.char_position + char_index + 1,
+                    });
+                }
             }
+            Ok::<_, Error>(lines)
+        })??;
+
+        self.index_mut().line_ending_positions.restore(page, lines);
+        Ok(())
+    }
+
+    fn find_with_cache(&self, index: usize) -> Result<CharPosition, Error> {
+        // Find the last cached position at or before the index
+        let nearest = self.index().nearest_cached(index);
+        match nearest {
+            // The index is only known to be in the cache if there is a cached position after it
+            (last, Some(current)) => {
+                self.find_nth_in_str(index - last.char_position, last, Some(current))
+            }
+            // If we get here, it means that the index is past the end of the cached lines
+            (_, None) => Err(Error::OutOfBounds {
+                index,
+                len: self.index().frontier().char_position,
+            }),
         }
-        
-        // If we get here, it means that the index is outside of the bounds of the line
-        Err(IndexError::OutOfBounds)
     }
 
     fn find_nth_in_str(
-        &mut self,
+        &self,
         n: usize,
         start: CharPosition,
         end: Option<CharPosition>,
-    ) -> Result<char, IndexError> {
-        let str = match std::str::from_utf8(match end {
-            Some(end) => &self.map[start.byte_position..end.byte_position],
-            None => &self.map[start.byte_position..],
-        }) {
-            Ok(s) => s,
-            Err(e) => return Err(IndexError::InvalidChar(e)),
-        };
-
-        // If we know we're inside a line, we can just get the nth character
-        if let Some(_) = end {
-            Ok(str.chars().nth(n).unwrap())
-        // Otherwise we have to iterate and update the cache
-        } else {
-            let mut byte_position = start.byte_position;
-            for (char_index, c) in str.chars().enumerate() {
-                // if we have a newline we need to update the line ending indexes
-                if c == '\n' {
-                    self.line_ending_positions.push(CharPosition {
-                        byte_position: byte_position,
-                        char_position: char_index + start.char_position,
+    ) -> Result<CharPosition, Error> {
+        // Past the cache we have to scan forward and update it as we go
+        let end = match end {
+            Some(end) => end,
+            None => return self.find_nth_scanning(n, start),
+        };
+
+        self.map
+            .with_bytes(start.byte_position..end.byte_position, |bytes| {
+                // Every character of ASCII is one byte, so there's nothing to decode
+                if bytes.is_ascii() && n < bytes.len() {
+                    return Ok(CharPosition {
+                        byte_position: start.byte_position + n,
+                        char_position: start.char_position + n,
                     });
                 }
 
-                // if we have found the index, return the char
-                if char_index == n {
-                    return Ok(c);
+                // We know we're between two cached positions, so we can just get the nth character
+                let (byte_offset, _, _) = self
+                    .decode_policy
+                    .char_indices(bytes, start)?
+                    .nth(n)
+                    .ok_or(Error::OutOfBounds {
+                        index: start.char_position + n,
+                        len: end.char_position,
+                    })?;
+                Ok(CharPosition {
+                    byte_position: start.byte_position + byte_offset,
+                    char_position: start.char_position + n,
+                })
+            })?
+    }
+
+    /// Finds the nth character after the start by scanning forward a stretch at a time,
+    /// only decoding the stretch the character is in.
+    fn find_nth_scanning(&self, n: usize, start: CharPosition) -> Result<CharPosition, Error> {
+        let target = start.char_position + n;
+        let mut position = start;
+        loop {
+            let next = self.scan_step(position)?;
+            if target < next.char_position {
+                return self.find_nth_in_str(target - position.char_position, position, Some(next));
+            }
+
+            // The index just past the last character is the end of the file
+            if self.index().end_position.is_some() {
+                if target == next.char_position {
+                    return Ok(next);
+                }
+                return Err(Error::OutOfBounds {
+                    index: target,
+                    len: next.char_position,
+                });
+            }
+            position = next;
+        }
+    }
+
+    /// Scans one stretch of the file up to the next terminator, using memchr to jump to it
+    /// when every terminator is a single byte, recording the line start or any checkpoints
+    /// found on the way and returning where it stopped.
+    /// A stretch ends after a terminator, at the end of the file or after about `SCAN_STEP` bytes,
+    /// so only that much is ever decoded at once.
+    fn scan_step(&self, start: CharPosition) -> Result<CharPosition, Error> {
+        let len = self.map.len();
+        let window_end = (start.byte_position + SCAN_STEP).min(len);
+        // Grab a few bytes past the window so we can see what follows it
+        let range = start.byte_position..(window_end + 4).min(len);
+        self.map.with_bytes(range, |bytes| {
+            let window = window_end - start.byte_position;
+
+            let cut = stretch_end(bytes, window, window_end == len);
+
+            let line_end = if self.line_endings.is_ascii() {
+                // A \r might be followed by a \n that ends the line instead
+                let mut search = 0;
+                let mut line_end = None;
+                while let Some(found) = self.line_endings.find_terminator(&bytes[search..window]) {
+                    let at = search + found;
+                    if self
+                        .line_endings
+                        .ends_line(bytes[at] as char, bytes.get(at + 1).copied())
+                    {
+                        line_end = Some(at + 1);
+                        break;
+                    }
+                    search = at + 1;
                 }
+                line_end
+            } else {
+                // Multibyte terminators have to be decoded to be found, but when being strict only up to
+                // the first invalid byte since the line might end before it
+                let decodable = match (self.decode_policy, str_from_utf8(&bytes[..cut])) {
+                    (DecodePolicy::Strict, Err(e)) => &bytes[..e.valid_up_to()],
+                    _ => &bytes[..cut],
+                };
+                self.decode_policy
+                    .char_indices(decodable, start)?
+                    .find(|&(byte_offset, c, width)| {
+                        self.line_endings
+                            .ends_line(c, bytes.get(byte_offset + width).copied())
+                    })
+                    .map(|(byte_offset, _, width)| byte_offset + width)
+            };
+            let end = line_end.unwrap_or(cut);
 
-                // update the positions
-                byte_position += c.len_utf8();
+            // A stretch of ASCII has as many characters as bytes, so it doesn't have to be decoded
+            let ascii = bytes[..end].is_ascii();
+            let chars = match ascii {
+                true => end,
+                false => self.decode_policy.count_chars(&bytes[..end], start)?,
+            };
+            let next = CharPosition {
+                byte_position: start.byte_position + end,
+                char_position: start.char_position + chars,
+            };
+
+            let mut index = self.index_mut();
+            if ascii && index.ascii_prefix == start.byte_position {
+                index.ascii_prefix = next.byte_position;
+            }
+
+            // Only walk the characters if a checkpoint lands somewhere in the stretch
+            if let Some(interval) = self.checkpoint_interval {
+                let first = (start.char_position / interval + 1) * interval;
+                if ascii {
+                    for char_position in (first..=next.char_position).step_by(interval) {
+                        index.record_checkpoint(
+                            self.checkpoint_interval,
+                            CharPosition {
+                                byte_position: start.byte_position + char_position
+                                    - start.char_position,
+                                char_position,
+                            },
+                        );
+                    }
+                } else if first <= next.char_position {
+                    let chars = self.decode_policy.char_indices(&bytes[..end], start)?;
+                    for (char_index, (byte_offset, _, width)) in chars.enumerate() {
+                        index.record_checkpoint(
+                            self.checkpoint_interval,
+                            CharPosition {
+                                byte_position: start.byte_position + byte_offset + width,
+                                char_position: start.char_position + char_index + 1,
+                            },
+                        );
+                    }
+                }
             }
 
-            // if we get here, we didn't find the index
-            Err(IndexError::OutOfBounds)
+            if line_end.is_some() {
+                index.push_line_start(next);
+            } else if window_end == len {
+                // We've seen the whole file so remember where it ends
+                index.end_position = Some(next);
+            }
+
+            Ok(next)
+        })?
+    }
+
+    /// Runs `read` on the bytes from a position up to a byte a stretch at a time,
+    /// each ending on a character boundary, so only about `SCAN_STEP` bytes are read at once.
+    /// `read` gets where its stretch starts and gives back how many characters are in it,
+    /// and the position of the end is returned
+    fn read_stretches(
+        &self,
+        start: CharPosition,
+        end: usize,
+        mut read: impl FnMut(&[u8], CharPosition) -> Result<usize, Error>,
+    ) -> Result<CharPosition, Error> {
+        let mut position = start;
+        while position.byte_position < end {
+            let window_end = (position.byte_position + SCAN_STEP).min(end);
+            // Grab a few bytes past the window so we can see what follows it
+            let range = position.byte_position..(window_end + 4).min(end);
+            let (cut, chars) = self.map.with_bytes(range, |bytes| {
+                let cut = stretch_end(
+                    bytes,
+                    window_end - position.byte_position,
+                    window_end == end,
+                );
+                Ok::<_, Error>((cut, read(&bytes[..cut], position)?))
+            })??;
+            position = CharPosition {
+                byte_position: position.byte_position + cut,
+                char_position: position.char_position + chars,
+            };
         }
+        Ok(position)
     }
 
-    /// Returns the index of the line ending at the given byte position.
-    /// Returns an error if the byte position is out of bounds.
-    pub fn unicode_at(&mut self, index: usize) -> Result<char, IndexError> {
-        // Check through to see if we have something close to the index in the line cache
-        if let Ok(c) = self.find_with_cache(index) {
-            return Ok(c);
+    /// Counts the characters from a position up to a byte, a stretch at a time
+    fn count_chars_to(&self, start: CharPosition, end: usize) -> Result<usize, Error> {
+        let end = self.read_stretches(start, end, |bytes, position| {
+            self.decode_policy.count_chars(bytes, position)
+        })?;
+        Ok(end.char_position - start.char_position)
+    }
+
+    /// Finds the position of the character at the given index.
+    /// The index one past the last character resolves to the end of the file.
+    fn position_at(&self, index: usize) -> Result<CharPosition, Error> {
+        self.check_fresh()?;
+
+        // Once we know where the file ends we don't need to scan to find out an index is past it
+        let end_position = self.index().end_position;
+        match end_position {
+            Some(end) if index == end.char_position => return Ok(end),
+            Some(end) if index > end.char_position => {
+                return Err(Error::OutOfBounds {
+                    index,
+                    len: end.char_position,
+                })
+            }
+            _ => {}
+        }
+
+        {
+            let cache = self.index();
+            if index < cache.ascii_prefix {
+                return Ok(CharPosition {
+                    byte_position: index,
+                    char_position: index,
+                });
+            }
+            if let Some(char_map) = &cache.char_map {
+                return char_map.select(&self.map, index);
+            }
+        }
+
+        let current = self.index().frontier();
+
+        // Check in the line cache if the index is before the furthest position we know about
+        if index < current.char_position {
+            self.find_with_cache(index)
         } else {
-            let current = self.line_ending_positions.last().cloned().unwrap();
             // Go through the file until we find the index
             self.find_nth_in_str(index - current.char_position, current, None)
         }
     }
+
+    /// Finds the line containing the character at the given index along with the character's position.
+    fn line_of(&self, index: usize) -> Result<(usize, CharPosition), Error> {
+        let position = self.position_at(index)?;
+        // Locating the character usually caches every line start up to it, but not with a char map
+        self.index_lines_while(|_, start| start.char_position <= index)?;
+
+        // The last line starting at or before the index, and the first line always starts at 0
+        let line = self.with_lines(|lines| {
+            lines.partition_point(|position| position.char_position <= index)
+        })? - 1;
+
+        Ok((line, position))
+    }
+
+    /// Decodes the character at a position that is known to be the start of a character.
+    fn char_at(&self, position: CharPosition) -> Result<char, Error> {
+        Ok(self.char_and_width_at(position)?.0)
+    }
+
+    /// Decodes the character at a position that is known to be the start of a character,
+    /// along with how many bytes it takes up in the file.
+    fn char_and_width_at(&self, position: CharPosition) -> Result<(char, usize), Error> {
+        let byte_position = position.byte_position;
+        // A character can only start at the end of the file if there are that many characters
+        let out_of_bounds = Error::OutOfBounds {
+            index: position.char_position,
+            len: position.char_position,
+        };
+        if byte_position >= self.map.len() {
+            return Err(out_of_bounds);
+        }
+
+        let width = self
+            .map
+            .with_bytes(byte_position..byte_position + 1, |bytes| {
+                utf8_char_width(bytes[0])
+            })?;
+        let end = (byte_position + width).min(self.map.len());
+
+        let decoded = self.map.with_bytes(byte_position..end, |bytes| {
+            Ok::<_, Error>(self.decode_policy.char_indices(bytes, position)?.next())
+        })??;
+        match decoded {
+            Some((_, c, width)) => Ok((c, width)),
+            None => Err(out_of_bounds),
+        }
+    }
+
+    /// Returns an iterator over the characters starting at the given index.
+    /// The iterator is empty if the index is out of bounds and stops early at invalid utf8,
+    /// unless decoding lossily where it gives U+FFFD instead.
+    pub fn chars_from(&self, index: usize) -> impl Iterator<Item = char> + '_ {
+        // Find where to start using the line cache, after that we just decode sequentially
+        let mut byte_position = match self.position_at(index) {
+            Ok(position) => position.byte_position,
+            Err(_) => self.map.len(),
+        };
+        let map = &self.map;
+        let decode_policy = self.decode_policy;
+
+        std::iter::from_fn(move || {
+            let end = (byte_position + 4).min(map.len());
+            let (c, width) = map
+                .with_bytes(byte_position..end, |bytes| decode_policy.decode_next(bytes))
+                .ok()??;
+            byte_position += width;
+            Some(c)
+        })
+    }
+
+    /// Returns an iterator over the characters before the given index, walking backwards.
+    /// Together with `chars_from` at the same index this covers the whole file.
+    /// The iterator is empty if the index is out of bounds and stops early at invalid utf8,
+    /// unless decoding lossily where it gives U+FFFD instead.
+    pub fn chars_rev_from(&self, index: usize) -> impl Iterator<Item = char> + '_ {
+        let mut byte_position = match self.position_at(index) {
+            Ok(position) => position.byte_position,
+            Err(_) => 0,
+        };
+        let map = &self.map;
+        let decode_policy = self.decode_policy;
+
+        std::iter::from_fn(move || {
+            let window_start = byte_position.saturating_sub(4);
+            let (c, start) = map
+                .with_bytes(window_start..byte_position, |window| {
+                    decode_policy.decode_previous(window)
+                })
+                .ok()??;
+            byte_position = window_start + start;
+            Some(c)
+        })
+    }
+
+    /// Scans forward from the last cached line start until the start of the given line is known
+    /// or the end of the file is reached.
+    fn index_lines_until(&self, line: usize) -> Result<(), Error> {
+        self.index_lines_while(|lines, _| lines <= line)
+    }
+
+    /// Scans forward from the furthest indexed position, recording line starts for as long as
+    /// `keep_going` holds for the number of known lines and the position scanned up to,
+    /// or until the end of the file is reached.
+    fn index_lines_while(
+        &self,
+        mut keep_going: impl FnMut(usize, CharPosition) -> bool,
+    ) -> Result<(), Error> {
+        self.check_fresh()?;
+
+        let (start, finished, lines) = {
+            let index = self.index();
+            (
+                index.frontier(),
+                index.end_position.is_some(),
+                index.line_ending_positions.len(),
+            )
+        };
+        if finished || !keep_going(lines, start) {
+            return Ok(());
+        }
+
+        // Go a stretch at a time so only what's needed gets decoded
+        let mut position = start;
+        while self.index().end_position.is_none() {
+            position = self.scan_step(position)?;
+            let lines = self.index().line_ending_positions.len();
+            if !keep_going(lines, position) {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the number of characters in the file.
+    /// The first call indexes the rest of the file, after that the count is cached.
+    /// Returns an error if the file isn't valid utf8.
+    pub fn char_count(&self) -> Result<usize, Error> {
+        if let Some(char_map) = &self.index().char_map {
+            return Ok(char_map.chars());
+        }
+        if self.index().ascii_prefix == self.map.len() {
+            return Ok(self.map.len());
+        }
+        self.index_lines_until(usize::MAX)?;
+
+        // Indexing to the end of the file always records where it ends
+        Ok(self.index().end_position.unwrap().char_position)
+    }
+
+    /// Indexes the whole file up front, recording every line start and checkpoint
+    /// and checking the file is valid utf8, so later lookups never have to scan.
+    /// Does nothing more than return the stats if the file has already been indexed.
+    /// Returns an error if the file isn't valid utf8.
+    pub fn build_index(&self) -> Result<IndexStats, Error> {
+        self.index_lines_until(usize::MAX)?;
+
+        let index = self.index();
+        let end = index.end_position.unwrap();
+        Ok(IndexStats {
+            bytes: end.byte_position,
+            chars: end.char_position,
+            lines: index.line_ending_positions.len(),
+            checkpoints: index.checkpoints.len(),
+        })
+    }
+
+    /// Finds the bytes of a line including its trailing newline if it has one.
+    fn line_span(&self, line_number: usize) -> Result<Range<usize>, Error> {
+        // We need the start of the next line to know where this one ends
+        self.index_lines_until(line_number + 1)?;
+
+        let start = match self.with_lines(|lines| lines.get(line_number))? {
+            Some(start) => start.byte_position,
+            None => {
+                return Err(Error::OutOfBounds {
+                    index: line_number,
+                    len: self.index().line_ending_positions.len(),
+                })
+            }
+        };
+        let end = match self.with_lines(|lines| lines.get(line_number + 1))? {
+            Some(next) => next.byte_position,
+            None => self.map.len(),
+        };
+
+        Ok(start..end)
+    }
+
+    /// Finds the bytes of a line's text along with the terminator that ends it.
+    /// The last line has no terminator.
+    fn line_parts(
+        &self,
+        line_number: usize,
+    ) -> Result<(Range<usize>, Option<LineTerminator>), Error> {
+        let span = self.line_span(line_number)?;
+
+        let next_line = self.with_lines(|lines| lines.get(line_number + 1))?;
+        let terminator = if let Some(next_line) = next_line {
+            // The line was ended by its last character, which was already decoded when it was indexed
+            let terminator = self.map.with_bytes(span.clone(), |bytes| {
+                let last = previous_char_start(bytes, bytes.len()).unwrap_or(0);
+                let (c, _) = decode_char(&bytes[last..]).ok_or_else(|| Error::InvalidUtf8 {
+                    byte_offset: span.start + last,
+                    char_index: next_line.char_position - 1,
+                    context: bytes[last..].to_vec(),
+                })?;
+                let previous_byte = last.checked_sub(1).map(|byte| bytes[byte]);
+                Ok::<_, Error>(self.line_endings.terminator(c, previous_byte))
+            })??;
+            Some(terminator)
+        } else {
+            None
+        };
+
+        let end = span.end - terminator.map_or(0, LineTerminator::byte_len);
+        Ok((span.start..end, terminator))
+    }
+
+    /// Returns which characters end a line.
+    pub fn line_endings(&self) -> &LineEndings {
+        &self.line_endings
+    }
+
+    /// Changes which characters end a line.
+    /// The cached lines are thrown away since they may no longer start in the same places.
+    pub fn set_line_endings(&mut self, line_endings: LineEndings) {
+        self.line_endings = line_endings;
+        self.index_mut().clear();
+    }
+
+    /// Returns what happens to bytes that aren't valid utf8.
+    pub fn decode_policy(&self) -> DecodePolicy {
+        self.decode_policy
+    }
+
+    /// Changes what happens to bytes that aren't valid utf8.
+    /// The cache is thrown away since replacements change where characters are.
+    /// Lookups that borrow a `&str` from the file, like `line_at`, still fail on invalid bytes
+    /// since there's nowhere to put the replacements, see `UnicodeAccess::line_string`.
+    pub fn set_decode_policy(&mut self, decode_policy: DecodePolicy) {
+        self.decode_policy = decode_policy;
+        self.index_mut().clear();
+    }
+
+    /// Returns whether the file has changed since it was opened, going by its length and modification time.
+    /// Text that's only in memory never changes.
+    /// Returns an error if the file's metadata can't be read.
+    pub fn is_stale(&self) -> Result<bool, Error> {
+        Ok(self.map.is_stale()?)
+    }
+
+    /// Frees whatever the source has been holding on to only because lookups might still have been borrowing it,
+    /// like the windows a `Windowed` file has slid away from, see `TextSource::release`
+    pub fn release(&mut self) {
+        self.map.release();
+    }
+
+    /// Returns whether lookups check the file hasn't changed first
+    pub fn check_stale(&self) -> bool {
+        self.check_stale
+    }
+
+    /// Sets whether lookups check the file hasn't changed before reading it,
+    /// failing with `Error::FileChanged` if it has rather than giving back whatever is there now.
+    /// It's off by default since it costs a call to the OS every lookup.
+    pub fn set_check_stale(&mut self, check: bool) {
+        self.check_stale = check;
+    }
+
+    /// Fails with `Error::FileChanged` if checking is on and the file has changed
+    fn check_fresh(&self) -> Result<(), Error> {
+        if self.check_stale && self.is_stale()? {
+            return Err(Error::FileChanged);
+        }
+        Ok(())
+    }
+
+    /// Returns how many characters apart checkpoints are recorded, if they are at all
+    pub fn checkpoint_interval(&self) -> Option<usize> {
+        self.checkpoint_interval
+    }
+
+    /// Sets how many characters apart checkpoints are recorded so lookups into long lines
+    /// don't have to scan from the start of the line, or turns them off with `None`.
+    /// Checkpoints already recorded are dropped.
+    pub fn set_checkpoint_interval(&mut self, interval: Option<usize>) {
+        // An interval of zero would checkpoint nothing, so treat it as off
+        self.checkpoint_interval = interval.filter(|&interval| interval > 0);
+        self.index_mut().checkpoints.clear();
+    }
+
+    /// Returns whether line starts are stored as deltas from one another,
+    /// which takes a fraction of the memory at the cost of a little decoding on each lookup
+    pub fn compact_index(&self) -> bool {
+        self.index().line_ending_positions.is_compact()
+    }
+
+    /// Chooses whether line starts are stored as deltas from one another (the default)
+    /// or in full as two `usize` each, which is a little quicker to look up.
+    /// The lines cached so far are kept.
+    pub fn set_compact_index(&mut self, compact: bool) {
+        self.index_mut().line_ending_positions.set_compact(compact);
+    }
+
+    /// Returns how many bytes the cached line starts can take up before some are evicted, if there's a limit
+    pub fn index_memory_limit(&self) -> Option<usize> {
+        self.index().line_ending_positions.memory_limit()
+    }
+
+    /// Limits roughly how many bytes the cached line starts can take up, or lifts the limit with `None`.
+    /// Once they're over it, the line starts of whichever 1 MiB pages of the file were used least recently
+    /// are evicted, keeping only where each page's first line starts,
+    /// and are scanned again from the file the next time they're needed.
+    /// Lookups by character index carry on from the coarser positions left behind without scanning them again.
+    /// Checkpoints and the char map aren't limited. The pages being added to are never evicted.
+    pub fn set_index_memory_limit(&mut self, limit: Option<usize>) {
+        self.index_mut()
+            .line_ending_positions
+            .set_memory_limit(limit);
+    }
+
+    /// Builds rank and select samples over the whole file so that going between character indices
+    /// and byte offsets takes constant time however long the lines are: looking through at most 5 samples
+    /// and counting through at most 512 bytes.
+    /// They take up at most a quarter of a bit for every byte of the file.
+    /// Lines are still cached as lookups reach them.
+    /// Does nothing if they've already been built.
+    /// Returns an error if the file isn't valid utf8.
+    pub fn build_char_map(&self) -> Result<(), Error> {
+        if self.has_char_map() {
+            return Ok(());
+        }
+
+        // Counting is done without the lock so lookups can carry on in the meantime
+        let char_map = CharMap::build(&self.map)?;
+        self.index_mut().char_map = Some(char_map);
+        Ok(())
+    }
+
+    /// Returns whether `build_char_map` has been called
+    pub fn has_char_map(&self) -> bool {
+        self.index().char_map.is_some()
+    }
+
+    /// Checks whether the whole file is ASCII, carrying on from however much of it is already known to be.
+    /// Once it is, a character's index is its byte offset, so lookups never decode or scan for it.
+    /// Scanning the file for lines finds the same thing for as far as it goes
+    pub fn is_ascii(&self) -> Result<bool, Error> {
+        self.check_fresh()?;
+        let len = self.map.len();
+        loop {
+            let prefix = self.index().ascii_prefix;
+            if prefix == len {
+                return Ok(true);
+            }
+
+            let end = (prefix + SCAN_STEP).min(len);
+            let other = self.map.with_bytes(prefix..end, |bytes| {
+                bytes.iter().position(|byte| !byte.is_ascii())
+            })?;
+            let mut index = self.index_mut();
+            index.ascii_prefix = index
+                .ascii_prefix
+                .max(other.map_or(end, |other| prefix + other));
+            if other.is_some() {
+                return Ok(false);
+            }
+        }
+    }
+
+    /// Returns how many bytes from the start of the file are known to be ASCII so far
+    pub fn ascii_prefix_len(&self) -> usize {
+        self.index().ascii_prefix
+    }
+
+    /// Returns how many checkpoints have been recorded so far
+    pub fn checkpoint_count(&self) -> usize {
+        self.index().checkpoints.len()
+    }
+
+    /// Throws away everything that has been indexed, including the char map, to give back the memory.
+    /// The file is indexed again as lookups need it.
+    pub fn clear_index(&self) {
+        let mut index = self.index_mut();
+        index.truncate_lines(1);
+        index.char_map = None;
+        index.ascii_prefix = 0;
+    }
+
+    /// Forgets the starts of every line after the first `lines`, along with any checkpoints past them,
+    /// to give back the memory they take up. The rest of the file is indexed again as lookups need it.
+    pub fn shrink_index_to(&self, lines: usize) {
+        self.index_mut().truncate_lines(lines);
+    }
+
+    /// Returns roughly how many bytes the index takes up, counting the line starts that haven't been evicted,
+    /// checkpoints and the char map
+    pub fn index_memory_bytes(&self) -> usize {
+        self.index().memory_bytes()
+    }
+
+    /// Returns how far the file has been indexed and how much is cached, without indexing any more of it
+    pub fn index_stats(&self) -> IndexProgress {
+        let index = self.index();
+        let indexed = index.end_position.unwrap_or_else(|| index.frontier());
+        IndexProgress {
+            bytes: self.map.len(),
+            indexed_bytes: indexed.byte_position,
+            indexed_chars: indexed.char_position,
+            lines: index.line_ending_positions.len(),
+            checkpoints: index.checkpoints.len(),
+            line_memory: index.line_ending_positions.memory(),
+            complete: index.end_position.is_some(),
+            char_map: index.char_map.is_some(),
+        }
+    }
+
+    /// Returns the text of a line without its trailing newline.
+    /// A newline at the very end of the file starts one final empty line.
+    /// Returns an error if the line is out of bounds or isn't valid utf8.
+    pub fn line_at(&self, line_number: usize) -> Result<&str, Error> {
+        let (text, _) = self.line_parts(line_number)?;
+        let start = CharPosition {
+            byte_position: text.start,
+            char_position: self
+                .with_lines(|lines| lines.at(line_number))?
+                .char_position,
+        };
+        decode_str(self.map.bytes(text)?, start)
+    }
+
+    /// Returns the text of a range of lines as one slice of the underlying map,
+    /// including the terminators between them and the one ending the last line if it has one.
+    /// Returns an error if the range is out of bounds or isn't valid utf8.
+    pub fn lines_range(&self, range: Range<usize>) -> Result<&str, Error> {
+        if range.start > range.end {
+            return Err(Error::OutOfBounds {
+                index: range.start,
+                len: range.end,
+            });
+        }
+
+        if range.is_empty() {
+            // An empty range can start just past the last line, like a character range
+            self.index_lines_until(range.start)?;
+            let lines = self.index().line_ending_positions.len();
+            if range.start > lines {
+                return Err(Error::OutOfBounds {
+                    index: range.start,
+                    len: lines,
+                });
+            }
+            return Ok("");
+        }
+
+        let first = self.line_span(range.start)?;
+        let last = self.line_span(range.end - 1)?;
+        let start = self.with_lines(|lines| lines.at(range.start))?;
+        decode_str(self.map.bytes(first.start..last.end)?, start)
+    }
+
+    /// Returns an iterator over the lines of the file along with their numbers and where they start,
+    /// indexing them as it goes.
+    /// The iterator stops early at a line that isn't valid utf8.
+    pub fn lines(&self) -> impl Iterator<Item = LineRef<'_>> + '_ {
+        (0..).map_while(move |line| self.line_ref(line).ok())
+    }
+
+    /// Borrows a line along with its number and where it starts
+    fn line_ref(&self, line: usize) -> Result<LineRef<'_>, Error> {
+        let text = self.line_at(line)?;
+        let start = self.with_lines(|lines| lines.at(line))?;
+        Ok(LineRef { line, start, text })
+    }
+
+    /// Returns the terminator that ends a line, or `None` for the last line in the file.
+    /// Returns an error if the line is out of bounds or the file isn't valid utf8.
+    pub fn line_terminator(&self, line_number: usize) -> Result<Option<LineTerminator>, Error> {
+        let (_, terminator) = self.line_parts(line_number)?;
+        Ok(terminator)
+    }
+
+    /// Returns the number of lines in the file.
+    /// The first call indexes the rest of the file, after that the count is cached.
+    /// Returns an error if the file isn't valid utf8.
+    pub fn line_count(&self) -> Result<usize, Error> {
+        self.index_lines_until(usize::MAX)?;
+        Ok(self.index().line_ending_positions.len())
+    }
+
+    /// Returns the length of a line in characters, not counting its trailing newline.
+    /// Returns an error if the line is out of bounds or the file isn't valid utf8.
+    pub fn line_len(&self, line_number: usize) -> Result<usize, Error> {
+        let (_, terminator) = self.line_parts(line_number)?;
+
+        let start = self
+            .with_lines(|lines| lines.at(line_number))?
+            .char_position;
+        let end = match self.with_lines(|lines| lines.get(line_number + 1))? {
+            // Leave off the terminator before the next line
+            Some(next) => next.char_position - terminator.map_or(0, LineTerminator::char_len),
+            // The last line is only found by indexing to the end of the file
+            None => self.index().end_position.unwrap().char_position,
+        };
+
+        Ok(end - start)
+    }
+
+    /// Returns the length of a line in bytes, not counting its trailing newline.
+    /// Returns an error if the line is out of bounds or the file isn't valid utf8.
+    pub fn line_byte_len(&self, line_number: usize) -> Result<usize, Error> {
+        let (text, _) = self.line_parts(line_number)?;
+        Ok(text.len())
+    }
+
+    /// Returns the position of the character containing the given byte offset,
+    /// snapping back to the start of the character if the offset is in the middle of one.
+    /// The offset just past the end of the file resolves to the end of the file.
+    /// Returns an error if the offset is out of bounds or the line isn't valid utf8.
+    pub fn char_index_at_byte(&self, byte: usize) -> Result<CharPosition, Error> {
+        self.check_fresh()?;
+        if byte > self.map.len() {
+            return Err(Error::OutOfBounds {
+                index: byte,
+                len: self.map.len(),
+            });
+        }
+
+        // Every byte of the ASCII prefix is a character of its own
+        if byte < self.index().ascii_prefix {
+            return Ok(CharPosition {
+                byte_position: byte,
+                char_position: byte,
+            });
+        }
+
+        // Step back to the first byte of the character
+        let byte = if byte < self.map.len() {
+            let window_start = byte.saturating_sub(3);
+            window_start
+                + self.map.with_bytes(window_start..byte + 1, |window| {
+                    previous_char_start(window, window.len()).unwrap_or(window.len() - 1)
+                })?
+        } else {
+            byte
+        };
+
+        if let Some(char_map) = &self.index().char_map {
+            return Ok(CharPosition {
+                byte_position: byte,
+                char_position: char_map.rank(&self.map, byte)?,
+            });
+        }
+
+        // Make sure the line containing the byte is cached
+        self.index_lines_while(|_, start| start.byte_position <= byte)?;
+
+        // Count from whichever line start or checkpoint is closest before the byte
+        let line_start = self.index().nearest_cached_byte(byte);
+
+        let chars = self
+            .map
+            .with_bytes(line_start.byte_position..byte, |bytes| {
+                self.decode_policy.count_chars(bytes, line_start)
+            })??;
+
+        Ok(CharPosition {
+            byte_position: byte,
+            char_position: line_start.char_position + chars,
+        })
+    }
+
+    /// Decodes the character that starts at a byte offset, returning it along with where it starts.
+    /// An offset in the middle of a character snaps forward to the end of it, so the character after
+    /// is returned, and there's no character after the end of the file.
+    /// Nothing is indexed, so this is cheap anywhere in the file.
+    /// Returns an error if the offset is out of bounds or the character isn't valid utf8.
+    pub fn char_after_byte(&self, byte: usize) -> Result<Option<(char, usize)>, Error> {
+        let around = self.char_around_byte(byte)?;
+        let start = match around.start < byte {
+            true => around.end,
+            false => byte,
+        };
+        if start == self.map.len() {
+            return Ok(None);
+        }
+
+        let range = start..(start + 4).min(self.map.len());
+        let next = self.map.with_bytes(range.clone(), |window| {
+            self.decode_policy.decode_next(window)
+        })?;
+        match next {
+            Some((c, _)) => Ok(Some((c, start))),
+            // Counting the characters before it says which one isn't valid
+            None => {
+                let position = self.char_index_at_byte(start)?;
+                self.map
+                    .with_bytes(range, |window| decode_str(window, position).map(|_| None))?
+            }
+        }
+    }
+
+    /// Decodes the character that ends at a byte offset, returning it along with where it starts.
+    /// An offset in the middle of a character snaps back to the start of it, so the character before
+    /// is returned, and there's no character before the start of the file.
+    /// Nothing is indexed, so this is cheap anywhere in the file.
+    /// Returns an error if the offset is out of bounds or the character isn't valid utf8.
+    pub fn char_before_byte(&self, byte: usize) -> Result<Option<(char, usize)>, Error> {
+        let end = self.char_around_byte(byte)?.start;
+        if end == 0 {
+            return Ok(None);
+        }
+
+        let window_start = end.saturating_sub(4);
+        let previous = self.map.with_bytes(window_start..end, |window| {
+            self.decode_policy.decode_previous(window)
+        })?;
+        match previous {
+            Some((c, start)) => Ok(Some((c, window_start + start))),
+            None => {
+                let start = self.char_index_at_byte(end - 1)?;
+                self.map.with_bytes(start.byte_position..end, |bytes| {
+                    decode_str(bytes, start).map(|_| None)
+                })?
+            }
+        }
+    }
+
+    /// Finds the bytes of the valid character a byte offset is in the middle of,
+    /// or an empty range at the offset if it's at the start of a character or isn't in one
+    fn char_around_byte(&self, byte: usize) -> Result<Range<usize>, Error> {
+        self.check_fresh()?;
+        let len = self.map.len();
+        if byte > len {
+            return Err(Error::OutOfBounds { index: byte, len });
+        }
+
+        let window_start = byte.saturating_sub(3);
+        let offset = byte - window_start;
+        let around = self
+            .map
+            .with_bytes(window_start..(byte + 4).min(len), |window| {
+                if byte == len || !is_continuation_byte(window[offset]) {
+                    return None;
+                }
+                previous_char_start(window, offset).and_then(|start| {
+                    let (_, width) = decode_char(&window[start..])?;
+                    (start + width > offset)
+                        .then_some(window_start + start..window_start + start + width)
+                })
+            })?;
+        Ok(around.unwrap_or(byte..byte))
+    }
+
+    /// Returns the byte offset of the character at the given index without decoding any characters,
+    /// just stepping over the utf8 lengths from the nearest cached line start.
+    /// The index one past the last character resolves to the length of the file.
+    /// Returns an error if the index is out of bounds.
+    pub fn byte_offset_of(&self, index: usize) -> Result<usize, Error> {
+        self.check_fresh()?;
+        let end_position = self.index().end_position;
+        match end_position {
+            Some(end) if index == end.char_position => return Ok(end.byte_position),
+            Some(end) if index > end.char_position => {
+                return Err(Error::OutOfBounds {
+                    index,
+                    len: end.char_position,
+                })
+            }
+            _ => {}
+        }
+
+        if index < self.index().ascii_prefix {
+            return Ok(index);
+        }
+
+        // Replacements can cover several bytes, so the lengths can't be taken from the first byte
+        if self.decode_policy != DecodePolicy::Strict || self.has_char_map() {
+            return Ok(self.position_at(index)?.byte_position);
+        }
+
+        let (start, next) = self.index().nearest_cached(index);
+
+        // Only lines past the end of the cache need to be recorded as we go,
+        // and we can only spot terminators without decoding when they are all single bytes
+        let extending = next.is_none() && self.line_endings.is_ascii();
+
+        let len = self.map.len();
+        let mut position = start;
+        let mut char_start = start.byte_position;
+        while position.char_position < index {
+            if position.byte_position >= len {
+                return Err(Error::OutOfBounds {
+                    index,
+                    len: position.char_position,
+                });
+            }
+
+            // Step through a window at a time so only that much is read, with a byte past it
+            // to see whether a \r is followed by a \n
+            let window_start = position.byte_position;
+            let window_end = (window_start + SCAN_STEP).min(len);
+            let mut lines = Vec::new();
+            let mut checkpoints = Vec::new();
+            let range = window_start..(window_end + 1).min(len);
+            self.map.with_bytes(range, |bytes| {
+                while position.char_position < index && position.byte_position < window_end {
+                    let offset = position.byte_position - window_start;
+                    let byte = bytes[offset];
+                    let width = utf8_char_width(byte);
+                    char_start = position.byte_position;
+                    position = CharPosition {
+                        byte_position: position.byte_position + width,
+                        char_position: position.char_position + 1,
+                    };
+
+                    // The last character might run past the end of the file so don't record anything after it
+                    if !extending || position.byte_position > len {
+                        continue;
+                    }
+                    let next_byte = bytes.get(offset + width).copied();
+                    if byte.is_ascii() && self.line_endings.ends_line(byte as char, next_byte) {
+                        lines.push(position);
+                    }
+                    if self
+                        .checkpoint_interval
+                        .is_some_and(|interval| position.char_position.is_multiple_of(interval))
+                    {
+                        checkpoints.push(position);
+                    }
+                }
+            })?;
+
+            // The lock is only held to record what was found, so other lookups can carry on meanwhile
+            if !lines.is_empty() || !checkpoints.is_empty() {
+                let mut cache = self.index_mut();
+                for line in lines {
+                    cache.push_line_start(line);
+                }
+                for checkpoint in checkpoints {
+                    cache.record_checkpoint(self.checkpoint_interval, checkpoint);
+                }
+            }
+        }
+
+        // The last character might claim to be longer than what is left of the file
+        if position.byte_position > len {
+            return Err(Error::InvalidUtf8 {
+                byte_offset: char_start,
+                char_index: index - 1,
+                context: self.map.with_bytes(char_start..len, <[u8]>::to_vec)?,
+            });
+        }
+
+        Ok(position.byte_position)
+    }
+
+    /// Returns the position of the character at a zero-based line and column, counted in characters.
+    /// The column just past the last character of a line is allowed and resolves to the line's newline
+    /// (or the end of the file on the last line), like a cursor at the end of the line.
+    /// Any column further than that is out of bounds rather than being clamped or wrapping onto the next line.
+    pub fn position_of(&self, line: usize, column: usize) -> Result<CharPosition, Error> {
+        let text = self.line_at(line)?;
+
+        let byte_offset = match text.char_indices().nth(column) {
+            Some((byte_offset, _)) => byte_offset,
+            None if text.chars().count() == column => text.len(),
+            None => {
+                return Err(Error::OutOfBounds {
+                    index: column,
+                    len: text.chars().count(),
+                })
+            }
+        };
+
+        let line_start = self.with_lines(|lines| lines.at(line))?;
+        Ok(CharPosition {
+            byte_position: line_start.byte_position + byte_offset,
+            char_position: line_start.char_position + column,
+        })
+    }
+
+    /// Returns the zero-based line and column, counted in characters, of the character at the given index.
+    /// The index one past the last character resolves to the end of the last line.
+    /// Returns an error if the index is out of bounds.
+    pub fn line_col_of(&self, index: usize) -> Result<(usize, usize), Error> {
+        let (line, _) = self.line_of(index)?;
+        let line_start = self.with_lines(|lines| lines.at(line))?;
+        Ok((line, index - line_start.char_position))
+    }
+
+    /// Returns the character at the given index.
+    /// Returns an error if the index is out of bounds or the line it's on isn't valid utf8.
+    pub fn unicode_at(&self, index: usize) -> Result<char, Error> {
+        let position = self.position_at(index)?;
+        self.char_at(position)
+    }
+
+    /// Returns the character at the given index, or `None` if it's out of bounds or can't be decoded.
+    pub fn get(&self, index: usize) -> Option<char> {
+        self.unicode_at(index).ok()
+    }
+
+    /// Returns the character at the given index along with how many bytes of utf8 it takes up in the file,
+    /// for working out how far an edit moves the bytes after it.
+    /// An invalid sequence that's replaced with U+FFFD is as long as the bytes it replaces.
+    /// Returns an error if the index is out of bounds.
+    pub fn char_and_len(&self, index: usize) -> Result<(char, usize), Error> {
+        let position = self.position_at(index)?;
+        self.char_and_width_at(position)
+    }
+
+    /// Returns the character at the given index along with where it is, for highlighting or slicing
+    /// around it without looking it up again.
+    /// Returns an error if the index is out of bounds.
+    pub fn char_with_position(&self, index: usize) -> Result<(char, CharPosition), Error> {
+        let position = self.position_at(index)?;
+        Ok((self.char_at(position)?, position))
+    }
+
+    /// Returns the bytes of the character at the given index as a slice of the underlying map.
+    /// Invalid bytes that decode lossily to U+FFFD have nothing to borrow, so they give a static one instead.
+    fn char_str_at(&self, index: usize) -> Result<&str, Error> {
+        let position = self.position_at(index)?;
+        let c = self.char_at(position)?;
+
+        let end = (position.byte_position + c.len_utf8()).min(self.map.len());
+        match str_from_utf8(self.map.bytes(position.byte_position..end)?) {
+            Ok(text) if text.starts_with(c) => Ok(text),
+            _ => Ok("\u{FFFD}"),
+        }
+    }
+
+    /// Returns the characters at many indices at once, in the same order as the indices.
+    /// The indices are looked up in sorted order so the file is only walked forwards,
+    /// and nearby ones are stepped to from the one before instead of searching the cache again.
+    /// Each index gets its own result, so one being out of bounds doesn't stop the rest.
+    pub fn unicode_at_many(&self, indices: &[usize]) -> Vec<Result<char, Error>> {
+        let mut order: Vec<usize> = (0..indices.len()).collect();
+        order.sort_unstable_by_key(|&slot| indices[slot]);
+
+        let mut results: Vec<Option<Result<char, Error>>> =
+            (0..indices.len()).map(|_| None).collect();
+        let mut previous: Option<CharPosition> = None;
+        for slot in order {
+            let index = indices[slot];
+            let walked = previous
+                .filter(|previous| index - previous.char_position <= BATCH_WALK)
+                .and_then(|previous| self.walk_forward(previous, index - previous.char_position));
+
+            // Falling back to a normal lookup also gives the right error if walking failed
+            let position = match walked {
+                Some(position) => Ok(position),
+                None => self.position_at(index),
+            };
+            results[slot] = Some(match position {
+                Ok(position) => {
+                    previous = Some(position);
+                    self.char_at(position)
+                }
+                Err(e) => Err(e),
+            });
+        }
+
+        // Every slot was in the order once
+        results.into_iter().map(Option::unwrap).collect()
+    }
+
+    /// Steps forward a character at a time from a known position to the character `n` after it.
+    /// Returns `None` if the file ends or can't be decoded before getting there.
+    fn walk_forward(&self, from: CharPosition, n: usize) -> Option<CharPosition> {
+        let mut byte_position = from.byte_position;
+        for _ in 0..n {
+            let end = (byte_position + 4).min(self.map.len());
+            let (_, width) = self
+                .map
+                .with_bytes(byte_position..end, |bytes| {
+                    self.decode_policy.decode_next(bytes)
+                })
+                .ok()??;
+            byte_position += width;
+        }
+
+        Some(CharPosition {
+            byte_position,
+            char_position: from.char_position + n,
+        })
+    }
+
+    /// Returns the text between two character indices as a slice of the underlying map.
+    /// Returns an error if the range is out of bounds or isn't valid utf8.
+    pub fn str_range(&self, range: Range<usize>) -> Result<&str, Error> {
+        let (start, end) = self.range_positions(range)?;
+        decode_str(
+            self.map.bytes(start.byte_position..end.byte_position)?,
+            start,
+        )
+    }
+
+    /// Returns the raw bytes in a range of byte offsets, whether or not they're valid utf8,
+    /// for handing to something that works on bytes like a checksum or another parser.
+    /// Returns an error if the range is out of bounds.
+    pub fn bytes_range(&self, range: Range<usize>) -> Result<&[u8], Error> {
+        self.check_fresh()?;
+        let len = self.map.len();
+        if range.start > range.end || range.end > len {
+            return Err(Error::OutOfBounds {
+                index: range.end.max(range.start),
+                len,
+            });
+        }
+        Ok(self.map.bytes(range)?)
+    }
+
+    /// Returns the byte at the given byte offset.
+    /// Returns an error if the offset is out of bounds.
+    pub fn byte_at(&self, offset: usize) -> Result<u8, Error> {
+        let len = self.map.len();
+        match offset {
+            offset if offset < len => Ok(self.bytes_range(offset..offset + 1)?[0]),
+            _ => Err(Error::OutOfBounds { index: offset, len }),
+        }
+    }
+
+    /// Returns a copy of the text between two character indices.
+    /// Invalid bytes are replaced with U+FFFD if the file decodes lossily.
+    /// Returns an error if the range is out of bounds.
+    pub fn string_range(&self, range: Range<usize>) -> Result<String, Error> {
+        let mut text = String::new();
+        self.write_range_to(range, &mut text)?;
+        Ok(text)
+    }
+
+    /// Writes the text between two character indices to `out` without copying it first,
+    /// replacing invalid bytes with U+FFFD if the file decodes lossily.
+    /// Returns an error if the range is out of bounds or `out` fails.
+    pub fn write_range_to(
+        &self,
+        range: Range<usize>,
+        mut out: impl fmt::Write,
+    ) -> Result<(), Error> {
+        let (start, end) = self.range_positions(range)?;
+        self.read_stretches(start, end.byte_position, |bytes, position| {
+            let text = self.decode_policy.decode(bytes, position)?;
+            out.write_str(&text)
+                .map_err(|e| Error::Io(io::Error::other(e)))?;
+            Ok(text.chars().count())
+        })?;
+        Ok(())
+    }
+
+    /// Returns a reader over the utf8 bytes of the text between two character indices,
+    /// for streaming it somewhere without copying it into a `String` first.
+    /// It reads straight from the file, so its `BufRead::lines` and `read_line` don't copy anything more
+    /// than the lines themselves, though they only split lines at \n whatever the line endings are.
+    /// Seeking is in bytes from the start of the range.
+    /// The text is checked up front, and only copied if there are invalid bytes to replace when decoding lossily.
+    /// Returns an error if the range is out of bounds or isn't valid utf8.
+    pub fn reader(&self, range: Range<usize>) -> Result<impl BufRead + Seek + '_, Error> {
+        let (start, end) = self.range_positions(range)?;
+        self.reader_between(start, end.byte_position)
+    }
+
+    /// Returns a reader over the utf8 bytes of the whole file, see `reader`.
+    /// Returns an error if the file isn't valid utf8.
+    pub fn file_reader(&self) -> Result<impl BufRead + Seek + '_, Error> {
+        let start = CharPosition {
+            byte_position: 0,
+            char_position: 0,
+        };
+        self.reader_between(start, self.map.len())
+    }
+
+    /// Checks the bytes from a position up to a byte offset, and returns a reader over them
+    fn reader_between(
+        &self,
+        start: CharPosition,
+        end: usize,
+    ) -> Result<io::Cursor<Cow<'_, [u8]>>, Error> {
+        let bytes = self.map.bytes(start.byte_position..end)?;
+        let text = match self.decode_policy.decode(bytes, start)? {
+            Cow::Borrowed(text) => Cow::Borrowed(text.as_bytes()),
+            Cow::Owned(text) => Cow::Owned(text.into_bytes()),
+        };
+
+        Ok(io::Cursor::new(text))
+    }
+
+    /// Returns a copy of the text around the character at the given index, with up to `before` characters
+    /// before it and `after` characters after it, along with how many characters into the text it is.
+    /// The text is cut short at the start and end of the file rather than failing.
+    /// Invalid bytes are replaced with U+FFFD if the file decodes lossily.
+    /// Returns an error if the index is out of bounds.
+    pub fn chars_around(
+        &self,
+        index: usize,
+        before: usize,
+        after: usize,
+    ) -> Result<(String, usize), Error> {
+        // There has to be a character to be around
+        self.unicode_at(index)?;
+
+        let start = index.saturating_sub(before);
+        let end = match self.position_at(index.saturating_add(after).saturating_add(1)) {
+            Err(Error::OutOfBounds { .. }) => self.char_count()?,
+            found => found?.char_position,
+        };
+
+        Ok((self.string_range(start..end)?, index - start))
+    }
+
+    /// Finds the positions of both ends of a range of character indices.
+    /// Returns an error if the range is backwards or out of bounds.
+    fn range_positions(&self, range: Range<usize>) -> Result<(CharPosition, CharPosition), Error> {
+        if range.start > range.end {
+            return Err(Error::OutOfBounds {
+                index: range.start,
+                len: range.end,
+            });
+        }
+
+        Ok((self.position_at(range.start)?, self.position_at(range.end)?))
+    }
+}
+
+/// Shows how big the file is and how far it's been indexed rather than its contents
+impl<S: TextSource> fmt::Debug for MappedFile<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let stats = self.index_stats();
+        f.debug_struct("MappedFile")
+            .field("bytes", &stats.bytes)
+            .field("source", &self.map.kind())
+            .field("lines", &stats.lines)
+            .field("checkpoints", &stats.checkpoints)
+            .field("indexed_bytes", &stats.indexed_bytes)
+            .field("complete", &stats.complete)
+            .finish()
+    }
+}
+
+/// Indexing gives the text of a single character, since a decoded `char` has nowhere to be borrowed from.
+/// Use `get` or `unicode_at` for the `char` itself.
+/// Panics if the index is out of bounds or the character can't be decoded.
+impl<S: TextSource> ops::Index<usize> for MappedFile<S> {
+    type Output = str;
+
+    fn index(&self, index: usize) -> &str {
+        match self.char_str_at(index) {
+            Ok(text) => text,
+            Err(e) => panic!("{}", e),
+        }
+    }
+}
+
+/// Works out where a stretch of bytes should end so a character isn't cut in half,
+/// given a few bytes past the end of the window to look at unless it's the end of the file
+fn stretch_end(bytes: &[u8], window: usize, at_end: bool) -> usize {
+    if at_end {
+        return window;
+    }
+
+    let mut cut = window;
+    while cut > 0 && is_continuation_byte(bytes[cut]) {
+        cut -= 1;
+    }
+    // Nothing but continuation bytes isn't utf8 anyway, so let validation complain
+    if cut == 0 {
+        window
+    } else {
+        cut
+    }
+}
+
+/// Checks the bytes are valid utf8, using simdutf8 when the `simd` feature is on.
+/// simdutf8 only says whether the bytes are valid, so std is asked again for the details of an error
+fn str_from_utf8(bytes: &[u8]) -> Result<&str, Utf8Error> {
+    #[cfg(feature = "simd")]
+    if let Ok(str) = simdutf8::basic::from_utf8(bytes) {
+        return Ok(str);
+    }
+
+    std::str::from_utf8(bytes)
+}
+
+/// Checks bytes that start at a known position in the file are valid utf8,
+/// with the error saying where in the file the problem is if they aren't
+fn decode_str(bytes: &[u8], start: CharPosition) -> Result<&str, Error> {
+    str_from_utf8(bytes).map_err(|e| Error::invalid_utf8(start, bytes, e))
+}
+
+/// Decodes the first character of some bytes along with its length in bytes
+fn decode_char(bytes: &[u8]) -> Option<(char, usize)> {
+    let width = utf8_char_width(*bytes.first()?);
+    let c = str_from_utf8(bytes.get(..width)?).ok()?.chars().next()?;
+    Some((c, width))
+}
+
+/// Finds the start of the character ending at the given byte position
+/// by stepping back over at most three utf8 continuation bytes
+fn previous_char_start(bytes: &[u8], byte_position: usize) -> Option<usize> {
+    let lower_bound = byte_position.saturating_sub(4);
+    (lower_bound..byte_position)
+        .rev()
+        .find(|&i| !is_continuation_byte(bytes[i]))
+}
+
+/// Checks whether a byte is the continuation of a multibyte utf8 character
+fn is_continuation_byte(byte: u8) -> bool {
+    byte & 0xC0 == 0x80
+}
+
+/// Gets the length in bytes of a utf8 character from its first byte
+fn utf8_char_width(first_byte: u8) -> usize {
+    match first_byte {
+        0x00..=0x7F => 1,
+        0xC0..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF7 => 4,
+        // Not the start of a character, so let the decoder report it
+        _ => 1,
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,130 @@
+/// The set of characters that end a line
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LineEndings {
+    /// Characters that end a line on their own
+    pub terminators: Vec<char>,
+    /// Whether a `\r` directly before a `\n` is part of the same terminator
+    /// rather than ending a line by itself
+    pub crlf: bool,
+}
+
+impl LineEndings {
+    /// Lines end at `\n`, so a `\r` before it is part of the line's text
+    pub fn lf() -> LineEndings {
+        LineEndings {
+            terminators: vec!['\n'],
+            crlf: false,
+        }
+    }
+
+    /// Lines end at `\n` with a `\r` right before it counted as part of the same terminator
+    pub fn crlf() -> LineEndings {
+        LineEndings {
+            terminators: vec!['\n'],
+            crlf: true,
+        }
+    }
+
+    /// Lines end at any of the Unicode line breaks:
+    /// `\n`, `\r`, `\r\n`, U+0085 NEL, U+2028 LINE SEPARATOR and U+2029 PARAGRAPH SEPARATOR
+    pub fn unicode() -> LineEndings {
+        LineEndings {
+            terminators: vec!['\n', '\r', '\u{85}', '\u{2028}', '\u{2029}'],
+            crlf: true,
+        }
+    }
+
+    /// Lines end at any of the given characters
+    pub fn custom(terminators: impl IntoIterator<Item = char>) -> LineEndings {
+        LineEndings {
+            terminators: terminators.into_iter().collect(),
+            crlf: false,
+        }
+    }
+
+    /// Checks whether a character ends a line, given the byte right after it
+    pub(crate) fn ends_line(&self, c: char, next_byte: Option<u8>) -> bool {
+        // The \n after this will end the line instead
+        if self.crlf && c == '\r' && next_byte == Some(b'\n') {
+            return false;
+        }
+
+        self.terminators.contains(&c)
+    }
+
+    /// Works out which terminator ended a line from its last character and the byte before it
+    pub(crate) fn terminator(&self, c: char, previous_byte: Option<u8>) -> LineTerminator {
+        match c {
+            '\n' if self.crlf && previous_byte == Some(b'\r') => LineTerminator::CrLf,
+            '\n' => LineTerminator::Lf,
+            '\r' => LineTerminator::Cr,
+            '\u{85}' => LineTerminator::Nel,
+            '\u{2028}' => LineTerminator::LineSeparator,
+            '\u{2029}' => LineTerminator::ParagraphSeparator,
+            c => LineTerminator::Other(c),
+        }
+    }
+
+    /// Finds the first byte that might end a line, using memchr for up to three terminators.
+    /// Only makes sense when every terminator is a single byte
+    pub(crate) fn find_terminator(&self, bytes: &[u8]) -> Option<usize> {
+        match *self.terminators.as_slice() {
+            [a] => memchr::memchr(a as u8, bytes),
+            [a, b] => memchr::memchr2(a as u8, b as u8, bytes),
+            [a, b, c] => memchr::memchr3(a as u8, b as u8, c as u8, bytes),
+            ref terminators => bytes
+                .iter()
+                .position(|&byte| terminators.contains(&(byte as char))),
+        }
+    }
+
+    /// Checks whether every terminator is a single byte
+    pub(crate) fn is_ascii(&self) -> bool {
+        self.terminators.iter().all(char::is_ascii)
+    }
+}
+
+impl Default for LineEndings {
+    fn default() -> Self {
+        LineEndings::lf()
+    }
+}
+
+/// The terminator at the end of a line
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineTerminator {
+    /// `\n`
+    Lf,
+    /// `\r\n`
+    CrLf,
+    /// `\r` on its own
+    Cr,
+    /// U+0085 NEXT LINE
+    Nel,
+    /// U+2028 LINE SEPARATOR
+    LineSeparator,
+    /// U+2029 PARAGRAPH SEPARATOR
+    ParagraphSeparator,
+    /// Any other character configured as a terminator
+    Other(char),
+}
+
+impl LineTerminator {
+    /// The length of the terminator in characters
+    pub fn char_len(self) -> usize {
+        match self {
+            LineTerminator::CrLf => 2,
+            _ => 1,
+        }
+    }
+
+    /// The length of the terminator in bytes
+    pub fn byte_len(self) -> usize {
+        match self {
+            LineTerminator::Lf | LineTerminator::Cr => 1,
+            LineTerminator::CrLf | LineTerminator::Nel => 2,
+            LineTerminator::LineSeparator | LineTerminator::ParagraphSeparator => 3,
+            LineTerminator::Other(c) => c.len_utf8(),
+        }
+    }
+}
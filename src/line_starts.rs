@@ -0,0 +1,567 @@
+use {
+    crate::CharPosition,
+    std::sync::atomic::{AtomicBool, Ordering},
+};
+
+/// How many line starts share a base position in the compact layout
+const BLOCK_LEN: usize = 64;
+
+/// How many bytes of the file each page of line starts covers
+const PAGE_BYTES: usize = 1024 * 1024;
+
+/// The start of every cached line, in order, split up by which page of the file they're in.
+/// Lookups binary search the pages and then only the lines of one page, however the lines are spread out.
+/// When there's a memory limit, the lines of pages that haven't been used lately are evicted
+/// leaving just where the page's first line starts, and have to be scanned again to be used
+#[derive(Clone, Debug)]
+pub(crate) struct LineStarts {
+    /// Whether new pages store their line starts as deltas
+    compact: bool,
+    /// Every page with at least one line starting in it
+    pages: Vec<Page>,
+    /// The number of lines, evicted or not
+    len: usize,
+    last: CharPosition,
+    /// Roughly how many bytes the pages that haven't been evicted take up
+    memory: usize,
+    /// How many bytes the line starts can take up before pages start being evicted
+    memory_limit: Option<usize>,
+    /// The next page the clock will look at for eviction
+    hand: usize,
+}
+
+/// The line starts within one page of the file
+#[derive(Debug)]
+struct Page {
+    /// The number of the first line starting in the page
+    line: usize,
+    /// Where that line starts
+    start: CharPosition,
+    /// Every line start in the page beginning with that one, unless they've been evicted
+    lines: Option<Layout>,
+    /// Whether the page has been used since the clock last passed it
+    used: AtomicBool,
+}
+
+impl Clone for Page {
+    fn clone(&self) -> Self {
+        Page {
+            line: self.line,
+            start: self.start,
+            lines: self.lines.clone(),
+            used: AtomicBool::new(self.used.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// The page holding the lines that were needed has been evicted and has to be scanned again
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Evicted(pub(crate) usize);
+
+/// How the line starts of a page are stored
+#[derive(Clone, Debug)]
+enum Layout {
+    /// Every line start stored in full, two `usize` each
+    Plain(Vec<CharPosition>),
+    /// Line starts stored as deltas from the one before
+    Compact(CompactLineStarts),
+}
+
+/// Line starts split into blocks that each begin with a full position,
+/// followed by varint deltas from one line start to the next.
+/// Lines are usually short so most deltas fit in a byte or two, a fraction of the size of a `CharPosition`
+#[derive(Clone, Debug, Default)]
+struct CompactLineStarts {
+    /// The first line start in each block
+    bases: Vec<CharPosition>,
+    /// Where each block's deltas start in `deltas`
+    block_starts: Vec<usize>,
+    /// The byte and then char delta of every line start that isn't a base, as LEB128 varints
+    deltas: Vec<u8>,
+    /// The last line start, so appending doesn't have to decode anything
+    last: Option<CharPosition>,
+    len: usize,
+}
+
+impl LineStarts {
+    /// Creates the cache holding just the start of the file
+    pub(crate) fn new(compact: bool) -> LineStarts {
+        let start = CharPosition {
+            byte_position: 0,
+            char_position: 0,
+        };
+        let mut line_starts = LineStarts {
+            compact,
+            pages: Vec::new(),
+            len: 0,
+            last: start,
+            memory: 0,
+            memory_limit: None,
+            hand: 0,
+        };
+        line_starts.push(start);
+        line_starts
+    }
+
+    /// Checks whether new line starts are stored as deltas
+    pub(crate) fn is_compact(&self) -> bool {
+        self.compact
+    }
+
+    /// Changes how line starts are stored, converting the pages that haven't been evicted
+    pub(crate) fn set_compact(&mut self, compact: bool) {
+        self.compact = compact;
+        for page in &mut self.pages {
+            if let Some(lines) = &page.lines {
+                if matches!(lines, Layout::Compact(_)) != compact {
+                    let mut converted = Layout::new(compact);
+                    for position in lines.iter() {
+                        converted.push(position);
+                    }
+                    page.lines = Some(converted);
+                }
+            }
+        }
+        self.recount_memory();
+    }
+
+    /// Sets how many bytes the line starts can take up, evicting pages straight away if they're over
+    pub(crate) fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.memory_limit = limit;
+        self.evict(None);
+    }
+
+    pub(crate) fn memory_limit(&self) -> Option<usize> {
+        self.memory_limit
+    }
+
+    pub(crate) fn memory(&self) -> usize {
+        self.memory
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn last(&self) -> CharPosition {
+        self.last
+    }
+
+    /// Gets the start of a line, or `None` if it isn't cached
+    pub(crate) fn get(&self, line: usize) -> Result<Option<CharPosition>, Evicted> {
+        if line >= self.len {
+            return Ok(None);
+        }
+
+        let page = self.pages.partition_point(|page| page.line <= line) - 1;
+        let lines = self.resident(page)?;
+        Ok(lines.get(line - self.pages[page].line))
+    }
+
+    /// Gets the start of a line that is known to be cached, panicking like indexing if it isn't
+    pub(crate) fn at(&self, line: usize) -> Result<CharPosition, Evicted> {
+        match self.get(line)? {
+            Some(position) => Ok(position),
+            None => panic!(
+                "line {} is out of bounds for {} cached lines",
+                line, self.len
+            ),
+        }
+    }
+
+    /// Appends a line start, which has to come after the last one
+    pub(crate) fn push(&mut self, position: CharPosition) {
+        let page_number = position.byte_position / PAGE_BYTES;
+        let new_page = match self.pages.last() {
+            Some(page) => page.start.byte_position / PAGE_BYTES != page_number,
+            None => true,
+        };
+        if new_page {
+            self.pages.push(Page {
+                line: self.len,
+                start: position,
+                lines: Some(Layout::new(self.compact)),
+                used: AtomicBool::new(true),
+            });
+        }
+
+        // The last page is never evicted since it's the one being added to
+        let lines = self.pages.last_mut().unwrap().lines.as_mut().unwrap();
+        self.memory -= lines.memory_bytes();
+        lines.push(position);
+        self.memory += lines.memory_bytes();
+
+        self.len += 1;
+        self.last = position;
+        self.evict(None);
+    }
+
+    /// Shortens the cache to at most the first `len` line starts.
+    /// If the page the new last line is in has been evicted only its first line is kept
+    pub(crate) fn truncate(&mut self, len: usize) {
+        if len >= self.len {
+            return;
+        }
+
+        let pages = self.pages.partition_point(|page| page.line < len);
+        self.pages.truncate(pages.max(1));
+        let page = self.pages.last_mut().unwrap();
+        match &mut page.lines {
+            Some(lines) => lines.truncate(len - page.line),
+            None => {
+                let mut lines = Layout::new(self.compact);
+                lines.push(page.start);
+                page.lines = Some(lines);
+            }
+        }
+
+        // Shortening the cache is usually to give back memory, so don't keep hold of the space
+        self.pages.shrink_to_fit();
+        let page = self.pages.last_mut().unwrap();
+        page.lines.as_mut().unwrap().shrink_to_fit();
+
+        let lines = page.lines.as_ref().unwrap();
+        self.len = page.line + lines.len();
+        self.last = lines.last().unwrap();
+        self.hand = 0;
+        self.recount_memory();
+    }
+
+    /// Returns how many pages the line starts are split into
+    pub(crate) fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Iterates over the line starts in a page, as long as they haven't been evicted
+    pub(crate) fn page(
+        &self,
+        page: usize,
+    ) -> Result<impl Iterator<Item = CharPosition> + '_, Evicted> {
+        Ok(self.resident(page)?.iter())
+    }
+
+    /// Returns how many line starts from the beginning satisfy the predicate,
+    /// which has to hold for some prefix of them and not after, like `slice::partition_point`
+    pub(crate) fn partition_point(
+        &self,
+        pred: impl Fn(&CharPosition) -> bool,
+    ) -> Result<usize, Evicted> {
+        // Find the page, then search its lines
+        let page = self.pages.partition_point(|page| pred(&page.start));
+        match page.checked_sub(1) {
+            Some(page) => Ok(self.pages[page].line + self.resident(page)?.partition_point(pred)),
+            // Not even the first line satisfies it
+            None => Ok(0),
+        }
+    }
+
+    /// Finds the last line start that satisfies the predicate and the first one after it that doesn't.
+    /// If the page they're in has been evicted, the start of the page and the start of the next page
+    /// are given instead, which are still line starts on either side
+    pub(crate) fn around(
+        &self,
+        pred: impl Fn(&CharPosition) -> bool,
+    ) -> (Option<CharPosition>, Option<CharPosition>) {
+        let page = match self.pages.partition_point(|page| pred(&page.start)) {
+            0 => return (None, Some(self.pages[0].start)),
+            after => after - 1,
+        };
+        let next_page = self.pages.get(page + 1).map(|page| page.start);
+
+        match self.resident(page) {
+            Ok(lines) => {
+                let after = lines.partition_point(pred);
+                (lines.get(after - 1), lines.get(after).or(next_page))
+            }
+            Err(_) => (Some(self.pages[page].start), next_page),
+        }
+    }
+
+    /// Returns where an evicted page begins and where the next one does, between which its lines have to be found.
+    /// Returns `None` if the page isn't evicted anymore
+    pub(crate) fn evicted_bounds(&self, page: usize) -> Option<(CharPosition, CharPosition)> {
+        match self.pages.get(page) {
+            Some(Page {
+                lines: None, start, ..
+            }) => Some((*start, self.pages[page + 1].start)),
+            _ => None,
+        }
+    }
+
+    /// Puts back the line starts of an evicted page after they've been found again
+    pub(crate) fn restore(&mut self, page: usize, positions: Vec<CharPosition>) {
+        if self.evicted_bounds(page).is_none() {
+            return;
+        }
+
+        let mut lines = Layout::new(self.compact);
+        for position in positions {
+            lines.push(position);
+        }
+        self.memory += lines.memory_bytes();
+        self.pages[page].lines = Some(lines);
+        self.pages[page].used.store(true, Ordering::Relaxed);
+        // Not the page that was just restored though, or it might never get used
+        self.evict(Some(page));
+    }
+
+    /// Gets the lines of a page if they haven't been evicted, marking it as used
+    fn resident(&self, page: usize) -> Result<&Layout, Evicted> {
+        let page_lines = self.pages[page].lines.as_ref().ok_or(Evicted(page))?;
+        self.pages[page].used.store(true, Ordering::Relaxed);
+        Ok(page_lines)
+    }
+
+    /// Evicts pages until the line starts fit in the memory limit, going round the pages like a clock
+    /// and giving pages that have been used since it last came round another chance
+    fn evict(&mut self, keep: Option<usize>) {
+        let limit = match self.memory_limit {
+            Some(limit) => limit,
+            None => return,
+        };
+        let candidates = self.pages.len() - 1;
+
+        // Twice round is enough to clear every page's used flag
+        let mut checked = 0;
+        while self.memory > limit && checked < 2 * candidates {
+            self.hand %= candidates;
+            let page = &mut self.pages[self.hand];
+            if let (Some(lines), false) = (&page.lines, keep == Some(self.hand)) {
+                if !page.used.swap(false, Ordering::Relaxed) {
+                    self.memory -= lines.memory_bytes();
+                    page.lines = None;
+                }
+            }
+            self.hand += 1;
+            checked += 1;
+        }
+    }
+
+    fn recount_memory(&mut self) {
+        self.memory = self
+            .pages
+            .iter()
+            .filter_map(|page| page.lines.as_ref())
+            .map(Layout::memory_bytes)
+            .sum();
+    }
+}
+
+impl Extend<CharPosition> for LineStarts {
+    fn extend<I: IntoIterator<Item = CharPosition>>(&mut self, positions: I) {
+        for position in positions {
+            self.push(position);
+        }
+    }
+}
+
+impl Layout {
+    fn new(compact: bool) -> Layout {
+        if compact {
+            Layout::Compact(CompactLineStarts::default())
+        } else {
+            Layout::Plain(Vec::new())
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Layout::Plain(positions) => positions.len(),
+            Layout::Compact(compact) => compact.len,
+        }
+    }
+
+    fn last(&self) -> Option<CharPosition> {
+        match self {
+            Layout::Plain(positions) => positions.last().cloned(),
+            Layout::Compact(compact) => compact.last,
+        }
+    }
+
+    fn get(&self, line: usize) -> Option<CharPosition> {
+        match self {
+            Layout::Plain(positions) => positions.get(line).cloned(),
+            Layout::Compact(compact) if line < compact.len => {
+                compact.block(line / BLOCK_LEN).nth(line % BLOCK_LEN)
+            }
+            Layout::Compact(_) => None,
+        }
+    }
+
+    fn push(&mut self, position: CharPosition) {
+        match self {
+            Layout::Plain(positions) => positions.push(position),
+            Layout::Compact(compact) => compact.push(position),
+        }
+    }
+
+    fn truncate(&mut self, len: usize) {
+        match self {
+            Layout::Plain(positions) => positions.truncate(len),
+            Layout::Compact(compact) => compact.truncate(len),
+        }
+    }
+
+    fn shrink_to_fit(&mut self) {
+        match self {
+            Layout::Plain(positions) => positions.shrink_to_fit(),
+            Layout::Compact(compact) => {
+                compact.bases.shrink_to_fit();
+                compact.block_starts.shrink_to_fit();
+                compact.deltas.shrink_to_fit();
+            }
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = CharPosition> + '_> {
+        match self {
+            Layout::Plain(positions) => Box::new(positions.iter().cloned()),
+            Layout::Compact(compact) => {
+                Box::new((0..compact.bases.len()).flat_map(|block| compact.block(block)))
+            }
+        }
+    }
+
+    fn partition_point(&self, pred: impl Fn(&CharPosition) -> bool) -> usize {
+        match self {
+            Layout::Plain(positions) => positions.partition_point(pred),
+            Layout::Compact(compact) => {
+                // Find the block by its base, then walk through it
+                match compact.bases.partition_point(&pred).checked_sub(1) {
+                    Some(block) => {
+                        block * BLOCK_LEN + compact.block(block).take_while(|p| pred(p)).count()
+                    }
+                    None => 0,
+                }
+            }
+        }
+    }
+
+    /// Roughly how many bytes the line starts take up, going by how many there are
+    /// rather than what's been allocated so it doesn't depend on how the vectors grew
+    fn memory_bytes(&self) -> usize {
+        let position = std::mem::size_of::<CharPosition>();
+        match self {
+            Layout::Plain(positions) => positions.len() * position,
+            Layout::Compact(compact) => {
+                compact.bases.len() * (position + std::mem::size_of::<usize>())
+                    + compact.deltas.len()
+            }
+        }
+    }
+}
+
+impl CompactLineStarts {
+    fn push(&mut self, position: CharPosition) {
+        match self.last {
+            Some(last) if !self.len.is_multiple_of(BLOCK_LEN) => {
+                write_varint(
+                    &mut self.deltas,
+                    position.byte_position - last.byte_position,
+                );
+                write_varint(
+                    &mut self.deltas,
+                    position.char_position - last.char_position,
+                );
+            }
+            _ => {
+                self.bases.push(position);
+                self.block_starts.push(self.deltas.len());
+            }
+        }
+        self.last = Some(position);
+        self.len += 1;
+    }
+
+    fn truncate(&mut self, len: usize) {
+        if len >= self.len {
+            return;
+        }
+        if len == 0 {
+            *self = CompactLineStarts::default();
+            return;
+        }
+
+        // Keep the whole blocks before the one the new last line start is in,
+        // and as many deltas of that block as are still needed
+        let block = (len - 1) / BLOCK_LEN;
+        let keep = (len - 1) % BLOCK_LEN;
+        let mut decoder = self.block(block);
+        let last = decoder.by_ref().take(keep + 1).last();
+
+        self.deltas.truncate(decoder.offset);
+        self.bases.truncate(block + 1);
+        self.block_starts.truncate(block + 1);
+        self.last = last;
+        self.len = len;
+    }
+
+    /// Iterates over the line starts in a block, beginning with its base
+    fn block(&self, block: usize) -> Decoder<'_> {
+        let end = self
+            .block_starts
+            .get(block + 1)
+            .cloned()
+            .unwrap_or(self.deltas.len());
+        Decoder {
+            deltas: &self.deltas[..end],
+            offset: self.block_starts[block],
+            base: self.bases[block],
+            previous: None,
+        }
+    }
+}
+
+/// Walks through a block adding each delta on to the line start before it
+struct Decoder<'a> {
+    deltas: &'a [u8],
+    /// How far through the deltas the decoder has got
+    offset: usize,
+    base: CharPosition,
+    previous: Option<CharPosition>,
+}
+
+impl Iterator for Decoder<'_> {
+    type Item = CharPosition;
+
+    fn next(&mut self) -> Option<CharPosition> {
+        let next = match self.previous {
+            None => self.base,
+            Some(_) if self.offset >= self.deltas.len() => return None,
+            Some(previous) => {
+                let bytes = read_varint(self.deltas, &mut self.offset);
+                let chars = read_varint(self.deltas, &mut self.offset);
+                CharPosition {
+                    byte_position: previous.byte_position + bytes,
+                    char_position: previous.char_position + chars,
+                }
+            }
+        };
+        self.previous = Some(next);
+        Some(next)
+    }
+}
+
+/// Appends a number seven bits at a time, with the top bit set on every byte but the last
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Reads a number written by `write_varint`, moving the offset past it
+fn read_varint(bytes: &[u8], offset: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = bytes[*offset];
+        *offset += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
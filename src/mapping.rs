@@ -0,0 +1,548 @@
+#[cfg(unix)]
+use memmap2::{Advice, UncheckedAdvice};
+
+use {
+    crate::{
+        error::Truncated,
+        source::{offset_u64, Stamp},
+        Error, TextSource,
+    },
+    memmap2::{Mmap, MmapMut, MmapOptions},
+    std::{
+        fs::{File, Metadata},
+        io::{self, Read, Seek, SeekFrom},
+        ops::{Deref, Range},
+        path::{Path, PathBuf},
+        sync::Arc,
+    },
+};
+
+/// How the bytes of a file are being accessed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// The file is memory mapped
+    Mmap,
+    /// The file was copied into memory of its own when it was opened, see `MappedFileBuilder::private_copy`
+    Copy,
+    /// The file couldn't be mapped so it was read into memory
+    Memory,
+    /// The file is empty, so there is nothing to map
+    Empty,
+}
+
+/// A hint to the OS about how the mapped pages will be accessed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Access {
+    /// No particular pattern, the OS default
+    #[default]
+    Normal,
+    /// Lookups will jump around the file so reading ahead is wasted
+    Random,
+    /// The file will be read from start to end so read ahead aggressively
+    Sequential,
+    /// The pages will be needed soon so start reading them in
+    WillNeed,
+    /// The pages won't be needed for a while so they can be dropped
+    DontNeed,
+}
+
+/// The UTF-8 encoding of U+FEFF, which some tools start files with
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Whether the memory holding a file is backed by huge pages, see `MappedFileBuilder::huge_pages`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HugePages {
+    /// Ordinary pages, because huge ones weren't asked for or couldn't be had
+    No,
+    /// Pages from the pool of huge pages reserved by the system administrator (`MAP_HUGETLB`)
+    Reserved,
+    /// The kernel has been asked to back the memory with transparent huge pages wherever it can (`MADV_HUGEPAGE`)
+    Transparent,
+}
+
+/// How a file is mapped, kept so it can be mapped the same way again
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct MapOptions {
+    /// Whether to fault in every page of the mapping when it's created
+    pub(crate) populate: bool,
+    /// Whether to copy the file into anonymous memory instead of mapping it
+    pub(crate) copy: bool,
+    /// Whether to copy the file into memory backed by huge pages
+    pub(crate) huge_pages: bool,
+    /// Whether to lock the whole file into memory once it's mapped
+    pub(crate) lock: bool,
+    /// Whether a UTF-8 byte order mark at the start of the file is read as text instead of being skipped
+    pub(crate) keep_bom: bool,
+}
+
+/// A file as seen through its memory map
+pub struct Mapping {
+    /// The file that the memory map is mapped to
+    pub file: File,
+    /// The bytes of the file, shared with any snapshots so they can outlive it being mapped again
+    contents: Arc<Contents>,
+    /// What the file looked like when it was opened
+    stamp: Stamp,
+    /// Where the file was opened from, if it was opened by its path
+    path: Option<PathBuf>,
+    /// How the file was mapped
+    options: MapOptions,
+    /// Whether the file's length is checked before reading from the mapping
+    guarded: bool,
+    /// How many bytes of byte order mark are skipped at the start of the file
+    bom: usize,
+}
+
+enum Contents {
+    /// The file is memory mapped
+    Mapped(Mmap),
+    /// The file was copied into an anonymous mapping, so nothing writing to the file can change it
+    Copied {
+        map: Mmap,
+        /// The length of the file, since huge pages round the mapping up
+        len: usize,
+        huge_pages: HugePages,
+    },
+    /// The file couldn't be mapped (pipes, `/proc` files, some network filesystems...)
+    /// so its contents were read into memory instead
+    Memory(Vec<u8>),
+    /// The file is empty, which can't be memory mapped
+    Empty,
+}
+
+impl Mapping {
+    /// Maps a file into memory, falling back to reading it if it can't be mapped
+    /// and skipping the mapping for empty files.
+    /// A UTF-8 byte order mark at the start of the file is skipped, see `has_bom`
+    pub fn new(file: File) -> Result<Mapping, Error> {
+        Mapping::open(file, MapOptions::default())
+    }
+
+    /// Maps a file into memory with the given options
+    pub(crate) fn open(file: File, options: MapOptions) -> Result<Mapping, Error> {
+        let metadata = file.metadata()?;
+        let stamp = Stamp::from_metadata(&metadata);
+        let contents = Mapping::map(&file, &metadata, stamp.len()?, options)?;
+        let mut map = Mapping {
+            file,
+            contents: Arc::new(contents),
+            stamp,
+            path: None,
+            options,
+            guarded: false,
+            bom: 0,
+        };
+        if !options.keep_bom && map.starts_with(BOM) {
+            map.bom = BOM.len();
+        }
+        if options.lock {
+            map.lock(0..map.len())?;
+        }
+        Ok(map)
+    }
+
+    /// Maps a file into memory by its path, remembering the path so `reload` can open it again
+    pub(crate) fn open_path(path: &Path, options: MapOptions) -> Result<Mapping, Error> {
+        let mut map = Mapping::open(File::open(path)?, options)?;
+        map.path = Some(path.to_path_buf());
+        Ok(map)
+    }
+
+    /// Maps the file again to pick up any changes to it.
+    /// If it was opened by its path the path is opened again, so a file that has been replaced,
+    /// like a log that's been rotated, is read instead of the old one.
+    /// The access hint has to be given again.
+    pub fn reload(&mut self) -> Result<(), Error> {
+        let path = match self.path.take() {
+            Some(path) => path,
+            None => return self.remap(),
+        };
+
+        let guarded = self.guarded;
+        *self = Mapping::open(File::open(&path)?, self.options)?;
+        self.path = Some(path);
+        self.guarded = guarded;
+        Ok(())
+    }
+
+    /// Maps the same file again to pick up any changes to it, even if it has been replaced at its path since.
+    /// The access hint has to be given again.
+    pub fn remap(&mut self) -> Result<(), Error> {
+        // Reading the file into memory reads from wherever it was left
+        let mut file = self.file.try_clone()?;
+        file.seek(SeekFrom::Start(0))?;
+
+        let (path, guarded) = (self.path.take(), self.guarded);
+        *self = Mapping::open(file, self.options)?;
+        self.path = path;
+        self.guarded = guarded;
+        Ok(())
+    }
+
+    /// Returns another handle on the same mapping, which keeps it mapped however this one is mapped again.
+    /// Returns an error if the file can't be opened again.
+    pub(crate) fn share(&self) -> io::Result<Mapping> {
+        Ok(Mapping {
+            file: self.file.try_clone()?,
+            contents: Arc::clone(&self.contents),
+            stamp: self.stamp,
+            path: self.path.clone(),
+            options: self.options,
+            guarded: self.guarded,
+            bom: self.bom,
+        })
+    }
+
+    /// Returns whether the file starts with a UTF-8 byte order mark that's being skipped.
+    /// Everything read from the mapping, and every byte offset into it, starts after the mark,
+    /// so the first character is the one after it like other tools count
+    pub fn has_bom(&self) -> bool {
+        self.bom > 0
+    }
+
+    /// Returns how many bytes the whole file is, counting a byte order mark that's skipped
+    #[cfg(feature = "watch")]
+    pub(crate) fn file_len(&self) -> usize {
+        self.bom + self.len()
+    }
+
+    /// Returns the path the file was opened from, if it was opened by its path
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Sets whether the file's length is checked before every read from the mapping,
+    /// so reading bytes another process has truncated away fails with `Error::Truncated`
+    /// instead of the process being killed by `SIGBUS`.
+    /// It costs a `fstat` per read, and the file can still be truncated between the check and the read,
+    /// or under text that has already been borrowed, so it narrows the window rather than closing it.
+    /// Files read into memory can't be truncated under the reader so they're never checked
+    pub fn set_guarded(&mut self, guarded: bool) {
+        self.guarded = guarded;
+    }
+
+    /// Checks whether the file's length is checked before every read from the mapping
+    pub fn is_guarded(&self) -> bool {
+        self.guarded
+    }
+
+    /// Maps the bytes of the file, or reads them if they can't be mapped
+    fn map(
+        file: &File,
+        metadata: &Metadata,
+        len: usize,
+        options: MapOptions,
+    ) -> Result<Contents, Error> {
+        // Files like the ones in /proc claim to be empty but still have contents, so only trust that for regular files
+        // (and only bother trying to map regular files in the first place)
+        if metadata.is_file() && len > 0 {
+            if options.copy || options.huge_pages {
+                return Ok(Mapping::copy(file, len, options.huge_pages)?);
+            }
+
+            let mut mmap_options = MmapOptions::new();
+            if options.populate {
+                mmap_options.populate();
+            }
+
+            return match unsafe { mmap_options.map(file) } {
+                Ok(map) => Ok(Contents::Mapped(map)),
+                // If it can't be read either then the mapping going wrong is the more interesting problem
+                Err(e) => Mapping::read(file).map_err(|_| Error::Mmap(e)),
+            };
+        }
+
+        Ok(Mapping::read(file)?)
+    }
+
+    /// Reads the whole of a file into an anonymous mapping of its own
+    fn copy(mut file: &File, len: usize, huge_pages: bool) -> io::Result<Contents> {
+        let (mut copy, huge_pages) = Mapping::anonymous(len, huge_pages)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.read_exact(&mut copy[..len])?;
+        Ok(Contents::Copied {
+            map: copy.make_read_only()?,
+            len,
+            huge_pages,
+        })
+    }
+
+    /// Creates anonymous memory to copy a file into, backed by huge pages if they're wanted and can be had.
+    /// Reserved huge pages are tried first, then transparent ones
+    #[cfg(target_os = "linux")]
+    fn anonymous(len: usize, huge_pages: bool) -> io::Result<(MmapMut, HugePages)> {
+        if !huge_pages {
+            return Ok((MmapMut::map_anon(len)?, HugePages::No));
+        }
+
+        // Reserved huge pages can only be mapped a whole page at a time
+        if let Some(page) = huge_page_size() {
+            let reserved = MmapOptions::new()
+                .len(len.next_multiple_of(page))
+                .huge(None)
+                .map_anon();
+            if let Ok(map) = reserved {
+                return Ok((map, HugePages::Reserved));
+            }
+        }
+
+        let map = MmapMut::map_anon(len)?;
+        let huge_pages = match map.advise(Advice::HugePage) {
+            Ok(()) => HugePages::Transparent,
+            // The kernel wasn't built with transparent huge pages
+            Err(_) => HugePages::No,
+        };
+        Ok((map, huge_pages))
+    }
+
+    /// Creates anonymous memory to copy a file into. Huge pages are only supported on Linux
+    #[cfg(not(target_os = "linux"))]
+    fn anonymous(len: usize, _huge_pages: bool) -> io::Result<(MmapMut, HugePages)> {
+        Ok((MmapMut::map_anon(len)?, HugePages::No))
+    }
+
+    /// Reads the whole of a file into memory
+    fn read(mut file: &File) -> io::Result<Contents> {
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+
+        if contents.is_empty() {
+            Ok(Contents::Empty)
+        } else {
+            Ok(Contents::Memory(contents))
+        }
+    }
+
+    /// Tells the OS how the mapped pages will be accessed.
+    /// Does nothing if the file isn't memory mapped or the platform has no way to give the hint.
+    /// `Access::DontNeed` is ignored for a private copy, since dropping its pages would throw the copy away
+    pub fn advise(&self, access: Access) -> io::Result<()> {
+        match &*self.contents {
+            #[cfg(unix)]
+            Contents::Copied { .. } if access == Access::DontNeed => Ok(()),
+            #[cfg(unix)]
+            Contents::Mapped(map) | Contents::Copied { map, .. } => map.advise(match access {
+                Access::Normal => Advice::Normal,
+                Access::Random => Advice::Random,
+                Access::Sequential => Advice::Sequential,
+                Access::WillNeed => Advice::WillNeed,
+                Access::DontNeed => {
+                    return unsafe { map.unchecked_advise(UncheckedAdvice::DontNeed) }
+                }
+            }),
+            _ => {
+                let _ = access;
+                Ok(())
+            }
+        }
+    }
+
+    /// Starts reading the pages holding a range of bytes into memory, so they're there by the time they're looked at.
+    /// The range is cut off at the end of the file.
+    /// On platforms where the OS can't be asked to read them ahead the pages are touched one at a time instead,
+    /// which waits for them to be read.
+    /// Does nothing if the file isn't memory mapped
+    pub fn prefetch(&self, range: Range<usize>) -> io::Result<()> {
+        let map = match &*self.contents {
+            Contents::Mapped(map) => map,
+            _ => return Ok(()),
+        };
+        let end = (self.bom + range.end).min(map.len());
+        let start = (self.bom + range.start).min(end);
+        if start == end {
+            return Ok(());
+        }
+
+        #[cfg(unix)]
+        return map.advise_range(Advice::WillNeed, start, end - start);
+
+        #[cfg(not(unix))]
+        {
+            // Pages are at least this big everywhere, so reading a byte from each faults them all in
+            for byte in (start..end).step_by(4096) {
+                std::hint::black_box(map[byte]);
+            }
+            Ok(())
+        }
+    }
+
+    /// Locks the pages holding a range of bytes into memory, so reading them never has to wait for the disk.
+    /// They stay locked until they're unlocked or the file is mapped again or dropped.
+    /// Returns `Error::LockLimit` if it would lock more memory than the process is allowed to,
+    /// `Error::OutOfBounds` if the range goes past the end of the file, or an error if the platform can't lock memory
+    pub fn lock(&self, range: Range<usize>) -> Result<(), Error> {
+        let bytes = self.locked_bytes(range)?;
+        if bytes.is_empty() {
+            return Ok(());
+        }
+
+        #[cfg(unix)]
+        {
+            let (start, len) = page_span(bytes);
+            if unsafe { libc::mlock(start, len) } == 0 {
+                return Ok(());
+            }
+
+            let error = io::Error::last_os_error();
+            match (error.raw_os_error(), memlock_limit()) {
+                // Going over the limit is ENOMEM, or EPERM when the limit is zero on older kernels
+                (Some(libc::ENOMEM | libc::EPERM), Some(limit)) => {
+                    Err(Error::LockLimit { bytes: len, limit })
+                }
+                _ => Err(error.into()),
+            }
+        }
+
+        #[cfg(not(unix))]
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "memory can't be locked on this platform",
+        )
+        .into())
+    }
+
+    /// Unlocks the pages holding a range of bytes so they can be paged out again.
+    /// Returns `Error::OutOfBounds` if the range goes past the end of the file
+    pub fn unlock(&self, range: Range<usize>) -> Result<(), Error> {
+        let bytes = self.locked_bytes(range)?;
+        if bytes.is_empty() {
+            return Ok(());
+        }
+
+        #[cfg(unix)]
+        {
+            let (start, len) = page_span(bytes);
+            if unsafe { libc::munlock(start, len) } != 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+            Ok(())
+        }
+
+        #[cfg(not(unix))]
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "memory can't be locked on this platform",
+        )
+        .into())
+    }
+
+    /// Gets the bytes to lock or unlock, checking the range is within the file
+    fn locked_bytes(&self, range: Range<usize>) -> Result<&[u8], Error> {
+        if range.end > self.len() {
+            return Err(Error::OutOfBounds {
+                index: range.end,
+                len: self.len(),
+            });
+        }
+        Ok(&self[range.start.min(range.end)..range.end])
+    }
+
+    /// Returns whether the memory holding the file is backed by huge pages
+    pub fn huge_pages(&self) -> HugePages {
+        match *self.contents {
+            Contents::Copied { huge_pages, .. } => huge_pages,
+            _ => HugePages::No,
+        }
+    }
+
+    /// Returns how the bytes of the file are being accessed
+    pub fn backend(&self) -> Backend {
+        match *self.contents {
+            Contents::Mapped(_) => Backend::Mmap,
+            Contents::Copied { .. } => Backend::Copy,
+            Contents::Memory(_) => Backend::Memory,
+            Contents::Empty => Backend::Empty,
+        }
+    }
+}
+
+impl Deref for Mapping {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        let bytes = match &*self.contents {
+            Contents::Mapped(map) => map,
+            Contents::Copied { map, len, .. } => &map[..*len],
+            Contents::Memory(contents) => contents,
+            Contents::Empty => &[],
+        };
+        &bytes[self.bom..]
+    }
+}
+
+impl TextSource for Mapping {
+    fn len(&self) -> usize {
+        <[u8]>::len(self)
+    }
+
+    fn bytes(&self, range: Range<usize>) -> io::Result<&[u8]> {
+        if self.guarded
+            && matches!(*self.contents, Contents::Mapped(_))
+            && self.file.metadata()?.len() < offset_u64(self.bom + range.end)
+        {
+            return Err(Truncated::error());
+        }
+        Ok(&self[range])
+    }
+
+    fn kind(&self) -> &'static str {
+        match self.backend() {
+            Backend::Mmap => "mmap",
+            Backend::Copy => "copy",
+            Backend::Memory => "memory",
+            Backend::Empty => "empty",
+        }
+    }
+
+    fn is_stale(&self) -> io::Result<bool> {
+        self.stamp.changed(&self.file)
+    }
+}
+
+/// Reads the size of the system's default huge pages, if it has them
+#[cfg(target_os = "linux")]
+fn huge_page_size() -> Option<usize> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("Hugepagesize:"))?;
+    let kib = line
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<usize>()
+        .ok()?;
+    Some(kib * 1024)
+}
+
+/// Widens some bytes out to the whole pages they're in, since memory is locked a page at a time
+#[cfg(unix)]
+fn page_span(bytes: &[u8]) -> (*const libc::c_void, usize) {
+    let page = match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        size if size > 0 => size as usize,
+        _ => 4096,
+    };
+    let start = bytes.as_ptr() as usize;
+    let aligned = start - start % page;
+    (
+        aligned as *const libc::c_void,
+        bytes.len() + (start - aligned),
+    )
+}
+
+/// Reads how many bytes the process is allowed to lock into memory, if there's a limit
+#[cfg(unix)]
+fn memlock_limit() -> Option<u64> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) } != 0
+        || limit.rlim_cur == libc::RLIM_INFINITY
+    {
+        return None;
+    }
+    // rlim_t isn't a u64 everywhere
+    #[allow(clippy::unnecessary_cast)]
+    Some(limit.rlim_cur as u64)
+}
@@ -0,0 +1,131 @@
+use {
+    crate::{CharPosition, Error, MappedFile, TextSource},
+    nom::{Compare, CompareResult, FindSubstring, Input, Needed, Offset, ParseTo},
+    std::{
+        ops::Range,
+        str::{CharIndices, Chars, FromStr},
+    },
+};
+
+/// Text borrowed from a file for nom parsers to run over without copying it into a `String`.
+/// Like a `&str` it's indexed in bytes, but it remembers where it is in the file
+/// so whatever a parser recognizes can be turned back into character indices
+#[derive(Clone, Copy, Debug)]
+pub struct NomInput<'a> {
+    text: &'a str,
+    /// Where the text starts in the file
+    start: CharPosition,
+}
+
+impl<S: TextSource> MappedFile<S> {
+    /// Borrows the text between two character indices for a nom parser.
+    /// The text is checked up front so parsing never has to.
+    /// Returns an error if the range is out of bounds or isn't valid utf8.
+    pub fn nom_input(&self, range: Range<usize>) -> Result<NomInput<'_>, Error> {
+        let start = self.position_at(range.start)?;
+        Ok(NomInput {
+            text: self.str_range(range)?,
+            start,
+        })
+    }
+}
+
+impl<'a> NomInput<'a> {
+    /// Returns where the text starts in the file
+    pub fn position(&self) -> CharPosition {
+        self.start
+    }
+
+    /// Returns the character indices of the text in the file
+    pub fn char_range(&self) -> Range<usize> {
+        self.start.char_position..self.start.char_position + self.text.chars().count()
+    }
+
+    /// Returns the text itself
+    pub fn as_str(&self) -> &'a str {
+        self.text
+    }
+
+    /// Returns the text from `index` bytes in, working out where that is in the file
+    fn split_off(&self, index: usize) -> NomInput<'a> {
+        NomInput {
+            text: &self.text[index..],
+            start: CharPosition {
+                byte_position: self.start.byte_position + index,
+                char_position: self.start.char_position + self.text[..index].chars().count(),
+            },
+        }
+    }
+}
+
+impl<'a> Input for NomInput<'a> {
+    type Item = char;
+    type Iter = Chars<'a>;
+    type IterIndices = CharIndices<'a>;
+
+    fn input_len(&self) -> usize {
+        self.text.len()
+    }
+
+    fn take(&self, index: usize) -> Self {
+        NomInput {
+            text: &self.text[..index],
+            start: self.start,
+        }
+    }
+
+    fn take_from(&self, index: usize) -> Self {
+        self.split_off(index)
+    }
+
+    fn take_split(&self, index: usize) -> (Self, Self) {
+        (self.take_from(index), self.take(index))
+    }
+
+    fn position<P>(&self, predicate: P) -> Option<usize>
+    where
+        P: Fn(char) -> bool,
+    {
+        self.text.find(predicate)
+    }
+
+    fn iter_elements(&self) -> Chars<'a> {
+        self.text.chars()
+    }
+
+    fn iter_indices(&self) -> CharIndices<'a> {
+        self.text.char_indices()
+    }
+
+    fn slice_index(&self, count: usize) -> Result<usize, Needed> {
+        self.text.slice_index(count)
+    }
+}
+
+impl<'b> Compare<&'b str> for NomInput<'_> {
+    fn compare(&self, t: &'b str) -> CompareResult {
+        self.text.compare(t)
+    }
+
+    fn compare_no_case(&self, t: &'b str) -> CompareResult {
+        self.text.compare_no_case(t)
+    }
+}
+
+impl<'b> FindSubstring<&'b str> for NomInput<'_> {
+    fn find_substring(&self, substr: &'b str) -> Option<usize> {
+        self.text.find_substring(substr)
+    }
+}
+
+impl Offset for NomInput<'_> {
+    fn offset(&self, second: &Self) -> usize {
+        second.start.byte_position - self.start.byte_position
+    }
+}
+
+impl<R: FromStr> ParseTo<R> for NomInput<'_> {
+    fn parse_to(&self) -> Option<R> {
+        self.text.parse().ok()
+    }
+}
@@ -0,0 +1,272 @@
+use {
+    crate::{
+        is_continuation_byte, str_from_utf8, CharPosition, Error, IndexStats, MappedFile,
+        TextSource,
+    },
+    memchr::memmem::Finder,
+    rayon::prelude::*,
+    std::{io, ops::Range},
+};
+
+/// How many bytes each thread indexes at a time by default
+pub const DEFAULT_PARALLEL_CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
+impl<S: TextSource + Sync> MappedFile<S> {
+    /// Indexes the whole file up front like `build_index`, but splits it into chunks
+    /// that are scanned on the rayon thread pool.
+    /// Returns an error if the file isn't valid utf8.
+    pub fn build_index_parallel(&self) -> Result<IndexStats, Error> {
+        self.build_index_parallel_with_chunk_size(DEFAULT_PARALLEL_CHUNK_SIZE)
+    }
+
+    /// Indexes the whole file up front in parallel, giving each thread chunks of about the given number of bytes
+    pub fn build_index_parallel_with_chunk_size(
+        &self,
+        chunk_size: usize,
+    ) -> Result<IndexStats, Error> {
+        let (start, finished) = {
+            let index = self.index();
+            (index.frontier(), index.end_position.is_some())
+        };
+        if finished {
+            return self.build_index();
+        }
+
+        let len = self.map.len();
+        let chunks = split_chunks(&self.map, start.byte_position..len, chunk_size.max(1))?;
+
+        // First work out how many characters are in each chunk so we know where they all start.
+        // If any of them are invalid let the normal scan find it so the error points at the right place
+        let char_counts = chunks
+            .par_iter()
+            .map(|chunk| {
+                self.map.with_bytes(chunk.clone(), |bytes| {
+                    str_from_utf8(bytes)
+                        .ok()
+                        .map(|_| bytes.iter().filter(|&&b| !is_continuation_byte(b)).count())
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?
+            .into_iter()
+            .collect::<Option<Vec<usize>>>();
+        let char_counts = match char_counts {
+            Some(char_counts) => char_counts,
+            None => return self.build_index(),
+        };
+
+        let mut chunk_starts = Vec::with_capacity(chunks.len());
+        let mut char_position = start.char_position;
+        for (chunk, count) in chunks.iter().zip(&char_counts) {
+            chunk_starts.push(CharPosition {
+                byte_position: chunk.start,
+                char_position,
+            });
+            char_position += count;
+        }
+
+        // Then find the line starts and checkpoints in each chunk now that their positions are known
+        let line_endings = &self.line_endings;
+        let interval = self.checkpoint_interval;
+        let found = chunks
+            .par_iter()
+            .zip(&chunk_starts)
+            .map(|(chunk, chunk_start)| {
+                // The byte after might be in the next chunk, so look past the end of this one
+                let range = chunk.start..(chunk.end + 1).min(len);
+                self.map.with_bytes(range, |bytes| {
+                    let mut lines = Vec::new();
+                    let mut checkpoints = Vec::new();
+
+                    // Already validated above
+                    let str = str_from_utf8(&bytes[..chunk.len()]).unwrap();
+                    for (char_index, (byte_offset, c)) in str.char_indices().enumerate() {
+                        let next_offset = byte_offset + c.len_utf8();
+                        let next = CharPosition {
+                            byte_position: chunk.start + next_offset,
+                            char_position: chunk_start.char_position + char_index + 1,
+                        };
+                        if line_endings.ends_line(c, bytes.get(next_offset).copied()) {
+                            lines.push(next);
+                        }
+                        if let Some(interval) = interval {
+                            if next.char_position.is_multiple_of(interval) {
+                                checkpoints.push(next);
+                            }
+                        }
+                    }
+
+                    (lines, checkpoints)
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        {
+            let mut index = self.index_mut();
+            // Another thread might have scanned further while we were busy, so redo what's left
+            if index.frontier().char_position != start.char_position {
+                drop(index);
+                return self.build_index();
+            }
+            for (lines, checkpoints) in found {
+                index.line_ending_positions.extend(lines);
+                index.checkpoints.extend(checkpoints);
+            }
+            index.end_position = Some(CharPosition {
+                byte_position: len,
+                char_position,
+            });
+        }
+
+        self.build_index()
+    }
+}
+
+impl<S: TextSource + Sync> MappedFile<S> {
+    /// Finds every occurrence of some text like `find_all`, but splits the file into chunks
+    /// that are searched on the rayon thread pool and puts what they find back in order.
+    /// Returns an error if the file isn't valid utf8 unless decoding lossily.
+    pub fn find_all_parallel(&self, needle: &str) -> Result<Vec<CharPosition>, Error> {
+        self.find_all_parallel_with_chunk_size(needle, DEFAULT_PARALLEL_CHUNK_SIZE)
+    }
+
+    /// Finds every occurrence of some text in parallel, giving each thread chunks of about the given number of bytes
+    pub fn find_all_parallel_with_chunk_size(
+        &self,
+        needle: &str,
+        chunk_size: usize,
+    ) -> Result<Vec<CharPosition>, Error> {
+        if needle.is_empty() {
+            return Ok(self.find_all(needle).collect());
+        }
+
+        let len = self.map.len();
+        let chunk_size = chunk_size.max(1);
+        let decode_policy = self.decode_policy;
+
+        // Chunks in what's already been indexed start at cached line starts and checkpoints,
+        // so their characters don't need counting
+        let (mut chunk_starts, indexed) = {
+            let index = self.index();
+            let indexed = index.end_position.unwrap_or_else(|| index.frontier());
+            let mut chunk_starts = vec![index.nearest_cached_byte(0)];
+            for byte in (chunk_size..indexed.byte_position).step_by(chunk_size) {
+                let start = index.nearest_cached_byte(byte);
+                if start.byte_position > chunk_starts.last().unwrap().byte_position {
+                    chunk_starts.push(start);
+                }
+            }
+            if indexed.byte_position > chunk_starts.last().unwrap().byte_position {
+                chunk_starts.push(indexed);
+            }
+            (chunk_starts, indexed)
+        };
+
+        // The rest is split evenly, and how many characters are in each chunk is worked out to know where they all start.
+        // If any of them are invalid let the normal scan find it so the error points at the right place
+        let chunks = split_chunks(&self.map, indexed.byte_position..len, chunk_size)?;
+        let char_counts = chunks
+            .par_iter()
+            .map(|chunk| {
+                self.map.with_bytes(chunk.clone(), |bytes| {
+                    decode_policy.count_chars(bytes, indexed).ok()
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?
+            .into_iter()
+            .collect::<Option<Vec<usize>>>();
+        let char_counts = match char_counts {
+            Some(char_counts) => char_counts,
+            None => return self.char_count().map(|_| self.find_all(needle).collect()),
+        };
+
+        let mut char_position = indexed.char_position;
+        for (chunk, count) in chunks.iter().zip(&char_counts) {
+            if chunk.start > indexed.byte_position {
+                chunk_starts.push(CharPosition {
+                    byte_position: chunk.start,
+                    char_position,
+                });
+            }
+            char_position += count;
+        }
+        chunk_starts.retain(|start| start.byte_position < len);
+
+        // Then find every match starting in each chunk, even ones that overlap,
+        // since which of them count depends on the matches before it in other chunks
+        let finder = Finder::new(needle);
+        let chunk_ends: Vec<usize> = chunk_starts
+            .iter()
+            .skip(1)
+            .map(|start| start.byte_position)
+            .chain(std::iter::once(len))
+            .collect();
+        let found = chunk_starts
+            .par_iter()
+            .zip(&chunk_ends)
+            .map(|(&chunk_start, &chunk_end)| {
+                // A match can start in this chunk and end in the next
+                let range = chunk_start.byte_position..(chunk_end + needle.len() - 1).min(len);
+                self.map.with_bytes(range, |bytes| {
+                    let chunk_len = chunk_end - chunk_start.byte_position;
+                    let mut matches = Vec::new();
+                    let mut last = chunk_start;
+                    let mut counted = 0;
+                    let mut from = 0;
+                    while let Some(offset) = finder.find(&bytes[from..]) {
+                        let byte_offset = from + offset;
+                        if byte_offset >= chunk_len {
+                            break;
+                        }
+                        last = CharPosition {
+                            byte_position: chunk_start.byte_position + byte_offset,
+                            char_position: last.char_position
+                                + decode_policy.count_chars(&bytes[counted..byte_offset], last)?,
+                        };
+                        matches.push(last);
+                        counted = byte_offset;
+                        from = byte_offset + 1;
+                    }
+                    Ok(matches)
+                })?
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        // Lastly keep the ones that don't overlap a match before them, like searching from the start would
+        let mut matches = Vec::new();
+        let mut next_byte = 0;
+        for position in found.into_iter().flatten() {
+            if position.byte_position >= next_byte {
+                next_byte = position.byte_position + needle.len();
+                matches.push(position);
+            }
+        }
+        Ok(matches)
+    }
+}
+
+/// Splits a range of a source into ranges of about the chunk size, moving each split forward
+/// so that no character is cut in half
+fn split_chunks(
+    source: &impl TextSource,
+    range: Range<usize>,
+    chunk_size: usize,
+) -> io::Result<Vec<Range<usize>>> {
+    let mut chunks = Vec::new();
+    let mut start = range.start;
+    while start < range.end {
+        let mut end = (start + chunk_size).min(range.end);
+        if end < range.end {
+            // A character has at most three continuation bytes after its first
+            let after = end..(end + 3).min(range.end);
+            end += source.with_bytes(after, |bytes| {
+                bytes
+                    .iter()
+                    .take_while(|&&byte| is_continuation_byte(byte))
+                    .count()
+            })?;
+        }
+        chunks.push(start..end);
+        start = end;
+    }
+    Ok(chunks)
+}
@@ -0,0 +1,281 @@
+use {
+    crate::{
+        index::Index, CharPosition, DecodePolicy, Error, LineEndings, MappedFile, TextSource,
+        SCAN_STEP,
+    },
+    std::{
+        fs::{self, File},
+        io::{self, BufWriter, Write},
+        path::Path,
+    },
+};
+
+/// The first bytes of every saved index, so something else isn't mistaken for one
+const MAGIC: &[u8; 8] = b"RAUINDEX";
+
+/// Bumped whenever the layout of a saved index or how it's checked changes
+const FORMAT_VERSION: u64 = 2;
+
+impl<S: TextSource> MappedFile<S> {
+    /// Saves what has been indexed so far to a sidecar file, along with the settings it was built with,
+    /// so another run can pick up from here with `open_with_index` instead of indexing the file again.
+    /// The file's length and a checksum of its contents are saved too so a stale index can be spotted.
+    /// Returns an error if the sidecar can't be written.
+    pub fn save_index(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_index(&mut out)?;
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Writes the index in the layout read back by `load_index`
+    fn write_index(&self, mut out: impl Write) -> Result<(), Error> {
+        out.write_all(MAGIC)?;
+        write_u64(&mut out, FORMAT_VERSION)?;
+        write_u64(&mut out, self.map.len() as u64)?;
+        write_u64(&mut out, checksum(&self.map)?)?;
+
+        // The settings decide where lines start, so they have to come back with the index
+        write_u64(&mut out, self.line_endings.terminators.len() as u64)?;
+        for &terminator in &self.line_endings.terminators {
+            write_u64(&mut out, terminator as u64)?;
+        }
+        out.write_all(&[
+            self.line_endings.crlf as u8,
+            match self.decode_policy {
+                DecodePolicy::Strict => 0,
+                DecodePolicy::Lossy => 1,
+                DecodePolicy::Bytes => 2,
+            },
+        ])?;
+        // No interval is saved as zero, which isn't a valid interval anyway
+        write_u64(&mut out, self.checkpoint_interval.unwrap_or(0) as u64)?;
+
+        // Evicted lines are scanned again before the lock is taken to write everything out
+        let lines = self.line_ending_positions()?;
+        let index = self.index();
+        match index.end_position {
+            Some(end) => {
+                out.write_all(&[1])?;
+                write_position(&mut out, end)?;
+            }
+            None => out.write_all(&[0])?,
+        }
+        write_u64(&mut out, lines.len() as u64)?;
+        for &position in &lines {
+            write_position(&mut out, position)?;
+        }
+        write_u64(&mut out, index.checkpoints.len() as u64)?;
+        for &position in &index.checkpoints {
+            write_position(&mut out, position)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl MappedFile {
+    /// Creates a new MappedFile from a File using an index saved by `save_index`,
+    /// restoring the settings it was built with, so lookups don't have to index the file again.
+    /// Returns an error if the file can't be mapped or the sidecar can't be read or isn't an index,
+    /// or `Error::StaleIndex` if the index was saved by another version or the file has changed since.
+    /// Checking the index reads the whole file to checksum it, so a change anywhere in it is noticed.
+    pub fn open_with_index(file: File, path: impl AsRef<Path>) -> Result<MappedFile, Error> {
+        MappedFile::load_index(file, &fs::read(path)?)
+    }
+
+    /// Reads an index written by `write_index` and checks it matches the file
+    fn load_index(file: File, saved: &[u8]) -> Result<MappedFile, Error> {
+        let mut file = MappedFile::new(file)?;
+
+        let mut reader = Reader { bytes: saved };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(invalid("not a saved index"));
+        }
+        if reader.u64()? != FORMAT_VERSION {
+            return Err(Error::StaleIndex("saved by a different version"));
+        }
+        if reader.u64()? != file.map.len() as u64 {
+            return Err(Error::StaleIndex("the file's length has changed"));
+        }
+        if reader.u64()? != checksum(&file.map)? {
+            return Err(Error::StaleIndex("the file's contents have changed"));
+        }
+
+        let terminators = (0..reader.u64()?)
+            .map(|_| {
+                u32::try_from(reader.u64()?)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| invalid("a terminator isn't a character"))
+            })
+            .collect::<Result<Vec<char>, Error>>()?;
+        let crlf = reader.byte()? != 0;
+        let decode_policy = match reader.byte()? {
+            0 => DecodePolicy::Strict,
+            1 => DecodePolicy::Lossy,
+            2 => DecodePolicy::Bytes,
+            _ => return Err(invalid("unknown decode policy")),
+        };
+        // No interval was saved as zero
+        let interval = Some(reader.usize()?).filter(|&interval| interval > 0);
+
+        file.set_line_endings(LineEndings { terminators, crlf });
+        file.set_decode_policy(decode_policy);
+        file.set_checkpoint_interval(interval);
+
+        let mut index = Index::new(file.compact_index());
+        let end_position = match reader.byte()? {
+            0 => None,
+            _ => Some(reader.position()?),
+        };
+        let lines = (0..reader.u64()?)
+            .map(|_| reader.position())
+            .collect::<Result<Vec<_>, _>>()?;
+        let checkpoints = (0..reader.u64()?)
+            .map(|_| reader.position())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // A corrupted index could send lookups anywhere, so everything has to be somewhere a character could start
+        let len = file.map.len();
+        // Every index has to start with the start of the file, which the new index already has
+        if lines
+            .first()
+            .map(|start| (start.byte_position, start.char_position))
+            != Some((0, 0))
+        {
+            return Err(invalid("missing the start of the file"));
+        }
+        check_positions(&lines, len)?;
+        check_positions(&checkpoints, len)?;
+        if let Some(interval) = interval {
+            if checkpoints
+                .iter()
+                .any(|checkpoint| !checkpoint.char_position.is_multiple_of(interval))
+            {
+                return Err(invalid("a checkpoint isn't on the interval"));
+            }
+        }
+        if let Some(end) = end_position {
+            let mut last = lines.last().into_iter().chain(checkpoints.last());
+            if end.byte_position != len || last.any(|&last| !follows(last, end)) {
+                return Err(invalid("the end of the file isn't after everything else"));
+            }
+        }
+
+        for &line in &lines[1..] {
+            index.line_ending_positions.push(line);
+        }
+        index.checkpoints = checkpoints;
+        index.end_position = end_position;
+        *file.index_mut() = index;
+
+        Ok(file)
+    }
+}
+
+/// Checksums the whole file with FNV-1a, a stretch at a time
+fn checksum(source: &impl TextSource) -> Result<u64, Error> {
+    let len = source.len();
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut start = 0;
+    while start < len {
+        let end = (start + SCAN_STEP).min(len);
+        hash = source.with_bytes(start..end, |bytes| {
+            bytes.iter().fold(hash, |hash, &byte| {
+                (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+            })
+        })?;
+        start = end;
+    }
+    Ok(hash)
+}
+
+/// Checks saved positions are strictly in order, within a file `len` bytes long
+/// and as far apart in bytes as the characters between them could take up
+fn check_positions(positions: &[CharPosition], len: usize) -> Result<(), Error> {
+    let mut previous: Option<CharPosition> = None;
+    for &position in positions {
+        let in_order = match previous {
+            Some(previous) => {
+                position.char_position > previous.char_position && follows(previous, position)
+            }
+            None => follows(
+                CharPosition {
+                    byte_position: 0,
+                    char_position: 0,
+                },
+                position,
+            ),
+        };
+        if position.byte_position > len || !in_order {
+            return Err(invalid(
+                "positions are out of order or don't fit the characters between them",
+            ));
+        }
+        previous = Some(position);
+    }
+    Ok(())
+}
+
+/// Checks a position can come at or after another, with between one and four bytes for each character between them
+fn follows(before: CharPosition, after: CharPosition) -> bool {
+    let bytes = after.byte_position.checked_sub(before.byte_position);
+    let chars = after.char_position.checked_sub(before.char_position);
+    match (bytes, chars) {
+        (Some(bytes), Some(chars)) => chars <= bytes && bytes <= chars.saturating_mul(4),
+        _ => false,
+    }
+}
+
+fn write_u64(out: &mut impl Write, value: u64) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+fn write_position(out: &mut impl Write, position: CharPosition) -> io::Result<()> {
+    write_u64(out, position.byte_position as u64)?;
+    write_u64(out, position.char_position as u64)
+}
+
+fn invalid(message: &str) -> Error {
+    Error::Io(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("couldn't load the index: {}", message),
+    ))
+}
+
+/// Reads the pieces of a saved index back in order
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.bytes.len() < len {
+            return Err(invalid("cut short"));
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// Reads a number that has to fit in a `usize`, which an index saved on a 64 bit target might not
+    fn usize(&mut self) -> Result<usize, Error> {
+        usize::try_from(self.u64()?).map_err(|_| invalid("too big for this platform"))
+    }
+
+    fn position(&mut self) -> Result<CharPosition, Error> {
+        Ok(CharPosition {
+            byte_position: self.usize()?,
+            char_position: self.usize()?,
+        })
+    }
+}
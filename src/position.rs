@@ -0,0 +1,116 @@
+use crate::{Error, MappedFile, TextSource};
+
+#[cfg(feature = "graphemes")]
+use unicode_segmentation::UnicodeSegmentation;
+
+/// The units a column within a line can be counted in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PositionEncoding {
+    /// Bytes of the utf8 encoding, as used by tree-sitter
+    Utf8Bytes,
+    /// UTF-16 code units, as used by the Language Server Protocol by default
+    Utf16Units,
+    /// Unicode scalar values, the same as the character indices used by the rest of the crate
+    Utf32Chars,
+    /// Extended grapheme clusters (user-perceived characters)
+    #[cfg(feature = "graphemes")]
+    Graphemes,
+}
+
+impl PositionEncoding {
+    /// Splits a line into the smallest pieces that can be addressed in this encoding,
+    /// giving how many units and how many characters each piece takes up.
+    fn segments(self, text: &str) -> Box<dyn Iterator<Item = (usize, usize)> + '_> {
+        match self {
+            PositionEncoding::Utf8Bytes => Box::new(text.chars().map(|c| (c.len_utf8(), 1))),
+            PositionEncoding::Utf16Units => Box::new(text.chars().map(|c| (c.len_utf16(), 1))),
+            PositionEncoding::Utf32Chars => Box::new(text.chars().map(|_| (1, 1))),
+            #[cfg(feature = "graphemes")]
+            PositionEncoding::Graphemes => Box::new(
+                text.graphemes(true)
+                    .map(|grapheme| (1, grapheme.chars().count())),
+            ),
+        }
+    }
+
+    /// Converts a column in this encoding to a column counted in characters.
+    /// A column in the middle of a piece snaps back to the start of it,
+    /// and the column just past the end of the line is allowed.
+    fn decode_column(self, text: &str, column: usize) -> Result<usize, Error> {
+        let mut units = 0;
+        let mut chars = 0;
+        for (segment_units, segment_chars) in self.segments(text) {
+            units += segment_units;
+            if units > column {
+                break;
+            }
+            chars += segment_chars;
+        }
+
+        // Running out of pieces before reaching the column means it's past the end of the line
+        if units < column {
+            return Err(Error::OutOfBounds {
+                index: column,
+                len: units,
+            });
+        }
+
+        Ok(chars)
+    }
+
+    /// Converts a column counted in characters to a column in this encoding.
+    /// A character in the middle of a piece snaps back to the start of it.
+    fn encode_column(self, text: &str, column: usize) -> usize {
+        let mut units = 0;
+        let mut chars = 0;
+        for (segment_units, segment_chars) in self.segments(text) {
+            chars += segment_chars;
+            if chars > column {
+                break;
+            }
+            units += segment_units;
+        }
+
+        units
+    }
+}
+
+impl<S: TextSource> MappedFile<S> {
+    /// Converts a column within a zero-based line from one encoding's units to another's.
+    /// Columns in the middle of a character (or grapheme) snap back to its start,
+    /// and the column just past the end of the line is allowed.
+    /// Returns an error if the column is further than that or the line isn't valid utf8.
+    pub fn convert_column(
+        &self,
+        line: usize,
+        column: usize,
+        from: PositionEncoding,
+        to: PositionEncoding,
+    ) -> Result<usize, Error> {
+        let text = self.line_at(line)?;
+        let char_column = from.decode_column(text, column)?;
+        Ok(to.encode_column(text, char_column))
+    }
+
+    /// Returns the zero-based line and UTF-16 code unit column of the character at the given index,
+    /// which is how the Language Server Protocol addresses positions.
+    /// Returns an error if the index is out of bounds or the line isn't valid utf8.
+    pub fn lsp_position_of(&self, index: usize) -> Result<(usize, usize), Error> {
+        let (line, column) = self.line_col_of(index)?;
+        let text = self.line_at(line)?;
+        Ok((
+            line,
+            PositionEncoding::Utf16Units.encode_column(text, column),
+        ))
+    }
+
+    /// Returns the character index at a zero-based line and UTF-16 code unit column.
+    /// A column in the middle of a surrogate pair snaps back to the start of that character,
+    /// and the column just past the end of the line resolves to the line's newline.
+    /// Returns an error if the column is further than that or the line isn't valid utf8.
+    pub fn char_index_of_lsp(&self, line: usize, utf16_column: usize) -> Result<usize, Error> {
+        let text = self.line_at(line)?;
+        let column = PositionEncoding::Utf16Units.decode_column(text, utf16_column)?;
+        Ok(self.with_lines(|lines| lines.at(line))?.char_position + column)
+    }
+}
@@ -0,0 +1,58 @@
+use {
+    crate::{CharPosition, Error, MappedFile, TextSource},
+    regex::Regex,
+    std::ops::Range,
+};
+
+/// Where a regex matched in a file, see `MappedFile::regex_find_iter`
+#[derive(Clone, Copy, Debug)]
+pub struct RegexMatch<'a> {
+    /// Where the match starts
+    pub start: CharPosition,
+    /// Where the match ends, just past its last character
+    pub end: CharPosition,
+    /// The text that matched
+    pub text: &'a str,
+}
+
+impl RegexMatch<'_> {
+    /// Returns the indices of the characters that matched
+    pub fn char_range(&self) -> Range<usize> {
+        self.start.char_position..self.end.char_position
+    }
+}
+
+impl<S: TextSource> MappedFile<S> {
+    /// Iterates over the matches of a regex in a range of characters, which is borrowed rather than copied.
+    /// The regex engine's byte offsets are turned into character positions in the file
+    /// by counting the characters between one match and the next.
+    /// Returns an error if the range is out of bounds or isn't valid utf8, whatever the decode policy.
+    pub fn regex_find_iter<'a>(
+        &'a self,
+        re: &'a Regex,
+        range: Range<usize>,
+    ) -> Result<impl Iterator<Item = RegexMatch<'a>> + 'a, Error> {
+        let (start, _) = self.range_positions(range.clone())?;
+        let text = self.str_range(range)?;
+
+        // Where the last match ended, as an offset into the text and a position in the file
+        let mut counted = (0, start);
+        Ok(re.find_iter(text).map(move |found| {
+            let mut position_of = |offset: usize| {
+                let (from, position) = counted;
+                let position = CharPosition {
+                    byte_position: start.byte_position + offset,
+                    char_position: position.char_position + text[from..offset].chars().count(),
+                };
+                counted = (offset, position);
+                position
+            };
+
+            RegexMatch {
+                start: position_of(found.start()),
+                end: position_of(found.end()),
+                text: found.as_str(),
+            }
+        }))
+    }
+}
@@ -0,0 +1,602 @@
+use {
+    crate::{
+        stretch_end, CharPosition, Cursor, Error, LineEndings, MappedFile, TextSource, SCAN_STEP,
+    },
+    memchr::memmem::{Finder, FinderRev},
+    std::ops::Range,
+};
+
+/// How letters in different cases are matched when searching, see `MappedFile::find_with`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CaseSensitivity {
+    /// Characters only match themselves
+    #[default]
+    Sensitive,
+    /// Characters match if they fold to the same character with Unicode simple case folding,
+    /// so "STRASSE" matches "strasse" but "straße" doesn't
+    Simple,
+    /// Characters match if they fold to the same characters with Unicode full case folding,
+    /// where some fold to more than one, so "straße" matches "strasse" and "STRASSE" too
+    Full,
+}
+
+/// How text is matched when searching, see `MappedFile::find_with`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SearchOptions {
+    case: CaseSensitivity,
+    #[cfg(feature = "normalization")]
+    normalized: bool,
+}
+
+impl SearchOptions {
+    /// Creates options that match text exactly
+    pub fn new() -> SearchOptions {
+        SearchOptions::default()
+    }
+
+    /// Sets how letters in different cases are matched
+    pub fn case(mut self, case: CaseSensitivity) -> SearchOptions {
+        self.case = case;
+        self
+    }
+
+    /// Sets whether text is matched under canonical equivalence,
+    /// so "é" as one character matches "e" followed by a combining acute accent and the other way around.
+    /// A match can't start or stop between a character and the combining marks after it
+    #[cfg(feature = "normalization")]
+    pub fn normalized(mut self, normalized: bool) -> SearchOptions {
+        self.normalized = normalized;
+        self
+    }
+
+    /// Checks whether text is matched under canonical equivalence
+    #[cfg(feature = "normalization")]
+    fn is_normalized(&self) -> bool {
+        self.normalized
+    }
+
+    #[cfg(not(feature = "normalization"))]
+    fn is_normalized(&self) -> bool {
+        false
+    }
+
+    /// Checks whether text can be matched byte for byte
+    fn is_exact(&self) -> bool {
+        self.case == CaseSensitivity::Sensitive && !self.is_normalized()
+    }
+
+    /// Reads the next thing that's matched as a whole from a cursor, expanding it into `out`.
+    /// That's a character along with any combining marks after it when normalizing, otherwise a single character.
+    /// Returns false at the end of the file or at bytes that can't be decoded
+    fn next_unit<S: TextSource>(&self, cursor: &mut Cursor<S>, out: &mut Vec<char>) -> bool {
+        out.clear();
+        let Some(c) = cursor.next() else {
+            return false;
+        };
+        self.expand(c, out);
+
+        #[cfg(feature = "normalization")]
+        if self.normalized {
+            use unicode_normalization::char::canonical_combining_class;
+            while let Some(mark) = cursor.peek().filter(|&m| canonical_combining_class(m) != 0) {
+                cursor.next();
+                self.expand(mark, out);
+            }
+            canonical_order(out);
+        }
+        true
+    }
+
+    /// Adds what a character is matched as onto the end of `out`
+    fn expand(&self, c: char, out: &mut Vec<char>) {
+        #[cfg(feature = "normalization")]
+        if self.normalized {
+            use unicode_normalization::char::decompose_canonical;
+            // Folding a decomposed character can give one that decomposes again
+            let mut folded = Vec::new();
+            decompose_canonical(c, |d| self.fold(d, &mut folded));
+            for f in folded {
+                decompose_canonical(f, |d| out.push(d));
+            }
+            return;
+        }
+        self.fold(c, out)
+    }
+
+    /// Adds what a character folds to onto the end of `out`
+    fn fold(&self, c: char, out: &mut Vec<char>) {
+        match self.case {
+            CaseSensitivity::Sensitive => out.push(c),
+            CaseSensitivity::Simple => out.push(simple_fold(c)),
+            // Lowercasing first takes characters like 'ẞ' to ones that fold further
+            CaseSensitivity::Full => out.extend(
+                c.to_lowercase()
+                    .flat_map(char::to_uppercase)
+                    .flat_map(char::to_lowercase),
+            ),
+        }
+    }
+}
+
+impl From<CaseSensitivity> for SearchOptions {
+    fn from(case: CaseSensitivity) -> SearchOptions {
+        SearchOptions::new().case(case)
+    }
+}
+
+/// Sorts each run of combining marks by combining class, which puts them in the order NFD would
+#[cfg(feature = "normalization")]
+fn canonical_order(chars: &mut [char]) {
+    use unicode_normalization::char::canonical_combining_class;
+    for marks in chars.split_mut(|&c| canonical_combining_class(c) == 0) {
+        marks.sort_by_key(|&c| canonical_combining_class(c));
+    }
+}
+
+/// Folds a character with the standard library's case mappings,
+/// leaving it alone where a mapping would give more than one character
+fn simple_fold(c: char) -> char {
+    fn single(mut chars: impl Iterator<Item = char>) -> Option<char> {
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    }
+
+    let lower = single(c.to_lowercase()).unwrap_or(c);
+    single(lower.to_uppercase())
+        .and_then(|upper| single(upper.to_lowercase()))
+        .unwrap_or(lower)
+}
+
+impl<S: TextSource> MappedFile<S> {
+    /// Finds the first occurrence of a character at or after the given index.
+    /// ASCII characters are found with `memchr`, others by searching for their bytes,
+    /// which can't match partway through another character.
+    /// Returns `None` if there isn't one, or an error if the index is out of bounds
+    /// or the file isn't valid utf8 before the character is found.
+    pub fn find_char(&self, c: char, from_index: usize) -> Result<Option<CharPosition>, Error> {
+        let start = self.position_at(from_index)?;
+        self.find_from(&Finder::new(c.encode_utf8(&mut [0; 4])), start)
+    }
+
+    /// Iterates over the positions of every occurrence of a character, in order.
+    /// Stops early at invalid utf8 unless decoding lossily
+    pub fn positions_of(&self, c: char) -> impl Iterator<Item = CharPosition> + '_ {
+        self.matches(Finder::new(c.encode_utf8(&mut [0; 4])).into_owned())
+    }
+
+    /// Finds the first occurrence of some text starting at or after the given character index,
+    /// searching the bytes with `memchr::memmem` and counting the characters skipped over to get there.
+    /// An empty needle is found straight away.
+    /// Returns `None` if there isn't one, or an error if the index is out of bounds
+    /// or the file isn't valid utf8 before the text is found.
+    pub fn find(&self, needle: &str, from_char: usize) -> Result<Option<CharPosition>, Error> {
+        let start = self.position_at(from_char)?;
+        self.find_from(&Finder::new(needle), start)
+    }
+
+    /// Iterates over the positions of every occurrence of some text that doesn't overlap one before it, in order,
+    /// like `str::match_indices`. An empty needle is found at every character and at the end.
+    /// Stops early at invalid utf8 unless decoding lossily
+    pub fn find_all(&self, needle: &str) -> impl Iterator<Item = CharPosition> + '_ {
+        self.matches(Finder::new(needle).into_owned())
+    }
+
+    /// Finds the first match for some text starting at or after the given character index,
+    /// matching it as the options say, like ignoring case with `CaseSensitivity`.
+    /// Gives where the match starts and ends, since it can be a different length to the needle.
+    /// A match always covers whole characters.
+    /// Returns `None` if there isn't one, or an error if the index is out of bounds
+    /// or the file isn't valid utf8 before the text is found.
+    pub fn find_with(
+        &self,
+        needle: &str,
+        from_char: usize,
+        options: impl Into<SearchOptions>,
+    ) -> Result<Option<Range<CharPosition>>, Error> {
+        let start = self.position_at(from_char)?;
+        let options = options.into();
+        if options.is_exact() {
+            return match self.find_from(&Finder::new(needle), start)? {
+                Some(found) => self.exact_match(found, needle).map(Some),
+                None => Ok(None),
+            };
+        }
+        self.find_expanded(&expand_needle(needle, &options), &options, start)
+    }
+
+    /// Iterates over every match for some text that doesn't overlap one before it, in order,
+    /// matching it as the options say, see `find_with`.
+    /// Stops early at invalid utf8 unless decoding lossily
+    pub fn find_all_with(
+        &self,
+        needle: &str,
+        options: impl Into<SearchOptions>,
+    ) -> impl Iterator<Item = Range<CharPosition>> + '_ {
+        let options = options.into();
+        let expanded = expand_needle(needle, &options);
+        let finder = Finder::new(needle).into_owned();
+        let mut next = Some(CharPosition {
+            byte_position: 0,
+            char_position: 0,
+        });
+
+        std::iter::from_fn(move || {
+            let found = match options.is_exact() {
+                true => self.find_from(&finder, next?).and_then(|found| {
+                    found
+                        .map(|f| self.exact_match(f, finder.needle()))
+                        .transpose()
+                }),
+                false => self.find_expanded(&expanded, &options, next?),
+            }
+            .ok()
+            .flatten();
+            next = match &found {
+                // Empty needles would be found in the same place forever
+                Some(found) if found.start.byte_position == found.end.byte_position => {
+                    self.position_at(found.end.char_position + 1).ok()
+                }
+                Some(found) => Some(found.end),
+                None => None,
+            };
+            found
+        })
+    }
+
+    /// Finds the last occurrence of a character before the given index, see `rfind`.
+    /// Returns `None` if there isn't one, or an error if the index is out of bounds.
+    pub fn rfind_char(&self, c: char, before_index: usize) -> Result<Option<CharPosition>, Error> {
+        self.rfind(c.encode_utf8(&mut [0; 4]), before_index)
+    }
+
+    /// Finds the last occurrence of some text that ends at or before the given character index,
+    /// searching backwards a stretch at a time, so "find previous" only reads back as far as the match.
+    /// The match's character index comes from the nearest cached line start or checkpoint before it.
+    /// Passing a match's own index finds the one before it.
+    /// An empty needle is found straight away.
+    /// Returns `None` if there isn't one, or an error if the index is out of bounds.
+    pub fn rfind(&self, needle: &str, before_char: usize) -> Result<Option<CharPosition>, Error> {
+        let end = self.position_at(before_char)?;
+        let needle = needle.as_bytes();
+        if needle.is_empty() {
+            return Ok(Some(end));
+        }
+
+        let finder = FinderRev::new(needle);
+        let mut window_end = end.byte_position;
+        while window_end > 0 {
+            let window_start = window_end.saturating_sub(SCAN_STEP);
+            // A match can start in this window and end in the one after
+            let range = window_start..(window_end + needle.len() - 1).min(end.byte_position);
+            let found = self.map.with_bytes(range, |bytes| match needle {
+                [byte] => memchr::memrchr(*byte, bytes),
+                _ => finder.rfind(bytes),
+            })?;
+
+            if let Some(offset) = found {
+                return self.char_index_at_byte(window_start + offset).map(Some);
+            }
+            window_end = window_start;
+        }
+
+        Ok(None)
+    }
+
+    /// Finds the first line that starts with the key in a file whose lines are sorted by their bytes,
+    /// like `look(1)`: a binary search jumps around the file by byte offset and snaps to the next line start,
+    /// comparing only as much of each line as the key is long.
+    /// Lines are split with the file's line endings, and their terminators aren't compared.
+    /// Only working out the line number of the match indexes the lines before it.
+    /// Returns `None` if no line starts with the key, or an error if the file can't be read.
+    pub fn bisect_line(&self, key: &str) -> Result<Option<usize>, Error> {
+        let key = key.as_bytes();
+        let len = self.map.len();
+        // The first line at or after the key starts at `low` or `high`, and no line starts between `top` and `high`
+        let (mut low, mut high, mut top) = (0, len, len);
+        while low < top {
+            let middle = low + (top - low) / 2;
+            match self.next_line_start(middle)? {
+                Some(start) if start < high => match *self.line_prefix(start, key.len())? < *key {
+                    true => (low, top) = (start, high),
+                    false => (high, top) = (start, start),
+                },
+                _ => top = middle,
+            }
+        }
+
+        let start = match *self.line_prefix(low, key.len())? < *key {
+            true => high,
+            false => low,
+        };
+        if self.line_prefix(start, key.len())? != key {
+            return Ok(None);
+        }
+        let position = self.char_index_at_byte(start)?;
+        self.line_col_of(position.char_position)
+            .map(|(line, _)| Some(line))
+    }
+
+    /// Finds where the first line after the one containing a byte starts, if there is one
+    fn next_line_start(&self, byte: usize) -> Result<Option<usize>, Error> {
+        let len = self.map.len();
+        let mut window_start = byte;
+        while window_start < len {
+            let window_end = (window_start + SCAN_STEP).min(len);
+            // Grab a few bytes past the window so we can see what follows it
+            let range = window_start..(window_end + 4).min(len);
+            let (found, cut) = self.map.with_bytes(range, |bytes| {
+                let cut = stretch_end(bytes, window_end - window_start, window_end == len);
+                (find_line_end(&self.line_endings, bytes, cut), cut)
+            })?;
+            if let Some(terminator) = found {
+                return Ok(Some(window_start + terminator.end));
+            }
+            window_start += cut;
+        }
+        Ok(None)
+    }
+
+    /// Reads up to the given number of bytes from the start of the line starting at a byte, without its terminator
+    fn line_prefix(&self, start: usize, len: usize) -> Result<Vec<u8>, Error> {
+        // A terminator can start anywhere in the prefix, and a \r needs the \n after it
+        let range = start..(start + len + 4).min(self.map.len());
+        let prefix = self.map.with_bytes(range, |bytes| {
+            let before = (len + 1).min(bytes.len());
+            let end = match find_line_end(&self.line_endings, bytes, before) {
+                Some(terminator) => terminator.start,
+                None => bytes.len(),
+            };
+            bytes[..end.min(len)].to_vec()
+        })?;
+        Ok(prefix)
+    }
+
+    /// Iterates over the matches of a searcher from the start of the file, skipping past each one
+    fn matches(&self, finder: Finder<'static>) -> impl Iterator<Item = CharPosition> + '_ {
+        let mut next = Some(CharPosition {
+            byte_position: 0,
+            char_position: 0,
+        });
+
+        std::iter::from_fn(move || {
+            let found = self.find_from(&finder, next?).ok().flatten();
+            next = match (found, finder.needle()) {
+                // Empty needles would be found in the same place forever
+                (Some(position), []) => self.position_at(position.char_position + 1).ok(),
+                (Some(position), needle) => {
+                    let chars = self.decode_policy.count_chars(needle, position).ok()?;
+                    Some(CharPosition {
+                        byte_position: position.byte_position + needle.len(),
+                        char_position: position.char_position + chars,
+                    })
+                }
+                (None, _) => None,
+            };
+            found
+        })
+    }
+
+    /// Works out where an exact match for a needle ends
+    fn exact_match(
+        &self,
+        found: CharPosition,
+        needle: impl AsRef<[u8]>,
+    ) -> Result<Range<CharPosition>, Error> {
+        let needle = needle.as_ref();
+        let chars = self.decode_policy.count_chars(needle, found)?;
+        Ok(found..CharPosition {
+            byte_position: found.byte_position + needle.len(),
+            char_position: found.char_position + chars,
+        })
+    }
+
+    /// Finds the first stretch of characters at or after a position
+    /// that expand to the expanded needle, trying each character (or character and its marks) in turn.
+    /// The needle has to take up every character it covers, so it can't match part of what one expands to
+    fn find_expanded(
+        &self,
+        needle: &[char],
+        options: &SearchOptions,
+        start: CharPosition,
+    ) -> Result<Option<Range<CharPosition>>, Error> {
+        let mut candidates = self.cursor_at(start);
+        let mut expanded = Vec::new();
+        loop {
+            let mut attempt = candidates.clone();
+            let mut matched = 0;
+            loop {
+                if matched == needle.len() {
+                    return Ok(Some(candidates.position()..attempt.position()));
+                }
+                if !options.next_unit(&mut attempt, &mut expanded) {
+                    break;
+                }
+                match needle[matched..].starts_with(&expanded) {
+                    true => matched += expanded.len(),
+                    false => break,
+                }
+            }
+
+            if !options.next_unit(&mut candidates, &mut expanded) {
+                break;
+            }
+        }
+
+        // The cursor stops at bytes it can't decode as well as at the end
+        let end = candidates.position();
+        match end.byte_position < self.map.len() {
+            true => self.char_at(end).map(|_| None),
+            false => Ok(None),
+        }
+    }
+
+    /// Finds the first match of a searcher at or after a position,
+    /// counting the characters skipped over to get to it.
+    /// The needle has to be whole characters, so it can't match partway through one
+    fn find_from(
+        &self,
+        finder: &Finder,
+        start: CharPosition,
+    ) -> Result<Option<CharPosition>, Error> {
+        let needle = finder.needle();
+        if needle.is_empty() {
+            return Ok(Some(start));
+        }
+
+        let len = self.map.len();
+        let mut window_start = start.byte_position;
+        while window_start < len {
+            let window_end = (window_start + SCAN_STEP).min(len);
+            // A match can start in this window and end in the next
+            let range = window_start..(window_end + needle.len() - 1).min(len);
+            let found = self.map.with_bytes(range, |bytes| match needle {
+                [byte] => memchr::memchr(*byte, bytes),
+                _ => finder.find(bytes),
+            })?;
+
+            if let Some(offset) = found {
+                let byte_position = window_start + offset;
+                let chars = self.count_chars_to(start, byte_position)?;
+                return Ok(Some(CharPosition {
+                    byte_position,
+                    char_position: start.char_position + chars,
+                }));
+            }
+            window_start = window_end;
+        }
+
+        Ok(None)
+    }
+}
+
+/// Finds the first terminator that starts before `before` in some bytes, giving the bytes it takes up.
+/// The bytes after `before` are only looked at to see what follows a terminator,
+/// and bytes that aren't valid utf8 can't be one
+fn find_line_end(line_endings: &LineEndings, bytes: &[u8], before: usize) -> Option<Range<usize>> {
+    let ends_at = |at: usize, c: char| {
+        let next = at + c.len_utf8();
+        match line_endings.ends_line(c, bytes.get(next).copied()) {
+            true => {
+                let previous = at.checked_sub(1).map(|byte| bytes[byte]);
+                Some(next - line_endings.terminator(c, previous).byte_len()..next)
+            }
+            false => None,
+        }
+    };
+
+    if line_endings.is_ascii() {
+        let mut search = 0;
+        while let Some(found) = line_endings.find_terminator(&bytes[search..before]) {
+            let at = search + found;
+            if let Some(terminator) = ends_at(at, bytes[at] as char) {
+                return Some(terminator);
+            }
+            search = at + 1;
+        }
+        return None;
+    }
+
+    let mut offset = 0;
+    for chunk in bytes[..before].utf8_chunks() {
+        for (byte_offset, c) in chunk.valid().char_indices() {
+            if let Some(terminator) = ends_at(offset + byte_offset, c) {
+                return Some(terminator);
+            }
+        }
+        offset += chunk.valid().len() + chunk.invalid().len();
+    }
+    None
+}
+
+/// Expands each character of a needle to what it's matched as
+fn expand_needle(needle: &str, options: &SearchOptions) -> Vec<char> {
+    let mut expanded = Vec::new();
+    for c in needle.chars() {
+        options.expand(c, &mut expanded);
+    }
+    #[cfg(feature = "normalization")]
+    if options.normalized {
+        canonical_order(&mut expanded);
+    }
+    expanded
+}
+
+#[cfg(feature = "aho-corasick")]
+impl<S: TextSource> MappedFile<S> {
+    /// Finds every occurrence of any of the patterns in one pass over the file with Aho-Corasick,
+    /// giving the index of the pattern that matched along with where it starts.
+    /// Matches don't overlap, and where several patterns match at the same place the first one listed wins.
+    /// Stops early at invalid utf8 unless decoding lossily.
+    /// Returns an error if the patterns can't be built into a searcher.
+    pub fn find_any(
+        &self,
+        patterns: &[&str],
+    ) -> Result<impl Iterator<Item = (usize, CharPosition)> + '_, Error> {
+        use {
+            aho_corasick::{AhoCorasick, MatchKind},
+            std::io,
+        };
+
+        let searcher = AhoCorasick::builder()
+            .match_kind(MatchKind::LeftmostFirst)
+            .build(patterns)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let longest = patterns
+            .iter()
+            .map(|pattern| pattern.len())
+            .max()
+            .unwrap_or(0);
+        let len = self.map.len();
+
+        // Where to search from next, and the position of the character there
+        let mut next = Some(CharPosition {
+            byte_position: 0,
+            char_position: 0,
+        });
+        Ok(std::iter::from_fn(move || {
+            let from = next.take()?;
+
+            // Search a stretch at a time, keeping only matches that start in the stretch
+            let mut window_start = from.byte_position;
+            let (found, pattern) = loop {
+                let window_end = (window_start + SCAN_STEP).min(len);
+                // A match can start in this window and end in the next
+                let range = window_start..(window_end + longest.saturating_sub(1)).min(len);
+                let found = self
+                    .map
+                    .with_bytes(range, |bytes| searcher.find(bytes))
+                    .ok()?;
+                match found {
+                    Some(found)
+                        if window_start + found.start() < window_end || window_end == len =>
+                    {
+                        let start = window_start + found.start();
+                        break (
+                            start..window_start + found.end(),
+                            found.pattern().as_usize(),
+                        );
+                    }
+                    None if window_end == len => return None,
+                    _ => window_start = window_end,
+                }
+            };
+
+            let start = CharPosition {
+                byte_position: found.start,
+                char_position: from.char_position + self.count_chars_to(from, found.start).ok()?,
+            };
+            // Empty patterns would be found in the same place forever
+            next = match found.is_empty() {
+                true => self.position_at(start.char_position + 1).ok(),
+                false => Some(CharPosition {
+                    byte_position: found.end,
+                    char_position: start.char_position
+                        + self.count_chars_to(start, found.end).ok()?,
+                }),
+            };
+            Some((pattern, start))
+        }))
+    }
+}
@@ -0,0 +1,149 @@
+use {
+    crate::{decode_str, Error, MappedFile, TextSource},
+    std::ops::Range,
+    unicode_segmentation::UnicodeSegmentation,
+};
+
+impl<S: TextSource> MappedFile<S> {
+    /// Returns the word segment containing the character at the given index along with its character range.
+    /// Segments follow UAX #29 so whitespace and punctuation between words are segments of their own.
+    /// Returns an error if the index is out of bounds or the line isn't valid utf8.
+    pub fn word_at(&self, index: usize) -> Result<(Range<usize>, &str), Error> {
+        let (line, position) = self.line_of(index)?;
+        if position.byte_position >= self.map.len() {
+            return Err(Error::OutOfBounds {
+                index,
+                len: position.char_position,
+            });
+        }
+
+        // Words never continue past a newline so we only need to segment the line
+        let line_start = self.with_lines(|lines| lines.at(line))?;
+        let span = self.line_span(line)?;
+        let text = decode_str(self.map.bytes(span)?, line_start)?;
+
+        let mut start = line_start.char_position;
+        for word in text.split_word_bounds() {
+            let end = start + word.chars().count();
+            if index < end {
+                return Ok((start..end, word));
+            }
+            start = end;
+        }
+
+        // The line contains the index so one of its segments must too
+        Err(Error::OutOfBounds { index, len: start })
+    }
+
+    /// Returns an iterator over the words in the file along with their character ranges,
+    /// skipping the whitespace and punctuation between them.
+    /// Words never continue past a newline, so the file is segmented a line at a time.
+    /// Stops with an error at the first line that isn't valid utf8.
+    pub fn words(&self) -> impl Iterator<Item = Result<(Range<usize>, &str), Error>> + '_ {
+        let mut line = 0;
+        let mut failed = false;
+        std::iter::from_fn(move || {
+            if failed {
+                return None;
+            }
+            let words = self.line_words(line).transpose()?;
+            line += 1;
+            failed = words.is_err();
+            Some(words)
+        })
+        .flat_map(|words| {
+            let (words, error) = match words {
+                Ok(words) => (Some(words), None),
+                Err(e) => (None, Some(e)),
+            };
+            words.into_iter().flatten().map(Ok).chain(error.map(Err))
+        })
+    }
+
+    /// Segments a line into words along with their character ranges, or gives `None` past the last line
+    fn line_words(
+        &self,
+        line: usize,
+    ) -> Result<Option<impl Iterator<Item = (Range<usize>, &str)> + '_>, Error> {
+        let span = match self.line_span(line) {
+            Ok(span) => span,
+            Err(Error::OutOfBounds { .. }) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let line_start = self.with_lines(|lines| lines.at(line))?;
+        let text = decode_str(self.map.bytes(span)?, line_start)?;
+
+        let mut start = line_start.char_position;
+        Ok(Some(text.split_word_bounds().filter_map(move |word| {
+            let range = start..start + word.chars().count();
+            start = range.end;
+
+            // Same rule as unicode_words for what counts as a word
+            if word.chars().any(char::is_alphanumeric) {
+                Some((range, word))
+            } else {
+                None
+            }
+        })))
+    }
+
+    /// Returns the sentence containing the character at the given index along with its character range.
+    /// Sentences follow UAX #29 and always end at a newline.
+    /// Returns an error if the index is out of bounds or the line isn't valid utf8.
+    pub fn sentence_at(&self, index: usize) -> Result<(Range<usize>, &str), Error> {
+        let (line, position) = self.line_of(index)?;
+        if position.byte_position >= self.map.len() {
+            return Err(Error::OutOfBounds {
+                index,
+                len: position.char_position,
+            });
+        }
+
+        // Sentences never continue past a newline so we only need to segment the line
+        let line_start = self.with_lines(|lines| lines.at(line))?;
+        let span = self.line_span(line)?;
+        let text = decode_str(self.map.bytes(span)?, line_start)?;
+
+        let mut start = line_start.char_position;
+        for sentence in text.split_sentence_bounds() {
+            let end = start + sentence.chars().count();
+            if index < end {
+                return Ok((start..end, sentence));
+            }
+            start = end;
+        }
+
+        // The line contains the index so one of its sentences must too
+        Err(Error::OutOfBounds { index, len: start })
+    }
+
+    /// Returns an iterator over the sentences in a range of lines along with their character ranges.
+    /// Returns an error if the lines are out of bounds or aren't valid utf8.
+    pub fn sentences_in_lines(
+        &self,
+        lines: Range<usize>,
+    ) -> Result<impl Iterator<Item = (Range<usize>, &str)> + '_, Error> {
+        if lines.start > lines.end {
+            return Err(Error::OutOfBounds {
+                index: lines.start,
+                len: lines.end,
+            });
+        }
+
+        let start = self.line_span(lines.start)?.start;
+        let end = if lines.end > lines.start {
+            self.line_span(lines.end - 1)?.end
+        } else {
+            start
+        };
+        let first_line = self.with_lines(|starts| starts.at(lines.start))?;
+        let text = decode_str(self.map.bytes(start..end)?, first_line)?;
+
+        let mut char_position = first_line.char_position;
+        Ok(text.split_sentence_bounds().map(move |sentence| {
+            let range = char_position..char_position + sentence.chars().count();
+            char_position = range.end;
+            (range, sentence)
+        }))
+    }
+}
@@ -0,0 +1,79 @@
+use {
+    crate::{Error, MappedFile, Mapping, TextSource},
+    std::{io, ops::Range, sync::RwLock},
+};
+
+/// The bytes of a mapped file up to the length it had when the snapshot was taken, see `MappedFile::snapshot`.
+/// It shares the mapping the file had then, so it keeps reading from it even after the file is refreshed
+/// or reloaded and mapped again
+pub struct Frozen {
+    source: Mapping,
+    /// How many bytes the source had
+    len: usize,
+}
+
+/// A view of a file as it was when the snapshot was taken, with a copy of everything indexed so far.
+/// It's a file in its own right, so every lookup works on it the same way
+pub type Snapshot = MappedFile<Frozen>;
+
+impl MappedFile {
+    /// Takes a snapshot of the file's length and everything indexed so far.
+    /// Reads through the snapshot never go past that length even if the file grows,
+    /// and what either of them indexes from then on isn't shared with the other.
+    /// The snapshot doesn't borrow the file, so the file can still be refreshed while it's around.
+    /// Returns an error if the file can't be opened again for the snapshot.
+    pub fn snapshot(&self) -> Result<Snapshot, Error> {
+        Ok(MappedFile {
+            map: Frozen {
+                source: self.map.share()?,
+                len: self.map.len(),
+            },
+            index: RwLock::new(self.index().clone()),
+            checkpoint_interval: self.checkpoint_interval,
+            line_endings: self.line_endings.clone(),
+            decode_policy: self.decode_policy,
+            check_stale: self.check_stale,
+        })
+    }
+}
+
+impl Frozen {
+    /// Returns the mapping the snapshot reads from, as it was when the snapshot was taken
+    pub fn source(&self) -> &Mapping {
+        &self.source
+    }
+}
+
+impl TextSource for Frozen {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn bytes(&self, range: Range<usize>) -> io::Result<&[u8]> {
+        if range.end > self.len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "read past the end of the snapshot",
+            ));
+        }
+        self.source.bytes(range)
+    }
+
+    fn with_bytes<T>(&self, range: Range<usize>, read: impl FnOnce(&[u8]) -> T) -> io::Result<T> {
+        if range.end > self.len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "read past the end of the snapshot",
+            ));
+        }
+        self.source.with_bytes(range, read)
+    }
+
+    fn kind(&self) -> &'static str {
+        self.source.kind()
+    }
+
+    fn is_stale(&self) -> io::Result<bool> {
+        self.source.is_stale()
+    }
+}
@@ -0,0 +1,313 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, VecDeque},
+    fs::{File, Metadata},
+    io::{self, Read, Seek, SeekFrom},
+    ops::Range,
+    time::SystemTime,
+};
+
+/// Somewhere the bytes of a text can be read from
+pub trait TextSource {
+    /// The length of the text in bytes
+    fn len(&self) -> usize;
+
+    /// The bytes in a range, which must be within `0..len()`
+    fn bytes(&self, range: Range<usize>) -> io::Result<&[u8]>;
+
+    /// Runs `read` on the bytes in a range, which must be within `0..len()`.
+    /// Unlike `bytes` nothing can borrow them once `read` returns,
+    /// so a source that only holds part of the text at a time can let go of them whenever it likes
+    fn with_bytes<T>(&self, range: Range<usize>, read: impl FnOnce(&[u8]) -> T) -> io::Result<T> {
+        Ok(read(self.bytes(range)?))
+    }
+
+    /// Frees anything the source has been holding on to only because `bytes` handed it out.
+    /// Nothing can still be borrowing it while the source is borrowed mutably
+    fn release(&mut self) {}
+
+    /// Whether the text is empty
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// A short name for where the bytes come from, for debugging
+    fn kind(&self) -> &'static str {
+        "memory"
+    }
+
+    /// Whether the bytes underneath might not match what was there when the source was opened,
+    /// like a file that has been written to since. Text that's only in memory can't be
+    fn is_stale(&self) -> io::Result<bool> {
+        Ok(false)
+    }
+}
+
+/// The length and modification time of a file, to tell whether it has changed since
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Stamp {
+    len: u64,
+    /// Not every platform or filesystem keeps modification times
+    modified: Option<SystemTime>,
+}
+
+impl Stamp {
+    /// Takes the stamp of a file as it is now
+    pub(crate) fn of(file: &File) -> io::Result<Stamp> {
+        Ok(Stamp::from_metadata(&file.metadata()?))
+    }
+
+    /// Gets the length of the file as a `usize`, which every position in it has to fit in.
+    /// Returns an error for a file bigger than the address space, like one over 4 GB on a 32 bit target
+    pub(crate) fn len(&self) -> io::Result<usize> {
+        usize::try_from(self.len).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the file is too big to address on this platform",
+            )
+        })
+    }
+
+    /// Gets the length of the file as a `u64`, which any file's length fits in
+    pub(crate) fn len_u64(&self) -> u64 {
+        self.len
+    }
+
+    pub(crate) fn from_metadata(metadata: &Metadata) -> Stamp {
+        Stamp {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        }
+    }
+
+    /// Checks whether the file has a different stamp now
+    pub(crate) fn changed(&self, file: &File) -> io::Result<bool> {
+        Ok(Stamp::of(file)? != *self)
+    }
+}
+
+/// Converts a position in a file to the `u64` offset `seek` and `mmap` take.
+/// A `usize` is never wider than 64 bits, so this can't wrap
+pub(crate) fn offset_u64(position: usize) -> u64 {
+    position as u64
+}
+
+/// In-memory text is just a slice of bytes
+macro_rules! slice_source {
+    ($($source:ty),*) => {
+        $(
+            impl TextSource for $source {
+                fn len(&self) -> usize {
+                    <[u8]>::len(self.as_ref())
+                }
+
+                fn bytes(&self, range: Range<usize>) -> io::Result<&[u8]> {
+                    Ok(&AsRef::<[u8]>::as_ref(self)[range])
+                }
+            }
+        )*
+    };
+}
+
+slice_source!(&[u8], Vec<u8>, Box<[u8]>, &str, String);
+
+/// A file read with `seek`/`read` a block at a time, keeping a cache of the blocks read most recently.
+///
+/// Bytes handed out by `bytes` can be borrowed for as long as the source is, so a block they came from
+/// is kept after dropping out of the cache until `release`, as are copies of ranges that cross blocks.
+/// Scanning the file for lines and characters only reads through `with_bytes`,
+/// so it never holds more than the cache
+pub struct BufferedSource {
+    /// The file being read
+    file: File,
+    /// The length of the file in bytes
+    len: usize,
+    /// The size of each block in bytes
+    block_size: usize,
+    /// The most blocks to keep in the cache at once
+    max_blocks: usize,
+
+    /// The cached blocks by their index in the file
+    blocks: RefCell<HashMap<usize, Block>>,
+    /// The order the cached blocks were read in, so the oldest can be dropped first
+    block_order: RefCell<VecDeque<usize>>,
+    /// Blocks dropped from the cache and copies of ranges across blocks that might still be borrowed
+    retired: RefCell<Vec<Box<[u8]>>>,
+    /// How many calls to `with_bytes` are still reading
+    reading: Cell<usize>,
+    /// What the file looked like when it was opened
+    stamp: Stamp,
+}
+
+/// A block of the file that's been read
+struct Block {
+    bytes: Box<[u8]>,
+    /// Whether `bytes` has handed out any of it
+    lent: bool,
+}
+
+impl BufferedSource {
+    /// Creates a new BufferedSource reading a File with a cache of 64 blocks of 64 KiB
+    pub fn new(file: File) -> io::Result<BufferedSource> {
+        BufferedSource::with_cache(file, 64 * 1024, 64)
+    }
+
+    /// Creates a new BufferedSource reading a File with a cache of 64 blocks of `block_size` bytes
+    pub fn with_block_size(file: File, block_size: usize) -> io::Result<BufferedSource> {
+        BufferedSource::with_cache(file, block_size, 64)
+    }
+
+    /// Creates a new BufferedSource reading a File with a cache of at most `max_blocks` blocks of `block_size` bytes
+    pub fn with_cache(
+        file: File,
+        block_size: usize,
+        max_blocks: usize,
+    ) -> io::Result<BufferedSource> {
+        let stamp = Stamp::of(&file)?;
+        Ok(BufferedSource {
+            file,
+            len: stamp.len()?,
+            block_size: block_size.max(1),
+            max_blocks: max_blocks.max(1),
+            blocks: RefCell::new(HashMap::new()),
+            block_order: RefCell::new(VecDeque::new()),
+            retired: RefCell::new(Vec::new()),
+            reading: Cell::new(0),
+            stamp,
+        })
+    }
+
+    /// Returns the length of the file in bytes as a `u64`
+    pub fn len_u64(&self) -> u64 {
+        self.stamp.len_u64()
+    }
+
+    /// Returns how many bytes of the file are held in memory, counting ones kept until `release`
+    pub fn held_bytes(&self) -> usize {
+        let blocks = self.blocks.borrow();
+        let retired = self.retired.borrow();
+        blocks
+            .values()
+            .map(|block| block.bytes.len())
+            .sum::<usize>()
+            + retired.iter().map(|bytes| bytes.len()).sum::<usize>()
+    }
+
+    /// Makes sure a block is cached, reading it from the file if it isn't, and returns its bytes,
+    /// marking them as lent if they're for `bytes`
+    fn load_block(&self, block: usize, lend: bool) -> io::Result<&[u8]> {
+        let mut blocks = self.blocks.borrow_mut();
+        if !blocks.contains_key(&block) {
+            let start = block * self.block_size;
+            let mut contents = vec![0; self.block_size.min(self.len - start)].into_boxed_slice();
+
+            let mut file = &self.file;
+            file.seek(SeekFrom::Start(offset_u64(start)))?;
+            file.read_exact(&mut contents)?;
+
+            // Make room by dropping the block we read longest ago, which can only be freed if nothing could still be reading it
+            let mut block_order = self.block_order.borrow_mut();
+            if block_order.len() >= self.max_blocks {
+                if let Some(oldest) = block_order
+                    .pop_front()
+                    .and_then(|oldest| blocks.remove(&oldest))
+                {
+                    if oldest.lent || self.reading.get() > 0 {
+                        self.retired.borrow_mut().push(oldest.bytes);
+                    }
+                }
+            }
+
+            blocks.insert(
+                block,
+                Block {
+                    bytes: contents,
+                    lent: false,
+                },
+            );
+            block_order.push_back(block);
+        }
+
+        let block = blocks.get_mut(&block).unwrap();
+        block.lent |= lend;
+        // A block's bytes don't move when the map holding it does, and they're only freed once nothing can be reading them
+        Ok(unsafe { &*(&*block.bytes as *const [u8]) })
+    }
+
+    /// Returns the bytes in a range if they're all in one block
+    fn in_block(&self, range: Range<usize>, lend: bool) -> io::Result<Option<&[u8]>> {
+        if range.is_empty() {
+            return Ok(Some(&[]));
+        }
+
+        let block = range.start / self.block_size;
+        if (range.end - 1) / self.block_size != block {
+            return Ok(None);
+        }
+        let start = block * self.block_size;
+        let bytes = self.load_block(block, lend)?;
+        Ok(Some(&bytes[range.start - start..range.end - start]))
+    }
+
+    /// Copies the bytes in a range that crosses blocks into one piece
+    fn copy(&self, range: Range<usize>) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(range.len());
+
+        let mut position = range.start;
+        while position < range.end {
+            let block = position / self.block_size;
+            let offset = position - block * self.block_size;
+            let contents = self.load_block(block, false)?;
+            let take = (range.end - position).min(contents.len() - offset);
+            bytes.extend_from_slice(&contents[offset..offset + take]);
+            position += take;
+        }
+
+        Ok(bytes)
+    }
+}
+
+impl TextSource for BufferedSource {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn bytes(&self, range: Range<usize>) -> io::Result<&[u8]> {
+        if let Some(bytes) = self.in_block(range.clone(), true)? {
+            return Ok(bytes);
+        }
+
+        // The copy is kept until `release` the same as a block that's been lent
+        let copy = self.copy(range)?.into_boxed_slice();
+        let bytes = unsafe { &*(&*copy as *const [u8]) };
+        self.retired.borrow_mut().push(copy);
+        Ok(bytes)
+    }
+
+    fn with_bytes<T>(&self, range: Range<usize>, read: impl FnOnce(&[u8]) -> T) -> io::Result<T> {
+        match self.in_block(range.clone(), false)? {
+            Some(bytes) => {
+                self.reading.set(self.reading.get() + 1);
+                let read = read(bytes);
+                self.reading.set(self.reading.get() - 1);
+                Ok(read)
+            }
+            None => Ok(read(&self.copy(range)?)),
+        }
+    }
+
+    fn release(&mut self) {
+        self.retired.get_mut().clear();
+        for block in self.blocks.get_mut().values_mut() {
+            block.lent = false;
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        "buffered"
+    }
+
+    fn is_stale(&self) -> io::Result<bool> {
+        self.stamp.changed(&self.file)
+    }
+}
@@ -0,0 +1,96 @@
+use crate::{stretch_end, CharPosition, Error, MappedFile, TextSource, SCAN_STEP};
+
+/// Counts of what's in a whole file, like `wc` gives, see `MappedFile::stats`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FileStats {
+    /// The number of bytes in the file
+    pub bytes: usize,
+    /// The number of characters in the file
+    pub chars: usize,
+    /// The number of lines in the file, counted the same way as `MappedFile::line_count`
+    pub lines: usize,
+    /// The number of runs of characters that aren't whitespace
+    pub words: usize,
+    /// The length of the longest line in characters, not counting its terminator
+    pub max_line_len: usize,
+}
+
+impl<S: TextSource> MappedFile<S> {
+    /// Counts the bytes, characters, lines and words in the file and finds the length of the longest line,
+    /// all in one pass. The counts are cached until the file is refreshed or reloaded
+    /// or its line endings or decode policy change.
+    /// Returns an error if the file isn't valid utf8 unless decoding lossily.
+    pub fn stats(&self) -> Result<FileStats, Error> {
+        self.check_fresh()?;
+        if let Some(stats) = self.index().stats {
+            return Ok(stats);
+        }
+
+        let len = self.map.len();
+        let mut stats = FileStats {
+            bytes: len,
+            lines: 1,
+            ..FileStats::default()
+        };
+        let mut in_word = false;
+        let mut line_len = 0;
+        // The byte before the window, which might be a \r before a \n
+        let mut last_byte = None;
+        let mut position = CharPosition {
+            byte_position: 0,
+            char_position: 0,
+        };
+        while position.byte_position < len {
+            let window_end = (position.byte_position + SCAN_STEP).min(len);
+            // Grab a few bytes past the window so we can see what follows it
+            let range = position.byte_position..(window_end + 4).min(len);
+            let cut = self.map.with_bytes(range, |bytes| {
+                let cut = stretch_end(
+                    bytes,
+                    window_end - position.byte_position,
+                    window_end == len,
+                );
+
+                for (offset, c, width) in
+                    self.decode_policy.char_indices(&bytes[..cut], position)?
+                {
+                    stats.chars += 1;
+                    let next = bytes.get(offset + width).copied();
+                    if self.line_endings.ends_line(c, next) {
+                        // The terminator's characters have all been counted towards the line
+                        let previous = match offset {
+                            0 => last_byte,
+                            _ => Some(bytes[offset - 1]),
+                        };
+                        let terminator = self.line_endings.terminator(c, previous).char_len();
+                        stats.max_line_len = stats.max_line_len.max(line_len + 1 - terminator);
+                        stats.lines += 1;
+                        line_len = 0;
+                    } else {
+                        line_len += 1;
+                    }
+
+                    match c.is_whitespace() {
+                        true => in_word = false,
+                        false if !in_word => {
+                            stats.words += 1;
+                            in_word = true;
+                        }
+                        false => {}
+                    }
+                }
+
+                last_byte = bytes[..cut].last().copied();
+                Ok::<_, Error>(cut)
+            })??;
+            position = CharPosition {
+                byte_position: position.byte_position + cut,
+                char_position: stats.chars,
+            };
+        }
+        stats.max_line_len = stats.max_line_len.max(line_len);
+
+        self.index_mut().stats = Some(stats);
+        Ok(stats)
+    }
+}
@@ -0,0 +1,83 @@
+use crate::{Error, MappedFile, TextSource};
+
+/// Returns the visual column a character starting at a visual column ends at,
+/// with a tab going on to the next tab stop
+fn advance(visual: usize, c: char, tab_width: usize) -> usize {
+    match c {
+        '\t' if tab_width > 0 => (visual / tab_width + 1) * tab_width,
+        '\t' => visual,
+        _ => visual + 1,
+    }
+}
+
+impl<S: TextSource> MappedFile<S> {
+    /// Returns the zero-based visual column of a character column on a line, with each tab before it
+    /// taking up the columns to the next multiple of `tab_width` and every other character one column,
+    /// like the caret under an error message. A tab width of zero makes tabs take up no columns.
+    /// The column just past the last character of the line is allowed.
+    /// Returns an error if the line or column is out of bounds or the line isn't valid utf8.
+    pub fn visual_column(
+        &self,
+        line: usize,
+        column: usize,
+        tab_width: usize,
+    ) -> Result<usize, Error> {
+        let mut visual = 0;
+        let mut chars = 0;
+        for c in self.line_chars(line)? {
+            if chars == column {
+                return Ok(visual);
+            }
+            visual = advance(visual, c, tab_width);
+            chars += 1;
+        }
+
+        match column == chars {
+            true => Ok(visual),
+            false => Err(Error::OutOfBounds {
+                index: column,
+                len: chars,
+            }),
+        }
+    }
+
+    /// Returns the character column on a line that's shown at a zero-based visual column, see `visual_column`.
+    /// A visual column inside a tab is the tab's column.
+    /// The visual column just past the end of the line resolves to the column just past the last character.
+    /// Returns an error if the line or visual column is out of bounds or the line isn't valid utf8.
+    pub fn column_at_visual(
+        &self,
+        line: usize,
+        visual_column: usize,
+        tab_width: usize,
+    ) -> Result<usize, Error> {
+        let mut visual = 0;
+        let mut chars = 0;
+        for c in self.line_chars(line)? {
+            let next = advance(visual, c, tab_width);
+            if visual_column < next {
+                return Ok(chars);
+            }
+            visual = next;
+            chars += 1;
+        }
+
+        match visual_column == visual {
+            true => Ok(chars),
+            false => Err(Error::OutOfBounds {
+                index: visual_column,
+                len: visual,
+            }),
+        }
+    }
+
+    /// Decodes the characters of a line without its terminator
+    fn line_chars(&self, line: usize) -> Result<Vec<char>, Error> {
+        let (text, _) = self.line_parts(line)?;
+        let start = self.with_lines(|lines| lines.at(line))?;
+        self.map.with_bytes(text, |bytes| {
+            let chars = self.decode_policy.char_indices(bytes, start)?;
+            Ok(chars.map(|(_, c, _)| c).collect())
+        })?
+    }
+}
@@ -0,0 +1,71 @@
+use {
+    crate::{decode_char, DecodePolicy, Error, MappedFile, TextSource},
+    std::ops::Range,
+};
+
+/// A character, or a byte that isn't part of one, for files with binary mixed in with the text.
+/// See `DecodePolicy::Bytes`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Unit {
+    /// A valid character
+    Char(char),
+    /// A byte that isn't valid utf8
+    Byte(u8),
+}
+
+impl Unit {
+    /// Returns the character, or `None` for a byte
+    pub fn as_char(self) -> Option<char> {
+        match self {
+            Unit::Char(c) => Some(c),
+            Unit::Byte(_) => None,
+        }
+    }
+
+    /// Returns the character, or U+FFFD for a byte
+    pub fn to_char_lossy(self) -> char {
+        self.as_char().unwrap_or(char::REPLACEMENT_CHARACTER)
+    }
+}
+
+impl<S: TextSource> MappedFile<S> {
+    /// Returns the character at the given index, or the byte there if it isn't valid utf8.
+    /// Each invalid byte has an index of its own with `DecodePolicy::Bytes`, and decoding lossily
+    /// gives the first byte of an invalid sequence.
+    /// Returns an error if the index is out of bounds, or if the file decodes strictly and isn't valid utf8.
+    pub fn unit_at(&self, index: usize) -> Result<Unit, Error> {
+        let position = self.position_at(index)?;
+        let start = position.byte_position;
+        let unit =
+            self.map.with_bytes(
+                start..(start + 4).min(self.map.len()),
+                |bytes| match decode_char(bytes) {
+                    Some((c, _)) => Some(Unit::Char(c)),
+                    None if self.decode_policy == DecodePolicy::Strict || bytes.is_empty() => None,
+                    None => Some(Unit::Byte(bytes[0])),
+                },
+            )?;
+        match unit {
+            Some(unit) => Ok(unit),
+            None => self.char_at(position).map(Unit::Char),
+        }
+    }
+
+    /// Returns the characters and invalid bytes in a range, see `unit_at`.
+    /// Returns an error if the range is out of bounds, or if the file decodes strictly and isn't valid utf8.
+    pub fn units_range(&self, range: Range<usize>) -> Result<Vec<Unit>, Error> {
+        let (start, end) = self.range_positions(range)?;
+        let mut units = Vec::new();
+        self.read_stretches(start, end.byte_position, |bytes, position| {
+            let before = units.len();
+            units.extend(self.decode_policy.char_indices(bytes, position)?.map(
+                |(offset, c, width)| match decode_char(&bytes[offset..offset + width]) {
+                    Some(_) => Unit::Char(c),
+                    None => Unit::Byte(bytes[offset]),
+                },
+            ));
+            Ok(units.len() - before)
+        })?;
+        Ok(units)
+    }
+}
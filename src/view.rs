@@ -0,0 +1,95 @@
+use {
+    crate::{Error, MappedFile, Mapping, TextSource},
+    std::{io, ops::Range},
+};
+
+/// The bytes of part of another source, see `MappedFile::view`
+pub struct Region<'a, S: TextSource = Mapping> {
+    source: &'a S,
+    /// Where the part starts and ends in the source, in bytes
+    bytes: Range<usize>,
+    /// Where the part starts and ends in the source, in characters
+    chars: Range<usize>,
+}
+
+/// A range of characters in a file that can be looked things up in as if it were a file of its own,
+/// with indices, lines and columns counted from the start of the range.
+/// It has its own index, so lookups in it don't grow the file's
+pub type FileView<'a, S = Mapping> = MappedFile<Region<'a, S>>;
+
+impl<S: TextSource> MappedFile<S> {
+    /// Creates a view of the characters in a range, like a single record in a bigger file,
+    /// with the same settings as the file.
+    /// Returns an error if the range is out of bounds.
+    pub fn view(&self, range: Range<usize>) -> Result<FileView<'_, S>, Error> {
+        let (start, end) = self.range_positions(range)?;
+        let mut view = MappedFile::from_source(Region {
+            source: &self.map,
+            bytes: start.byte_position..end.byte_position,
+            chars: start.char_position..end.char_position,
+        });
+        view.set_line_endings(self.line_endings.clone());
+        view.set_checkpoint_interval(self.checkpoint_interval);
+        view.set_decode_policy(self.decode_policy);
+        view.set_compact_index(self.compact_index());
+        view.set_index_memory_limit(self.index_memory_limit());
+        view.set_check_stale(self.check_stale);
+        Ok(view)
+    }
+}
+
+impl<'a, S: TextSource> Region<'a, S> {
+    /// Returns the source the view is of
+    pub fn source(&self) -> &'a S {
+        self.source
+    }
+
+    /// Returns where the view starts and ends in the source, in bytes
+    pub fn byte_range(&self) -> Range<usize> {
+        self.bytes.clone()
+    }
+
+    /// Returns the indices in the source of the characters in the view,
+    /// so an index into the view can be turned back into one into the file by adding the start
+    pub fn char_range(&self) -> Range<usize> {
+        self.chars.clone()
+    }
+}
+
+impl<S: TextSource> TextSource for Region<'_, S> {
+    fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    fn bytes(&self, range: Range<usize>) -> io::Result<&[u8]> {
+        if range.end > self.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "read past the end of the view",
+            ));
+        }
+        self.source
+            .bytes(self.bytes.start + range.start..self.bytes.start + range.end)
+    }
+
+    fn with_bytes<T>(&self, range: Range<usize>, read: impl FnOnce(&[u8]) -> T) -> io::Result<T> {
+        if range.end > self.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "read past the end of the view",
+            ));
+        }
+        self.source.with_bytes(
+            self.bytes.start + range.start..self.bytes.start + range.end,
+            read,
+        )
+    }
+
+    fn kind(&self) -> &'static str {
+        self.source.kind()
+    }
+
+    fn is_stale(&self) -> io::Result<bool> {
+        self.source.is_stale()
+    }
+}
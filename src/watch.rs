@@ -0,0 +1,130 @@
+use {
+    crate::{source::offset_u64, Appended, Error, MappedFile},
+    notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher},
+    std::{
+        fs::{File, Metadata},
+        io,
+        path::Path,
+        sync::{Arc, PoisonError, RwLock, RwLockReadGuard},
+    },
+};
+
+/// What happened to a watched file, see `MappedFile::watch`
+#[derive(Debug)]
+pub enum Change {
+    /// Text was added to the end of the file and has been indexed
+    Appended(Appended),
+    /// The file got shorter, so it has been mapped again and everything indexed was thrown away
+    Truncated,
+    /// A different file was put at the path, like when a log is rotated,
+    /// so that one has been mapped instead and everything indexed was thrown away
+    Replaced,
+    /// The change couldn't be picked up
+    Failed(Error),
+}
+
+/// A file that keeps itself up to date as it changes until it's dropped, see `MappedFile::watch`
+pub struct WatchedFile {
+    file: Arc<RwLock<MappedFile>>,
+    _watcher: RecommendedWatcher,
+}
+
+impl MappedFile {
+    /// Watches the file for changes, picking up whatever is appended to it, mapping it again if it's truncated
+    /// or replaced at its path and calling `on_change` with what happened each time.
+    /// The file has to have been opened by its path, see `MappedFile::open_path`.
+    /// The callback runs on the watcher's own thread.
+    /// Returns an error if the file wasn't opened by its path or can't be watched.
+    pub fn watch(
+        self,
+        mut on_change: impl FnMut(Change) + Send + 'static,
+    ) -> Result<WatchedFile, Error> {
+        let path = self.map.path().map(Path::to_path_buf).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "only files opened by their path can be watched",
+            )
+        })?;
+        // Watching the directory sees the file being replaced as well as written to
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => ".".into(),
+        };
+        let name = path.file_name().map(ToOwned::to_owned);
+
+        let file = Arc::new(RwLock::new(self));
+        let watched = Arc::clone(&file);
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => return on_change(Change::Failed(Error::Io(io::Error::other(e)))),
+            };
+            let ours = event
+                .paths
+                .iter()
+                .any(|changed| changed.file_name() == name.as_deref());
+            if !ours || matches!(event.kind, EventKind::Access(_)) {
+                return;
+            }
+
+            let mut file = watched.write().unwrap_or_else(PoisonError::into_inner);
+            if let Some(change) = file.pick_up_change(&path) {
+                on_change(change);
+            }
+        })
+        .map_err(|e| Error::Io(io::Error::other(e)))?;
+        watcher
+            .watch(&directory, RecursiveMode::NonRecursive)
+            .map_err(|e| Error::Io(io::Error::other(e)))?;
+
+        Ok(WatchedFile {
+            file,
+            _watcher: watcher,
+        })
+    }
+
+    /// Works out how the file has changed and catches up with it, returning what happened if anything did
+    fn pick_up_change(&mut self, path: &Path) -> Option<Change> {
+        // The file might be missing for a moment while it's being replaced
+        let at_path = File::open(path).and_then(|file| file.metadata()).ok()?;
+        let ours = match self.map.file.metadata() {
+            Ok(ours) => ours,
+            Err(e) => return Some(Change::Failed(e.into())),
+        };
+
+        let change = if !same_file(&at_path, &ours) {
+            self.reload().map(|_| Change::Replaced)
+        } else if ours.len() < offset_u64(self.map.file_len()) {
+            self.reload().map(|_| Change::Truncated)
+        } else {
+            match self.refresh() {
+                Ok(appended) if appended.chars.is_empty() => return None,
+                appended => appended.map(Change::Appended),
+            }
+        };
+        Some(change.unwrap_or_else(Change::Failed))
+    }
+}
+
+impl WatchedFile {
+    /// Locks the file to look things up in it. Changes are picked up once the lock is let go
+    pub fn read(&self) -> RwLockReadGuard<'_, MappedFile> {
+        self.file.read().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Checks whether two sets of metadata are for the same file
+#[cfg(unix)]
+fn same_file(a: &Metadata, b: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+/// Without inode numbers a file that's been replaced can only be told apart by being created at a different time
+#[cfg(not(unix))]
+fn same_file(a: &Metadata, b: &Metadata) -> bool {
+    match (a.created(), b.created()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => true,
+    }
+}
@@ -0,0 +1,67 @@
+use {
+    crate::{CharPosition, Error, MappedFile, TextSource},
+    std::ops::Range,
+    unicode_width::UnicodeWidthChar,
+};
+
+/// How many terminal columns a character takes up: two for wide characters like CJK,
+/// none for combining marks and control characters, and one for everything else
+fn char_width(c: char) -> usize {
+    c.width().unwrap_or(0)
+}
+
+impl<S: TextSource> MappedFile<S> {
+    /// Returns how many terminal columns the characters in a range take up, adding up the width of each.
+    /// Invalid bytes take up a column for each U+FFFD they decode to if the file decodes lossily.
+    /// Returns an error if the range is out of bounds or isn't valid utf8.
+    pub fn display_width(&self, range: Range<usize>) -> Result<usize, Error> {
+        let (start, end) = self.range_positions(range)?;
+        let mut width = 0;
+        self.read_stretches(start, end.byte_position, |bytes, position| {
+            let mut chars = 0;
+            for (_, c, _) in self.decode_policy.char_indices(bytes, position)? {
+                width += char_width(c);
+                chars += 1;
+            }
+            Ok(chars)
+        })?;
+        Ok(width)
+    }
+
+    /// Finds the character shown at a zero-based terminal column of a line,
+    /// which for the second column of a wide character is the wide character.
+    /// Characters that take up no columns are never at one.
+    /// The column just past the end of the line resolves to the line's terminator, like `position_of`.
+    /// Returns an error if the line or column is out of bounds or the line isn't valid utf8.
+    pub fn char_at_display_col(&self, line: usize, column: usize) -> Result<CharPosition, Error> {
+        let (text, _) = self.line_parts(line)?;
+        let start = self.with_lines(|lines| lines.at(line))?;
+        let end = text.end;
+        self.map.with_bytes(text, |bytes| {
+            let mut columns = 0;
+            let mut chars = 0;
+            for (byte_offset, c, _) in self.decode_policy.char_indices(bytes, start)? {
+                let width = char_width(c);
+                if column < columns + width {
+                    return Ok(CharPosition {
+                        byte_position: start.byte_position + byte_offset,
+                        char_position: start.char_position + chars,
+                    });
+                }
+                columns += width;
+                chars += 1;
+            }
+
+            match column == columns {
+                true => Ok(CharPosition {
+                    byte_position: end,
+                    char_position: start.char_position + chars,
+                }),
+                false => Err(Error::OutOfBounds {
+                    index: column,
+                    len: columns,
+                }),
+            }
+        })?
+    }
+}
@@ -0,0 +1,158 @@
+use {
+    crate::{
+        source::{offset_u64, Stamp},
+        TextSource, SCAN_STEP,
+    },
+    memmap2::{Mmap, MmapOptions},
+    std::{
+        cell::{Cell, RefCell},
+        fs::File,
+        io,
+        ops::Range,
+    },
+};
+
+/// Windows start on a multiple of this, which is a whole number of pages everywhere (64 KiB is the Windows granularity)
+const WINDOW_ALIGN: usize = 64 * 1024;
+
+/// The smallest window, which is a few of the stretches scans read at a time so they don't have to slide it every step
+const MIN_WINDOW: usize = 4 * SCAN_STEP;
+
+/// A file mapped a window at a time, for when mapping all of it would take up too much of the address space.
+/// The window slides to whatever part of the file a read needs, so `MappedFile::from_source`
+/// gives it every lookup a file mapped all at once has.
+/// Positions in it are still `usize`s though, so it can't open a file bigger than that can count,
+/// like one over 4 GB on a 32 bit target.
+///
+/// Bytes handed out by `bytes` can be borrowed for as long as the source is, so a window they came from
+/// stays mapped after sliding away until `release`. Scanning the file for lines and characters only reads
+/// through `with_bytes`, so it never keeps more than the one window mapped
+pub struct Windowed {
+    /// The file being read
+    file: File,
+    /// The length of the file in bytes
+    len: usize,
+    /// How many bytes each window covers
+    size: usize,
+
+    /// The window that's mapped now
+    window: RefCell<Option<Window>>,
+    /// Windows slid away from that might still be borrowed
+    retired: RefCell<Vec<Mmap>>,
+    /// How many calls to `with_bytes` are still reading
+    reading: Cell<usize>,
+    /// What the file looked like when it was opened
+    stamp: Stamp,
+}
+
+/// A part of the file that's mapped
+struct Window {
+    /// Where the window starts in the file
+    start: usize,
+    map: Mmap,
+    /// Whether `bytes` has handed out any of it
+    lent: bool,
+}
+
+impl Windowed {
+    /// Creates a new Windowed source that maps `window_size` bytes of a File at a time.
+    /// The window is at least 256 KiB, and grows if a single read needs more than that.
+    /// Returns an error if the file is too big for positions in it to fit in a `usize`
+    pub fn new(file: File, window_size: usize) -> io::Result<Windowed> {
+        let stamp = Stamp::of(&file)?;
+        Ok(Windowed {
+            file,
+            len: stamp.len()?,
+            size: window_size.max(MIN_WINDOW),
+            window: RefCell::new(None),
+            retired: RefCell::new(Vec::new()),
+            reading: Cell::new(0),
+            stamp,
+        })
+    }
+
+    /// Returns the length of the file in bytes as a `u64`
+    pub fn len_u64(&self) -> u64 {
+        self.stamp.len_u64()
+    }
+
+    /// Returns how many windows are mapped, counting the ones kept until `release`
+    pub fn mapped_windows(&self) -> usize {
+        self.retired.borrow().len() + self.window.borrow().is_some() as usize
+    }
+
+    /// Slides the window over a range if it isn't already and returns the bytes in it,
+    /// marking them as lent if they're for `bytes`
+    fn read(&self, range: Range<usize>, lend: bool) -> io::Result<&[u8]> {
+        if range.is_empty() {
+            return Ok(&[]);
+        }
+
+        let mut window = self.window.borrow_mut();
+        let within = |window: &Window| {
+            window.start <= range.start && range.end <= window.start + window.map.len()
+        };
+        if !window.as_ref().is_some_and(within) {
+            // Mappings have to start on a boundary
+            let start = range.start - range.start % WINDOW_ALIGN;
+            let len = self.size.max(range.end - start).min(self.len - start);
+            let map = unsafe {
+                MmapOptions::new()
+                    .offset(offset_u64(start))
+                    .len(len)
+                    .map(&self.file)?
+            };
+
+            let old = window.replace(Window {
+                start,
+                map,
+                lent: false,
+            });
+            // A window can only be unmapped if nothing could still be reading it
+            if let Some(old) = old {
+                if old.lent || self.reading.get() > 0 {
+                    self.retired.borrow_mut().push(old.map);
+                }
+            }
+        }
+
+        let window = window.as_mut().unwrap();
+        window.lent |= lend;
+        let bytes = &window.map[range.start - window.start..range.end - window.start];
+        // A mapping doesn't move when the window holding it does, and it's only unmapped once nothing can be reading it
+        Ok(unsafe { &*(bytes as *const [u8]) })
+    }
+}
+
+impl TextSource for Windowed {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn bytes(&self, range: Range<usize>) -> io::Result<&[u8]> {
+        self.read(range, true)
+    }
+
+    fn with_bytes<T>(&self, range: Range<usize>, read: impl FnOnce(&[u8]) -> T) -> io::Result<T> {
+        let bytes = self.read(range, false)?;
+        self.reading.set(self.reading.get() + 1);
+        let read = read(bytes);
+        self.reading.set(self.reading.get() - 1);
+        Ok(read)
+    }
+
+    fn release(&mut self) {
+        self.retired.get_mut().clear();
+        if let Some(window) = self.window.get_mut() {
+            window.lent = false;
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        "windowed"
+    }
+
+    fn is_stale(&self) -> io::Result<bool> {
+        self.stamp.changed(&self.file)
+    }
+}
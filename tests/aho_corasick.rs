@@ -0,0 +1,53 @@
+#![cfg(feature = "aho-corasick")]
+
+use std::{fs::File, io::Write};
+
+use random_access_unicode::*;
+
+#[test]
+pub fn test_find_any() {
+    let r = MappedFile::from("ERROR: disk füll\nWARN: slow\nerror: ignored\nERROR: WARNING");
+
+    let hits: Vec<_> = r
+        .find_any(&["ERROR", "WARN", "WARNING", "füll"])
+        .unwrap()
+        .map(|(pattern, position)| (pattern, position.char_position, position.byte_position))
+        .collect();
+    // WARN is listed first so it wins over WARNING
+    assert_eq!(
+        hits,
+        [
+            (0, 0, 0),
+            (3, 12, 12),
+            (1, 17, 18),
+            (0, 43, 44),
+            (1, 50, 51)
+        ]
+    );
+
+    assert_eq!(r.find_any(&["nothing"]).unwrap().count(), 0);
+    assert_eq!(r.find_any(&[]).unwrap().count(), 0);
+}
+
+#[test]
+pub fn test_find_any_windowed() {
+    let mut file = File::create("test_find_any_windowed.txt").unwrap();
+    for line in 0..50000 {
+        writeln!(file, "línea {} €😀", line).unwrap();
+    }
+    file.flush().unwrap();
+
+    // Matches straddle the stretches searched at a time
+    let source = Windowed::new(File::open("test_find_any_windowed.txt").unwrap(), 0).unwrap();
+    let r = MappedFile::from_source(source);
+    let m = MappedFile::new(File::open("test_find_any_windowed.txt").unwrap()).unwrap();
+    let patterns = ["😀\nlínea 1", "€", "9\n"];
+    let hits = |f: &dyn Fn() -> Vec<(usize, CharPosition)>| {
+        f().into_iter()
+            .map(|(pattern, position)| (pattern, position.char_position, position.byte_position))
+            .collect::<Vec<_>>()
+    };
+    let windowed = hits(&|| r.find_any(&patterns).unwrap().collect());
+    assert_eq!(windowed, hits(&|| m.find_any(&patterns).unwrap().collect()));
+    assert_eq!(r.map.mapped_windows(), 1);
+}
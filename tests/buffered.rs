@@ -0,0 +1,67 @@
+use std::fs::File;
+use std::io::Write;
+
+use random_access_unicode::*;
+
+/// Reads some characters and lines through any backend
+fn read_back(r: &mut impl UnicodeAccess) -> (String, Vec<String>) {
+    let chars = (0..r.char_count().unwrap())
+        .map(|i| r.unicode_at(i).unwrap())
+        .collect();
+    let lines = (0..r.line_count().unwrap())
+        .map(|i| r.line_string(i).unwrap())
+        .collect();
+    (chars, lines)
+}
+
+#[test]
+pub fn test_buffered() {
+    let mut file = File::create("test_buffered.txt").unwrap();
+    write!(file, "añb\n€😀 straddles\nblocks\n").unwrap();
+    file.flush().unwrap();
+
+    // Tiny blocks so characters and lines cross them, and few enough that some get dropped
+    let mut r = BufferedFile::with_cache(File::open("test_buffered.txt").unwrap(), 3, 2).unwrap();
+
+    assert_eq!(r.unicode_at(5).unwrap(), '😀');
+    assert_eq!(r.line_string(1).unwrap(), "€😀 straddles");
+    assert_eq!(r.unicode_at(1).unwrap(), 'ñ');
+    assert!(matches!(r.unicode_at(25), Err(Error::OutOfBounds { .. })));
+
+    let mut m = MappedFile::new(File::open("test_buffered.txt").unwrap()).unwrap();
+    assert_eq!(read_back(&mut r), read_back(&mut m));
+}
+
+#[test]
+pub fn test_buffered_cache() {
+    let mut file = File::create("test_buffered_cache.txt").unwrap();
+    for line in 0..100 {
+        writeln!(file, "línea {} €😀", line).unwrap();
+    }
+    file.flush().unwrap();
+
+    let mut r =
+        BufferedFile::with_cache(File::open("test_buffered_cache.txt").unwrap(), 16, 4).unwrap();
+
+    // Scanning only keeps what fits in the cache
+    r.build_index().unwrap();
+    assert!(r.map.held_bytes() <= 16 * 4);
+
+    // Lines that were borrowed are kept until they're released
+    for line in 0..100 {
+        assert_eq!(r.line_at(line).unwrap(), format!("línea {} €😀", line));
+    }
+    assert!(r.map.held_bytes() > 16 * 4);
+    r.release();
+    assert!(r.map.held_bytes() <= 16 * 4);
+
+    assert_eq!(r.line_at(42).unwrap(), "línea 42 €😀");
+    assert_eq!(r.unicode_at(9).unwrap(), '😀');
+
+    // Whole-file scans don't hold on to anything past the cache
+    r.release();
+    assert_eq!(r.stats().unwrap().lines, 101);
+    assert_eq!(r.find_all("😀").count(), 100);
+    assert!(r.validate().is_ok());
+    assert!(r.map.held_bytes() <= 16 * 4);
+}
@@ -0,0 +1,21 @@
+#![cfg(feature = "categories")]
+
+use random_access_unicode::*;
+
+#[test]
+pub fn test_category_histogram() {
+    let r = MappedFile::from("Hello, Wörld 42!\u{7}\u{301}");
+    let counts = r.category_histogram(0..18).unwrap();
+    let expected = [
+        ("Cc", 1),
+        ("Ll", 8),
+        ("Lu", 2),
+        ("Mn", 1),
+        ("Nd", 2),
+        ("Po", 2),
+        ("Zs", 2),
+    ];
+    assert_eq!(counts.into_iter().collect::<Vec<_>>(), expected);
+    assert_eq!(r.category_histogram(7..12).unwrap()["Ll"], 4);
+    assert!(r.category_histogram(0..19).is_err());
+}
@@ -0,0 +1,53 @@
+use std::fs::File;
+use std::io::Write;
+
+use random_access_unicode::*;
+
+#[test]
+pub fn test_chained() {
+    let texts = ["first\nsëcond\n", "thi", "rd\nfourth", "", "\nfifth"];
+    let files = texts
+        .iter()
+        .enumerate()
+        .map(|(number, text)| {
+            let path = format!("test_chained_{}.txt", number);
+            let mut file = File::create(&path).unwrap();
+            write!(file, "{}", text).unwrap();
+            file.flush().unwrap();
+            MappedFile::new(File::open(&path).unwrap()).unwrap()
+        })
+        .collect();
+    let mut chained = ChainedFile::new(files);
+
+    // It reads the same as all the text in one file
+    let whole = InMemoryFile::from(texts.concat());
+    assert_eq!(chained.char_count().unwrap(), whole.char_count().unwrap());
+    assert_eq!(chained.line_count().unwrap(), whole.line_count().unwrap());
+    for line in 0..whole.line_count().unwrap() {
+        assert_eq!(
+            chained.line_string(line).unwrap(),
+            whole.line_at(line).unwrap()
+        );
+    }
+    assert_eq!(chained.string_range(10..20).unwrap(), "nd\nthird\nf");
+    assert_eq!(chained.unicode_at(7).unwrap(), 'ë');
+    assert_eq!(UnicodeAccess::unicode_at(&mut chained, 14).unwrap(), 'h');
+
+    // Boundaries between the files
+    assert_eq!(chained.locate(13).unwrap(), (1, 0));
+    assert_eq!(chained.locate(16).unwrap(), (2, 0));
+    assert_eq!(chained.locate(31).unwrap(), (4, 6));
+    assert_eq!(chained.file_range(2).unwrap(), 16..25);
+    assert_eq!(chained.file_range(3).unwrap(), 25..25);
+    assert!(matches!(
+        chained.locate(32),
+        Err(Error::OutOfBounds { index: 32, len: 31 })
+    ));
+    assert!(chained.file_range(5).is_err());
+    assert!(chained.line_string(5).is_err());
+    assert_eq!(chained.into_files().len(), 5);
+
+    let empty = ChainedFile::<Mapping>::new(Vec::new());
+    assert_eq!(empty.char_count().unwrap(), 0);
+    assert_eq!(empty.line_string(0).unwrap(), "");
+}
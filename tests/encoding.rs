@@ -0,0 +1,291 @@
+use std::fs::File;
+use std::io::Write;
+
+use random_access_unicode::*;
+
+/// Encodes text as UTF-16 with the bytes of each code unit in the given order
+fn utf16(text: &str, little_endian: bool) -> Vec<u8> {
+    text.encode_utf16()
+        .flat_map(|unit| match little_endian {
+            true => unit.to_le_bytes(),
+            false => unit.to_be_bytes(),
+        })
+        .collect()
+}
+
+/// Encodes text as UTF-32 with the bytes of each code unit in the given order
+fn utf32(text: &str, little_endian: bool) -> Vec<u8> {
+    text.chars()
+        .flat_map(|c| match little_endian {
+            true => (c as u32).to_le_bytes(),
+            false => (c as u32).to_be_bytes(),
+        })
+        .collect()
+}
+
+/// Detects the encoding of some bytes
+fn detect(bytes: Vec<u8>) -> DetectedEncoding {
+    InMemoryFile::from_bytes(bytes).detect_encoding().unwrap()
+}
+
+#[test]
+pub fn test_detect_encoding() {
+    let text = "Hello, wörld!\r\nSecond line\r\n";
+
+    assert_eq!(detect(text.into()), DetectedEncoding::Utf8 { bom: false });
+    assert_eq!(detect(Vec::new()), DetectedEncoding::Utf8 { bom: false });
+    let bom = [b"\xEF\xBB\xBF".as_slice(), text.as_bytes()].concat();
+    assert_eq!(detect(bom), DetectedEncoding::Utf8 { bom: true });
+
+    assert_eq!(
+        detect(utf16(text, true)),
+        DetectedEncoding::Utf16Le { bom: false }
+    );
+    assert_eq!(
+        detect(utf16(text, false)),
+        DetectedEncoding::Utf16Be { bom: false }
+    );
+    assert_eq!(
+        detect(utf16("\u{feff}テキスト", true)),
+        DetectedEncoding::Utf16Le { bom: true }
+    );
+    assert_eq!(
+        detect(utf16("\u{feff}テキスト", false)),
+        DetectedEncoding::Utf16Be { bom: true }
+    );
+    assert_eq!(
+        detect(utf32(text, true)),
+        DetectedEncoding::Utf32Le { bom: false }
+    );
+    assert_eq!(
+        detect(utf32(text, false)),
+        DetectedEncoding::Utf32Be { bom: false }
+    );
+    assert_eq!(
+        detect(utf32("\u{feff}x", true)),
+        DetectedEncoding::Utf32Le { bom: true }
+    );
+    assert_eq!(
+        detect(utf32("\u{feff}x", false)),
+        DetectedEncoding::Utf32Be { bom: true }
+    );
+
+    // Latin-1
+    assert_eq!(
+        detect(b"caf\xE9 cr\xE8me".to_vec()),
+        DetectedEncoding::Other
+    );
+    assert_eq!(DetectedEncoding::Utf16Le { bom: true }.bom_len(), 2);
+    assert_eq!(DetectedEncoding::Other.bom_len(), 0);
+
+    // Only the start of the file is looked at, which can stop partway through a character
+    let long = "ü".repeat(40000) + "\u{FF}";
+    assert_eq!(detect(long.into()), DetectedEncoding::Utf8 { bom: false });
+}
+
+#[test]
+pub fn test_require_utf8() {
+    let mut file = File::create("test_encoding_utf16.txt").unwrap();
+    file.write_all(&utf16("\u{feff}line one\r\nline two", true))
+        .unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::builder()
+        .require_utf8(true)
+        .open_path("test_encoding_utf16.txt");
+    assert!(matches!(
+        r,
+        Err(Error::UnsupportedEncoding(DetectedEncoding::Utf16Le {
+            bom: true
+        }))
+    ));
+    // Without asking it opens, and only fails when reading
+    let r = MappedFile::open_path("test_encoding_utf16.txt").unwrap();
+    assert!(r.line_at(0).is_err());
+
+    let mut file = File::create("test_encoding_utf8.txt").unwrap();
+    file.write_all("line one\nline two".as_bytes()).unwrap();
+    file.flush().unwrap();
+    let r = MappedFile::builder()
+        .require_utf8(true)
+        .open_path("test_encoding_utf8.txt")
+        .unwrap();
+    assert_eq!(r.line_at(1).unwrap(), "line two");
+}
+
+#[test]
+pub fn test_skip_bom() {
+    let mut file = File::create("test_encoding_bom.txt").unwrap();
+    file.write_all("\u{feff}first\nsecond".as_bytes()).unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::open_path("test_encoding_bom.txt").unwrap();
+    assert!(r.has_bom());
+    assert_eq!(r.unicode_at(0).unwrap(), 'f');
+    assert_eq!(r.char_count().unwrap(), 12);
+    assert_eq!(r.line_at(0).unwrap(), "first");
+    assert_eq!(r.byte_offset_of(6).unwrap(), 6);
+    assert_eq!(r.find("second", 0).unwrap().unwrap().char_position, 6);
+
+    let r = MappedFile::builder()
+        .skip_bom(false)
+        .open_path("test_encoding_bom.txt")
+        .unwrap();
+    assert!(!r.has_bom());
+    assert_eq!(r.unicode_at(0).unwrap(), '\u{feff}');
+    assert_eq!(r.line_at(0).unwrap(), "\u{feff}first");
+    assert_eq!(
+        r.detect_encoding().unwrap(),
+        DetectedEncoding::Utf8 { bom: true }
+    );
+
+    // Only a mark at the very start is skipped
+    let mut file = File::create("test_encoding_no_bom.txt").unwrap();
+    file.write_all("x\u{feff}".as_bytes()).unwrap();
+    file.flush().unwrap();
+    let r = MappedFile::open_path("test_encoding_no_bom.txt").unwrap();
+    assert!(!r.has_bom());
+    assert_eq!(r.char_count().unwrap(), 2);
+}
+
+#[test]
+pub fn test_utf16() {
+    let text = "Hello, wörld 😀\r\nline two\r\n\r\nthé end";
+    for little_endian in [true, false] {
+        let encoding = match little_endian {
+            true => TextEncoding::Utf16Le,
+            false => TextEncoding::Utf16Be,
+        };
+        let mut bytes = utf16("\u{feff}", little_endian);
+        bytes.extend(utf16(text, little_endian));
+        let mut r = EncodedFile::from_source(bytes, encoding);
+
+        assert!(r.has_bom());
+        assert_eq!(r.unicode_at(0).unwrap(), 'H');
+        assert_eq!(r.unicode_at(13).unwrap(), '😀');
+        assert_eq!(r.unicode_at(14).unwrap(), '\r');
+        // 2 bytes of mark, then 13 code units and a surrogate pair
+        assert_eq!(r.byte_offset_of(14).unwrap(), 32);
+        assert_eq!(r.char_count().unwrap(), text.chars().count());
+        assert!(r.unicode_at(text.chars().count()).is_err());
+        assert_eq!(r.string_range(7..14).unwrap(), "wörld 😀");
+
+        assert_eq!(r.line_count().unwrap(), 4);
+        assert_eq!(r.line_string(0).unwrap(), "Hello, wörld 😀\r");
+        assert_eq!(r.line_string(3).unwrap(), "thé end");
+        assert!(r.line_string(4).is_err());
+        r.set_line_endings(LineEndings::crlf());
+        assert_eq!(r.line_string(0).unwrap(), "Hello, wörld 😀");
+        assert_eq!(r.line_string(2).unwrap(), "");
+        assert_eq!(UnicodeAccess::line_count(&mut r).unwrap(), 4);
+    }
+
+    // Surrogate pairs split across the stretches the file is scanned in, and lines found past them
+    let text = "x".repeat(32767) + &"😀\n".repeat(40000);
+    let r = EncodedFile::from_source(utf16(&text, true), TextEncoding::Utf16Le);
+    assert!(!r.has_bom());
+    assert_eq!(r.char_count().unwrap(), 32767 + 80000);
+    assert_eq!(r.line_count().unwrap(), 40001);
+    assert_eq!(r.unicode_at(32767 + 2 * 39999).unwrap(), '😀');
+    assert_eq!(r.line_string(39999).unwrap(), "😀");
+    assert_eq!(r.line_string(0).unwrap().chars().count(), 32768);
+
+    // A lone surrogate isn't valid
+    let mut bytes = utf16("ab", true);
+    bytes.extend([0x00, 0xD8]);
+    bytes.extend(utf16("c\n", true));
+    let mut r = EncodedFile::from_source(bytes, TextEncoding::Utf16Le);
+    assert!(matches!(
+        r.unicode_at(0),
+        Err(Error::InvalidEncoding {
+            byte_offset: 4,
+            char_index: 2
+        })
+    ));
+    r.set_decode_policy(DecodePolicy::Lossy);
+    assert_eq!(r.line_string(0).unwrap(), "ab\u{fffd}c");
+}
+
+#[test]
+pub fn test_encoded_between_threads() {
+    let mut text = String::new();
+    for line in 0..20000 {
+        text.push_str(&format!("line {} ñ€😀\n", line));
+    }
+    let expected: Vec<char> = text.chars().collect();
+    let r = EncodedFile::from_source(utf16(&text, false), TextEncoding::Utf16Be);
+
+    // The threads race to scan the same stretches, and only one of them gets to add each to the index
+    std::thread::scope(|scope| {
+        for thread in 0..4 {
+            let (r, expected) = (&r, &expected);
+            scope.spawn(move || {
+                for line in (thread..20000).step_by(331) {
+                    assert_eq!(r.line_string(line).unwrap(), format!("line {} ñ€😀", line));
+                }
+                for index in (thread * 13..expected.len()).step_by(997) {
+                    assert_eq!(r.unicode_at(index).unwrap(), expected[index]);
+                }
+            });
+        }
+    });
+    assert_eq!(r.line_count().unwrap(), 20001);
+    assert_eq!(r.char_count().unwrap(), expected.len());
+}
+
+#[test]
+pub fn test_utf16_file() {
+    let mut file = File::create("test_encoding_utf16_open.txt").unwrap();
+    file.write_all(&utf16("one\ntwo", false)).unwrap();
+    file.flush().unwrap();
+
+    let r = EncodedFile::open(
+        File::open("test_encoding_utf16_open.txt").unwrap(),
+        TextEncoding::Utf16Be,
+    )
+    .unwrap();
+    assert_eq!(r.encoding(), TextEncoding::Utf16Be);
+    assert_eq!(r.line_string(1).unwrap(), "two");
+    assert_eq!(r.get(4), Some('t'));
+}
+
+#[test]
+pub fn test_utf32() {
+    let text = "naïve 😀\nsecond line\nthird";
+    for (little_endian, encoding) in [
+        (true, TextEncoding::Utf32Le),
+        (false, TextEncoding::Utf32Be),
+    ] {
+        let mut bytes = utf32("\u{feff}", little_endian);
+        bytes.extend(utf32(text, little_endian));
+        let r = EncodedFile::from_source(bytes, encoding);
+
+        assert!(r.has_bom());
+        assert!(encoding.is_fixed_width());
+        // Looking up a character doesn't need anything before it to be scanned
+        assert_eq!(r.unicode_at(6).unwrap(), '😀');
+        assert_eq!(r.byte_offset_of(6).unwrap(), 28);
+        assert_eq!(r.char_count().unwrap(), 25);
+        assert!(r.unicode_at(25).is_err());
+        assert_eq!(r.string_range(2..8).unwrap(), "ïve 😀\n");
+        assert_eq!(r.line_count().unwrap(), 3);
+        assert_eq!(r.line_string(1).unwrap(), "second line");
+    }
+
+    // Code units past U+10FFFF or in the surrogate range aren't characters
+    let mut bytes = utf32("ab", true);
+    bytes.extend(0xD800u32.to_le_bytes());
+    bytes.extend(0x110000u32.to_le_bytes());
+    let mut r = EncodedFile::from_source(bytes, TextEncoding::Utf32Le);
+    assert_eq!(r.unicode_at(1).unwrap(), 'b');
+    assert!(matches!(
+        r.unicode_at(3),
+        Err(Error::InvalidEncoding {
+            byte_offset: 12,
+            char_index: 3
+        })
+    ));
+    assert_eq!(r.char_count().unwrap(), 4);
+    r.set_decode_policy(DecodePolicy::Lossy);
+    assert_eq!(r.line_string(0).unwrap(), "ab\u{fffd}\u{fffd}");
+}
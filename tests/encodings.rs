@@ -0,0 +1,80 @@
+#![cfg(feature = "encodings")]
+
+use encoding_rs::{Encoding, GBK, ISO_2022_JP, SHIFT_JIS, UTF_16LE, UTF_8, WINDOWS_1252};
+use random_access_unicode::*;
+
+/// Encodes text in an encoding that can represent all of it
+fn encode(text: &str, encoding: &'static Encoding) -> Vec<u8> {
+    let (bytes, _, unmappable) = encoding.encode(text);
+    assert!(!unmappable);
+    bytes.into_owned()
+}
+
+#[test]
+pub fn test_legacy_encodings() {
+    let text = "日本語のテキスト\nsecond ライン\nthird";
+    for encoding in [SHIFT_JIS, GBK] {
+        let r = EncodedFile::from_source(
+            encode(text, encoding),
+            TextEncoding::for_encoding(encoding).unwrap(),
+        );
+        assert!(!r.encoding().is_fixed_width());
+        assert_eq!(r.char_count().unwrap(), text.chars().count());
+        assert_eq!(r.line_count().unwrap(), 3);
+        assert_eq!(r.unicode_at(2).unwrap(), '語');
+        assert_eq!(r.unicode_at(16).unwrap(), 'ラ');
+        assert_eq!(r.byte_offset_of(9).unwrap(), 17);
+        assert_eq!(r.line_string(1).unwrap(), "second ライン");
+        assert_eq!(r.line_string(2).unwrap(), "third");
+        assert_eq!(r.string_range(3..12).unwrap(), "のテキスト\nsec");
+    }
+}
+
+#[test]
+pub fn test_single_byte_encoding() {
+    let text = "café – “quoted”\nnext";
+    let r = EncodedFile::from_source(
+        encode(text, WINDOWS_1252),
+        TextEncoding::for_encoding(WINDOWS_1252).unwrap(),
+    );
+    assert!(r.encoding().is_fixed_width());
+    assert_eq!(r.char_count().unwrap(), text.chars().count());
+    assert_eq!(r.unicode_at(3).unwrap(), 'é');
+    assert_eq!(r.unicode_at(5).unwrap(), '–');
+    assert_eq!(r.line_string(0).unwrap(), "café – “quoted”");
+    assert_eq!(r.line_string(1).unwrap(), "next");
+}
+
+#[test]
+pub fn test_invalid_legacy_bytes() {
+    // 0x81 starts a two byte character in Shift_JIS, but a space can't follow it
+    let mut r = EncodedFile::from_source(
+        b"x\x81 y\x82".to_vec(),
+        TextEncoding::for_encoding(SHIFT_JIS).unwrap(),
+    );
+    assert!(matches!(
+        r.char_count(),
+        Err(Error::InvalidEncoding {
+            byte_offset: 1,
+            char_index: 1
+        })
+    ));
+
+    r.set_decode_policy(DecodePolicy::Lossy);
+    assert_eq!(r.string_range(0..5).unwrap(), "x\u{fffd} y\u{fffd}");
+    assert_eq!(r.unicode_at(2).unwrap(), ' ');
+}
+
+#[test]
+pub fn test_for_encoding() {
+    assert_eq!(
+        TextEncoding::for_encoding(UTF_16LE),
+        Some(TextEncoding::Utf16Le)
+    );
+    assert_eq!(TextEncoding::for_encoding(UTF_8), None);
+    assert_eq!(TextEncoding::for_encoding(ISO_2022_JP), None);
+    assert_eq!(
+        TextEncoding::for_encoding(Encoding::for_label(b"latin1").unwrap()),
+        Some(TextEncoding::Legacy(WINDOWS_1252))
+    );
+}
@@ -0,0 +1,50 @@
+#![cfg(feature = "graphemes")]
+
+use std::fs::File;
+use std::io::Write;
+
+use random_access_unicode::*;
+
+#[test]
+pub fn test_graphemes() {
+    let mut file = File::create("test_graphemes.txt").unwrap();
+    write!(file, "e\u{301}👩‍👩‍👧!\r\nnaïve\n🇳🇴").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_graphemes.txt").unwrap()).unwrap();
+
+    assert_eq!(r.grapheme_at(10).unwrap(), "🇳🇴");
+    assert_eq!(r.grapheme_at(0).unwrap(), "e\u{301}");
+    assert_eq!(r.grapheme_at(1).unwrap(), "👩‍👩‍👧");
+    assert_eq!(r.grapheme_at(3).unwrap(), "\r\n");
+    assert_eq!(r.grapheme_at(6).unwrap(), "ï");
+    assert!(matches!(r.grapheme_at(11), Err(Error::OutOfBounds { .. })));
+
+    assert_eq!(
+        r.graphemes_from(4).collect::<Result<Vec<_>, _>>().unwrap(),
+        vec!["n", "a", "ï", "v", "e", "\n", "🇳🇴"]
+    );
+    assert_eq!(r.graphemes_from(11).count(), 0);
+
+    // The graphemes before a line that isn't valid utf8 still come out, then the error
+    let r = MappedFile::from_bytes(b"ok\nbad \xFF\nnever".to_vec());
+    let graphemes: Vec<_> = r.graphemes_from(1).collect();
+    assert_eq!(graphemes.len(), 3);
+    assert_eq!(graphemes[1].as_ref().unwrap(), &"\n");
+    assert!(matches!(graphemes[2], Err(Error::InvalidUtf8 { .. })));
+}
+
+#[test]
+pub fn test_grapheme_columns() {
+    let mut file = File::create("test_grapheme_columns.txt").unwrap();
+    write!(file, "ae\u{301}👩‍👩‍👧b").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_grapheme_columns.txt").unwrap()).unwrap();
+
+    use PositionEncoding::*;
+    assert_eq!(r.convert_column(0, 3, Graphemes, Utf32Chars).unwrap(), 8);
+    assert_eq!(r.convert_column(0, 2, Utf32Chars, Graphemes).unwrap(), 1);
+    assert_eq!(r.convert_column(0, 8, Utf32Chars, Graphemes).unwrap(), 3);
+    assert_eq!(r.convert_column(0, 4, Graphemes, Utf16Units).unwrap(), 12);
+}
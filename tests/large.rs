@@ -0,0 +1,69 @@
+#![cfg(feature = "large-file-tests")]
+
+use std::fs::File;
+use std::io::{Seek, SeekFrom, Write};
+
+use random_access_unicode::*;
+
+/// Just past where a 32 bit offset would wrap
+const FOUR_GB: usize = 1 << 32;
+
+#[test]
+pub fn test_past_four_gb() {
+    // Everything before the text is holes, which read as nul characters
+    let mut file = File::create("test_large.txt").unwrap();
+    file.seek(SeekFrom::Start(FOUR_GB as u64)).unwrap();
+    write!(file, "héllo\nwörld").unwrap();
+    file.flush().unwrap();
+    drop(file);
+
+    let r = MappedFile::new(File::open("test_large.txt").unwrap()).unwrap();
+    assert_eq!(r.map.len(), FOUR_GB + 13);
+    assert_eq!(r.unicode_at(FOUR_GB + 1).unwrap(), 'é');
+    assert_eq!(r.byte_offset_of(FOUR_GB + 2).unwrap(), FOUR_GB + 3);
+    assert_eq!(
+        r.char_index_at_byte(FOUR_GB + 8).unwrap().char_position,
+        FOUR_GB + 7
+    );
+    assert_eq!(r.line_count().unwrap(), 2);
+    assert_eq!(r.line_at(1).unwrap(), "wörld");
+    assert_eq!(r.line_col_of(FOUR_GB + 8).unwrap(), (1, 2));
+    assert_eq!(r.char_count().unwrap(), FOUR_GB + 11);
+
+    // Saved indices keep positions past 4 GB
+    r.save_index("test_large.idx").unwrap();
+    let loaded =
+        MappedFile::open_with_index(File::open("test_large.txt").unwrap(), "test_large.idx")
+            .unwrap();
+    assert!(loaded.index_stats().complete);
+    assert_eq!(loaded.position_of(1, 1).unwrap().byte_position, FOUR_GB + 8);
+
+    std::fs::remove_file("test_large.txt").unwrap();
+    std::fs::remove_file("test_large.idx").unwrap();
+}
+
+#[test]
+pub fn test_sources_past_four_gb() {
+    let mut file = File::create("test_large_sources.txt").unwrap();
+    file.seek(SeekFrom::Start(FOUR_GB as u64)).unwrap();
+    write!(file, "héllo\nwörld").unwrap();
+    file.flush().unwrap();
+    drop(file);
+
+    // Windows and blocks past 4 GB are read from the right place
+    let source = Windowed::new(File::open("test_large_sources.txt").unwrap(), 0).unwrap();
+    assert_eq!(source.len_u64(), FOUR_GB as u64 + 13);
+    let r = MappedFile::from_source(source);
+    assert_eq!(r.unicode_at(FOUR_GB + 1).unwrap(), 'é');
+    assert_eq!(r.line_at(1).unwrap(), "wörld");
+
+    let r = BufferedFile::buffered(File::open("test_large_sources.txt").unwrap()).unwrap();
+    assert_eq!(r.map.len_u64(), FOUR_GB as u64 + 13);
+    assert_eq!(
+        r.char_index_at_byte(FOUR_GB + 8).unwrap().char_position,
+        FOUR_GB + 7
+    );
+    assert_eq!(r.line_at(1).unwrap(), "wörld");
+
+    std::fs::remove_file("test_large_sources.txt").unwrap();
+}
@@ -0,0 +1,66 @@
+#![cfg(feature = "nom")]
+
+use std::fs::File;
+use std::io::Write;
+
+use nom::{
+    bytes::complete::{tag, take_until, take_while1},
+    character::complete::{char, digit1, space0},
+    combinator::recognize,
+    sequence::{delimited, preceded},
+    IResult, Parser,
+};
+use random_access_unicode::*;
+
+type Parsed<'a> = IResult<NomInput<'a>, NomInput<'a>>;
+
+/// nom's own `alpha1` only takes ascii letters
+fn word(input: NomInput) -> Parsed {
+    take_while1(char::is_alphabetic)(input)
+}
+
+fn key(input: NomInput) -> Parsed {
+    preceded(space0, word).parse(input)
+}
+
+#[test]
+pub fn test_nom_input() {
+    let mut file = File::create("test_nom_input.txt").unwrap();
+    write!(file, "«übung» = 42;\n  naïve: \"wörld\"").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_nom_input.txt").unwrap()).unwrap();
+    let input = r.nom_input(0..r.char_count().unwrap()).unwrap();
+
+    let parsed: Parsed = delimited(char('«'), word, char('»')).parse(input);
+    let (rest, name) = parsed.unwrap();
+    assert_eq!(name.as_str(), "übung");
+    assert_eq!(name.char_range(), 1..6);
+    assert_eq!(rest.position().char_position, 7);
+
+    let parsed: Parsed = preceded(tag(" = "), digit1).parse(rest);
+    let (rest, number) = parsed.unwrap();
+    assert_eq!(number.char_range(), 10..12);
+    let (rest, _) = take_until::<_, _, ()>("\n").parse(rest).unwrap();
+    assert_eq!(rest.position().byte_position, 16);
+
+    let (rest, _) = char::<_, ()>('\n').parse(rest).unwrap();
+    let (rest, key) = key(rest).unwrap();
+    assert_eq!(key.as_str(), "naïve");
+    assert_eq!(key.char_range(), 16..21);
+
+    let parsed: Parsed =
+        recognize(preceded(tag(": "), delimited(char('"'), word, char('"')))).parse(rest);
+    let (rest, value) = parsed.unwrap();
+    assert_eq!(value.as_str(), ": \"wörld\"");
+    assert_eq!(value.char_range(), 21..30);
+    assert_eq!(rest.as_str(), "");
+
+    // Only part of the file can be parsed too
+    let input = r.nom_input(16..21).unwrap();
+    assert_eq!(word(input).unwrap().1.char_range(), 16..21);
+    assert!(matches!(
+        r.nom_input(16..31),
+        Err(Error::OutOfBounds { .. })
+    ));
+}
@@ -0,0 +1,48 @@
+#![cfg(feature = "normalization")]
+
+use random_access_unicode::*;
+
+/// Collects the character ranges of every match for some text
+fn match_spans(
+    r: &MappedFile<impl TextSource>,
+    needle: &str,
+    options: SearchOptions,
+) -> Vec<(usize, usize)> {
+    r.find_all_with(needle, options)
+        .map(|m| (m.start.char_position, m.end.char_position))
+        .collect()
+}
+
+#[test]
+pub fn test_find_normalized() {
+    // "café" composed, then decomposed, then "cafe" on its own
+    let r = MappedFile::from("caf\u{e9} cafe\u{301} cafe");
+    let normalized = SearchOptions::new().normalized(true);
+
+    assert_eq!(match_spans(&r, "caf\u{e9}", normalized), [(0, 4), (5, 10)]);
+    assert_eq!(
+        match_spans(&r, "cafe\u{301}", normalized),
+        [(0, 4), (5, 10)]
+    );
+    assert_eq!(match_spans(&r, "caf\u{e9}", SearchOptions::new()), [(0, 4)]);
+    // A match can't stop before the marks on a character
+    assert_eq!(match_spans(&r, "cafe", normalized), [(11, 15)]);
+
+    let found = r.find_with("\u{e9}", 4, normalized).unwrap().unwrap();
+    assert_eq!(
+        (found.start.char_position, found.start.byte_position),
+        (8, 9)
+    );
+    assert_eq!((found.end.char_position, found.end.byte_position), (10, 12));
+
+    // Marks match in any order that means the same thing
+    let r = MappedFile::from("a\u{323}\u{301}");
+    assert_eq!(match_spans(&r, "a\u{301}\u{323}", normalized), [(0, 3)]);
+    assert_eq!(match_spans(&r, "\u{1ea1}\u{301}", normalized), [(0, 3)]);
+
+    // Along with ignoring case
+    let r = MappedFile::from("CAFE\u{301}");
+    let options = normalized.case(CaseSensitivity::Full);
+    assert_eq!(match_spans(&r, "caf\u{e9}", options), [(0, 5)]);
+    assert!(match_spans(&r, "caf\u{e9}", normalized).is_empty());
+}
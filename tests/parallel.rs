@@ -0,0 +1,115 @@
+#![cfg(feature = "parallel")]
+
+use std::fs::File;
+use std::io::Write;
+
+use random_access_unicode::*;
+
+#[test]
+pub fn test_build_index_parallel() {
+    let mut file = File::create("test_parallel.txt").unwrap();
+    let text = "ünïcödé 😀\r\nline\n\nwindows\r\n".repeat(40) + "tail";
+    write!(file, "{}", text).unwrap();
+    file.flush().unwrap();
+
+    let open = || {
+        MappedFile::builder()
+            .line_endings(LineEndings::unicode())
+            .checkpoint_interval(Some(7))
+            .open_path("test_parallel.txt")
+            .unwrap()
+    };
+
+    let sequential = open();
+    let expected = sequential.build_index().unwrap();
+
+    // Tiny chunks so the splits land inside characters and between \r and \n
+    for chunk_size in [1, 3, 64, 1 << 20] {
+        let parallel = open();
+        assert_eq!(
+            parallel
+                .build_index_parallel_with_chunk_size(chunk_size)
+                .unwrap(),
+            expected
+        );
+        for line in 0..expected.lines {
+            assert_eq!(
+                parallel.line_at(line).unwrap(),
+                sequential.line_at(line).unwrap()
+            );
+        }
+    }
+
+    // Picks up where lazy indexing left off
+    let partial = open();
+    assert_eq!(
+        partial.unicode_at(30).unwrap(),
+        text.chars().nth(30).unwrap()
+    );
+    assert_eq!(partial.build_index_parallel().unwrap(), expected);
+
+    let mut file = File::create("test_parallel_invalid.txt").unwrap();
+    file.write_all(b"fine\n\xC3").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_parallel_invalid.txt").unwrap()).unwrap();
+    assert!(matches!(
+        r.build_index_parallel_with_chunk_size(2),
+        Err(Error::InvalidUtf8 { .. })
+    ));
+}
+
+#[test]
+pub fn test_find_all_parallel() {
+    let text = "ünïcödé aaaa 😀 needle\n".repeat(50) + "needlaaa";
+    let r = MappedFile::from(text.as_str());
+
+    // Tiny chunks so matches cross from one chunk into the next
+    for needle in ["needle", "aa", "😀", "ü"] {
+        let expected: Vec<_> = r
+            .find_all(needle)
+            .map(|p| (p.char_position, p.byte_position))
+            .collect();
+        for chunk_size in [1, 3, 7, 64, 1 << 20] {
+            let found: Vec<_> = r
+                .find_all_parallel_with_chunk_size(needle, chunk_size)
+                .unwrap()
+                .into_iter()
+                .map(|p| (p.char_position, p.byte_position))
+                .collect();
+            assert_eq!(found, expected, "{needle} in chunks of {chunk_size}");
+        }
+    }
+    assert_eq!(r.find_all_parallel("aaa").unwrap().len(), 51);
+    assert!(r.find_all_parallel("missing").unwrap().is_empty());
+
+    // Chunks start at the line starts and checkpoints already indexed, partly and then all of it
+    let expected: Vec<_> = MappedFile::from(text.as_str())
+        .find_all("aa")
+        .map(|p| (p.char_position, p.byte_position))
+        .collect();
+    let mut r = MappedFile::from(text.as_str());
+    r.set_checkpoint_interval(Some(5));
+    r.unicode_at(300).unwrap();
+    for _ in 0..2 {
+        for chunk_size in [1, 3, 7, 64, 1 << 20] {
+            let found: Vec<_> = r
+                .find_all_parallel_with_chunk_size("aa", chunk_size)
+                .unwrap()
+                .into_iter()
+                .map(|p| (p.char_position, p.byte_position))
+                .collect();
+            assert_eq!(found, expected, "aa in chunks of {chunk_size}");
+        }
+        r.build_index().unwrap();
+    }
+
+    let mut r = InMemoryFile::from_bytes(b"fine\n\xC3 fine".to_vec());
+    assert!(matches!(
+        r.find_all_parallel_with_chunk_size("fine", 2),
+        Err(Error::InvalidUtf8 { .. })
+    ));
+    r.set_decode_policy(DecodePolicy::Lossy);
+    let found = r.find_all_parallel_with_chunk_size("fine", 2).unwrap();
+    assert_eq!(found[1].char_position, 7);
+}
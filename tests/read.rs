@@ -1,5 +1,5 @@
 use std::fs::File;
-use std::io::Write;
+use std::io::{BufRead, Read, Seek, SeekFrom, Write};
 
 use random_access_unicode::*;
 
@@ -9,7 +9,7 @@ pub fn test_helloworld() {
     write!(file, "Hello\nworld!\n").unwrap();
     file.flush().unwrap();
 
-    let mut r = MappedFile::new(File::open("test.txt").unwrap()).unwrap();
+    let r = MappedFile::new(File::open("test.txt").unwrap()).unwrap();
 
     assert_eq!(r.unicode_at(0).unwrap(), 'H');
     assert_eq!(r.unicode_at(1).unwrap(), 'e');
@@ -25,4 +25,2449 @@ pub fn test_helloworld() {
     assert_eq!(r.unicode_at(11).unwrap(), '!');
     assert_eq!(r.unicode_at(12).unwrap(), '\n');
     
-}
\ No newline at end of file
+}
+#[test]
+pub fn test_out_of_order() {
+    let mut file = File::create("test_out_of_order.txt").unwrap();
+    write!(file, "one\ntwo\nthree\nfour").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_out_of_order.txt").unwrap()).unwrap();
+
+    assert_eq!(r.unicode_at(15).unwrap(), 'o');
+    assert_eq!(r.unicode_at(0).unwrap(), 'o');
+    assert_eq!(r.unicode_at(3).unwrap(), '\n');
+    assert_eq!(r.unicode_at(8).unwrap(), 't');
+    assert_eq!(r.unicode_at(13).unwrap(), '\n');
+    assert_eq!(r.unicode_at(17).unwrap(), 'r');
+    assert!(matches!(r.unicode_at(18), Err(Error::OutOfBounds { .. })));
+    assert_eq!(r.line_ending_positions().unwrap().len(), 4);
+}
+
+#[test]
+pub fn test_str_range() {
+    let mut file = File::create("test_str_range.txt").unwrap();
+    write!(file, "héllo\nwörld").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_str_range.txt").unwrap()).unwrap();
+
+    assert_eq!(r.str_range(6..11).unwrap(), "wörld");
+    assert_eq!(r.str_range(1..4).unwrap(), "éll");
+    assert_eq!(r.str_range(3..3).unwrap(), "");
+    assert_eq!(r.str_range(0..11).unwrap(), "héllo\nwörld");
+    assert!(matches!(r.str_range(4..12), Err(Error::OutOfBounds { .. })));
+}
+
+#[test]
+pub fn test_line_at() {
+    let mut file = File::create("test_line_at.txt").unwrap();
+    write!(file, "\nfirst\nsécond\n\nlast\n").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_line_at.txt").unwrap()).unwrap();
+
+    assert_eq!(r.line_at(2).unwrap(), "sécond");
+    assert_eq!(r.line_at(0).unwrap(), "");
+    assert_eq!(r.line_at(1).unwrap(), "first");
+    assert_eq!(r.line_at(3).unwrap(), "");
+    assert_eq!(r.line_at(4).unwrap(), "last");
+    assert_eq!(r.line_at(5).unwrap(), "");
+    assert!(matches!(r.line_at(6), Err(Error::OutOfBounds { .. })));
+    assert_eq!(r.unicode_at(0).unwrap(), '\n');
+    assert_eq!(r.unicode_at(9).unwrap(), 'c');
+}
+
+#[test]
+pub fn test_chars_from() {
+    let mut file = File::create("test_chars_from.txt").unwrap();
+    write!(file, "añb\n€d").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_chars_from.txt").unwrap()).unwrap();
+
+    assert_eq!(r.chars_from(1).collect::<String>(), "ñb\n€d");
+    assert_eq!(r.chars_from(4).collect::<String>(), "€d");
+    assert_eq!(r.chars_from(6).count(), 0);
+    assert_eq!(r.chars_from(100).count(), 0);
+}
+
+#[test]
+pub fn test_chars_rev_from() {
+    let mut file = File::create("test_chars_rev_from.txt").unwrap();
+    write!(file, "añb\n€😀").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_chars_rev_from.txt").unwrap()).unwrap();
+
+    assert_eq!(r.chars_rev_from(6).collect::<String>(), "😀€\nbña");
+    assert_eq!(r.chars_rev_from(3).collect::<String>(), "bña");
+    assert_eq!(r.chars_rev_from(0).count(), 0);
+    assert_eq!(r.chars_rev_from(100).count(), 0);
+}
+
+#[test]
+pub fn test_char_count() {
+    let mut file = File::create("test_char_count.txt").unwrap();
+    write!(file, "añb\n€😀\n").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_char_count.txt").unwrap()).unwrap();
+
+    assert_eq!(r.unicode_at(1).unwrap(), 'ñ');
+    assert_eq!(r.char_count().unwrap(), 7);
+    assert_eq!(r.char_count().unwrap(), 7);
+    assert_eq!(r.unicode_at(5).unwrap(), '😀');
+    assert!(matches!(r.unicode_at(7), Err(Error::OutOfBounds { .. })));
+    assert_eq!(r.line_ending_positions().unwrap().len(), 3);
+}
+
+#[test]
+pub fn test_line_lengths() {
+    let mut file = File::create("test_line_lengths.txt").unwrap();
+    write!(file, "añb\n\n€😀").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_line_lengths.txt").unwrap()).unwrap();
+
+    assert_eq!(r.line_len(2).unwrap(), 2);
+    assert_eq!(r.line_byte_len(2).unwrap(), 7);
+    assert_eq!(r.line_len(0).unwrap(), 3);
+    assert_eq!(r.line_byte_len(0).unwrap(), 4);
+    assert_eq!(r.line_len(1).unwrap(), 0);
+    assert_eq!(r.line_byte_len(1).unwrap(), 0);
+    assert_eq!(r.line_count().unwrap(), 3);
+    assert!(matches!(r.line_len(3), Err(Error::OutOfBounds { .. })));
+}
+
+#[test]
+pub fn test_char_index_at_byte() {
+    let mut file = File::create("test_char_index_at_byte.txt").unwrap();
+    write!(file, "añb\n€😀").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_char_index_at_byte.txt").unwrap()).unwrap();
+
+    let position = r.char_index_at_byte(9).unwrap();
+    assert_eq!((position.byte_position, position.char_position), (8, 5));
+    let position = r.char_index_at_byte(2).unwrap();
+    assert_eq!((position.byte_position, position.char_position), (1, 1));
+    let position = r.char_index_at_byte(5).unwrap();
+    assert_eq!((position.byte_position, position.char_position), (5, 4));
+    let position = r.char_index_at_byte(12).unwrap();
+    assert_eq!((position.byte_position, position.char_position), (12, 6));
+    assert!(matches!(r.char_index_at_byte(13), Err(Error::OutOfBounds { .. })));
+}
+
+#[test]
+pub fn test_byte_offset_of() {
+    let mut file = File::create("test_byte_offset_of.txt").unwrap();
+    write!(file, "añb\n€😀").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_byte_offset_of.txt").unwrap()).unwrap();
+
+    assert_eq!(r.byte_offset_of(5).unwrap(), 8);
+    assert_eq!(r.byte_offset_of(2).unwrap(), 3);
+    assert_eq!(r.byte_offset_of(0).unwrap(), 0);
+    assert_eq!(r.byte_offset_of(6).unwrap(), 12);
+    assert!(matches!(
+        r.byte_offset_of(7),
+        Err(Error::OutOfBounds { .. })
+    ));
+    assert_eq!(r.unicode_at(4).unwrap(), '€');
+}
+
+#[test]
+pub fn test_byte_offset_of_across_windows() {
+    // Far more than one window of lines, with characters of every width
+    let mut text = String::new();
+    for i in 0..60000 {
+        text.push(['a', 'ñ', '€', '😀'][i % 4]);
+        if i % 1000 == 999 {
+            text.push('\n');
+        }
+    }
+    let r = InMemoryFile::from(text.clone());
+
+    let (byte, _) = text.char_indices().nth(59000).unwrap();
+    assert_eq!(r.byte_offset_of(59000).unwrap(), byte);
+    // Every line start stepped over was recorded on the way
+    let lines = r.line_ending_positions().unwrap();
+    assert_eq!(lines.len(), 59);
+    for line in &lines {
+        assert!(text.is_char_boundary(line.byte_position));
+        assert_eq!(
+            text[..line.byte_position].chars().count(),
+            line.char_position
+        );
+    }
+    let (byte, _) = text.char_indices().nth(123).unwrap();
+    assert_eq!(r.byte_offset_of(123).unwrap(), byte);
+    assert_eq!(r.byte_offset_of(60060).unwrap(), text.len());
+    assert!(matches!(
+        r.byte_offset_of(60061),
+        Err(Error::OutOfBounds { .. })
+    ));
+}
+
+#[test]
+pub fn test_position_of() {
+    let mut file = File::create("test_position_of.txt").unwrap();
+    write!(file, "añb\n€😀").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_position_of.txt").unwrap()).unwrap();
+
+    let position = r.position_of(1, 1).unwrap();
+    assert_eq!((position.byte_position, position.char_position), (8, 5));
+    let position = r.position_of(0, 3).unwrap();
+    assert_eq!((position.byte_position, position.char_position), (4, 3));
+    let position = r.position_of(1, 2).unwrap();
+    assert_eq!((position.byte_position, position.char_position), (12, 6));
+    assert!(matches!(
+        r.position_of(0, 4),
+        Err(Error::OutOfBounds { .. })
+    ));
+    assert!(matches!(
+        r.position_of(2, 0),
+        Err(Error::OutOfBounds { .. })
+    ));
+}
+
+#[test]
+pub fn test_line_col_of() {
+    let mut file = File::create("test_line_col_of.txt").unwrap();
+    write!(file, "añb\n\n€😀").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_line_col_of.txt").unwrap()).unwrap();
+
+    assert_eq!(r.line_col_of(6).unwrap(), (2, 1));
+    assert_eq!(r.line_col_of(0).unwrap(), (0, 0));
+    assert_eq!(r.line_col_of(3).unwrap(), (0, 3));
+    assert_eq!(r.line_col_of(4).unwrap(), (1, 0));
+    assert_eq!(r.line_col_of(7).unwrap(), (2, 2));
+    assert!(matches!(r.line_col_of(8), Err(Error::OutOfBounds { .. })));
+}
+
+#[test]
+pub fn test_lsp_positions() {
+    let mut file = File::create("test_lsp_positions.txt").unwrap();
+    write!(file, "a😀b\n€😀c").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_lsp_positions.txt").unwrap()).unwrap();
+
+    assert_eq!(r.lsp_position_of(6).unwrap(), (1, 3));
+    assert_eq!(r.lsp_position_of(2).unwrap(), (0, 3));
+    assert_eq!(r.lsp_position_of(3).unwrap(), (0, 4));
+
+    assert_eq!(r.char_index_of_lsp(1, 3).unwrap(), 6);
+    assert_eq!(r.char_index_of_lsp(0, 3).unwrap(), 2);
+    assert_eq!(r.char_index_of_lsp(0, 2).unwrap(), 1);
+    assert_eq!(r.char_index_of_lsp(0, 4).unwrap(), 3);
+    assert_eq!(r.char_index_of_lsp(1, 4).unwrap(), 7);
+    assert!(matches!(
+        r.char_index_of_lsp(1, 5),
+        Err(Error::OutOfBounds { .. })
+    ));
+}
+
+#[test]
+pub fn test_convert_column() {
+    let mut file = File::create("test_convert_column.txt").unwrap();
+    write!(file, "a😀€b").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_convert_column.txt").unwrap()).unwrap();
+
+    use PositionEncoding::*;
+    assert_eq!(r.convert_column(0, 3, Utf32Chars, Utf8Bytes).unwrap(), 8);
+    assert_eq!(r.convert_column(0, 3, Utf32Chars, Utf16Units).unwrap(), 4);
+    assert_eq!(r.convert_column(0, 8, Utf8Bytes, Utf16Units).unwrap(), 4);
+    assert_eq!(r.convert_column(0, 4, Utf16Units, Utf32Chars).unwrap(), 3);
+    assert_eq!(r.convert_column(0, 7, Utf8Bytes, Utf32Chars).unwrap(), 2);
+    assert_eq!(r.convert_column(0, 9, Utf8Bytes, Utf32Chars).unwrap(), 4);
+    assert!(matches!(
+        r.convert_column(0, 10, Utf8Bytes, Utf32Chars),
+        Err(Error::OutOfBounds { .. })
+    ));
+}
+
+#[test]
+pub fn test_crlf_lines() {
+    let mut file = File::create("test_crlf_lines.txt").unwrap();
+    write!(file, "one\r\ntwö\nthree\r\n").unwrap();
+    file.flush().unwrap();
+
+    let mut r = MappedFile::new(File::open("test_crlf_lines.txt").unwrap()).unwrap();
+
+    assert_eq!(r.line_at(0).unwrap(), "one\r");
+    assert_eq!(r.line_terminator(0).unwrap(), Some(LineTerminator::Lf));
+
+    r.set_line_endings(LineEndings::crlf());
+    assert_eq!(r.line_at(0).unwrap(), "one");
+    assert_eq!(r.line_at(1).unwrap(), "twö");
+    assert_eq!(r.line_at(2).unwrap(), "three");
+    assert_eq!(r.line_at(3).unwrap(), "");
+    assert_eq!(r.line_len(2).unwrap(), 5);
+    assert_eq!(r.line_byte_len(1).unwrap(), 4);
+    assert_eq!(r.line_terminator(0).unwrap(), Some(LineTerminator::CrLf));
+    assert_eq!(r.line_terminator(1).unwrap(), Some(LineTerminator::Lf));
+    assert_eq!(r.line_terminator(3).unwrap(), None);
+}
+
+#[test]
+pub fn test_unicode_line_endings() {
+    let mut file = File::create("test_unicode_line_endings.txt").unwrap();
+    write!(file, "a\rb\r\nc\u{85}d\u{2028}e\u{2029}f\ng").unwrap();
+    file.flush().unwrap();
+
+    let mut r = MappedFile::new(File::open("test_unicode_line_endings.txt").unwrap()).unwrap();
+
+    assert_eq!(r.line_count().unwrap(), 3);
+
+    r.set_line_endings(LineEndings::unicode());
+    assert_eq!(r.line_count().unwrap(), 7);
+    assert_eq!(r.line_at(1).unwrap(), "b");
+    assert_eq!(r.line_at(6).unwrap(), "g");
+    assert_eq!(r.line_terminator(0).unwrap(), Some(LineTerminator::Cr));
+    assert_eq!(r.line_terminator(1).unwrap(), Some(LineTerminator::CrLf));
+    assert_eq!(r.line_terminator(2).unwrap(), Some(LineTerminator::Nel));
+    assert_eq!(
+        r.line_terminator(3).unwrap(),
+        Some(LineTerminator::LineSeparator)
+    );
+    assert_eq!(r.line_col_of(11).unwrap(), (5, 0));
+    assert_eq!(r.line_len(2).unwrap(), 1);
+    assert_eq!(r.line_byte_len(3).unwrap(), 1);
+
+    r.set_line_endings(LineEndings::custom([';']));
+    assert_eq!(
+        r.line_at(0).unwrap(),
+        "a\rb\r\nc\u{85}d\u{2028}e\u{2029}f\ng"
+    );
+}
+
+#[test]
+pub fn test_empty_file() {
+    File::create("test_empty_file.txt").unwrap();
+
+    let r = MappedFile::new(File::open("test_empty_file.txt").unwrap()).unwrap();
+
+    assert_eq!(r.backend(), Backend::Empty);
+    assert!(matches!(r.unicode_at(0), Err(Error::OutOfBounds { .. })));
+    assert_eq!(r.str_range(0..0).unwrap(), "");
+    assert_eq!(r.chars_from(0).count(), 0);
+    assert_eq!(r.chars_rev_from(0).count(), 0);
+    assert_eq!(r.char_count().unwrap(), 0);
+    assert_eq!(r.line_count().unwrap(), 1);
+    assert_eq!(r.line_at(0).unwrap(), "");
+    assert!(matches!(r.line_at(1), Err(Error::OutOfBounds { .. })));
+}
+
+#[test]
+pub fn test_backends() {
+    let mut file = File::create("test_backends.txt").unwrap();
+    write!(file, "mapped").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_backends.txt").unwrap()).unwrap();
+    assert_eq!(r.backend(), Backend::Mmap);
+
+    // Files in /proc say they're empty and can't be mapped
+    #[cfg(target_os = "linux")]
+    {
+        let r = MappedFile::new(File::open("/proc/self/stat").unwrap()).unwrap();
+        assert_eq!(r.backend(), Backend::Memory);
+        assert!(r.unicode_at(0).unwrap().is_ascii_digit());
+    }
+}
+
+#[test]
+pub fn test_from_reader() {
+    let r = MappedFile::from_reader("piped\nïn".as_bytes()).unwrap();
+
+    assert_eq!(r.backend(), Backend::Mmap);
+    assert_eq!(r.line_at(1).unwrap(), "ïn");
+    assert_eq!(r.unicode_at(6).unwrap(), 'ï');
+}
+
+#[test]
+pub fn test_builder() {
+    let mut file = File::create("test_builder.txt").unwrap();
+    write!(file, "one\r\ntwo\r\n").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::builder()
+        .line_endings(LineEndings::crlf())
+        .eager(true)
+        .populate(true)
+        .access(Access::Random)
+        .open_path("test_builder.txt")
+        .unwrap();
+
+    assert_eq!(r.line_ending_positions().unwrap().len(), 3);
+    assert_eq!(r.line_at(1).unwrap(), "two");
+
+    let mut file = File::create("test_builder_invalid.txt").unwrap();
+    file.write_all(b"ok\n\xFF").unwrap();
+    file.flush().unwrap();
+
+    assert!(matches!(
+        MappedFile::builder()
+            .eager(true)
+            .open_path("test_builder_invalid.txt"),
+        Err(Error::InvalidUtf8 { byte_offset: 3, .. })
+    ));
+    assert!(matches!(
+        MappedFile::builder().open_path("test_builder_missing.txt"),
+        Err(Error::Io(_))
+    ));
+}
+
+#[test]
+pub fn test_checkpoints() {
+    let mut file = File::create("test_checkpoints.txt").unwrap();
+    let text: String = "aé😀b".repeat(250) + "\nend";
+    write!(file, "{}", text).unwrap();
+    file.flush().unwrap();
+
+    let mut r = MappedFile::builder()
+        .checkpoint_interval(Some(100))
+        .open_path("test_checkpoints.txt")
+        .unwrap();
+
+    // Jumping around a single long line should only ever need the nearest checkpoint
+    let chars: Vec<char> = text.chars().collect();
+    for &index in &[950, 10, 555, 999, 101, 1000, 1002, 300] {
+        assert_eq!(r.unicode_at(index).unwrap(), chars[index]);
+        let byte = r.byte_offset_of(index).unwrap();
+        assert_eq!(r.char_index_at_byte(byte).unwrap().char_position, index);
+    }
+    assert_eq!(r.checkpoint_count(), 10);
+    assert_eq!(r.line_count().unwrap(), 2);
+    assert_eq!(r.line_at(1).unwrap(), "end");
+    assert_eq!(r.char_count().unwrap(), chars.len());
+
+    r.set_checkpoint_interval(None);
+    assert_eq!(r.checkpoint_count(), 0);
+    assert_eq!(r.unicode_at(777).unwrap(), chars[777]);
+}
+
+#[test]
+pub fn test_build_index() {
+    let mut file = File::create("test_build_index.txt").unwrap();
+    write!(file, "ünï\ncödé\n{}", "x".repeat(30)).unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::builder()
+        .checkpoint_interval(Some(10))
+        .open_path("test_build_index.txt")
+        .unwrap();
+
+    let stats = r.build_index().unwrap();
+    assert_eq!(
+        stats,
+        IndexStats {
+            bytes: 43,
+            chars: 39,
+            lines: 3,
+            checkpoints: 3,
+        }
+    );
+
+    // Building it again is free and gives the same answer
+    assert_eq!(r.build_index().unwrap(), stats);
+    assert_eq!(r.line_at(2).unwrap().len(), 30);
+
+    let mut file = File::create("test_build_index_invalid.txt").unwrap();
+    file.write_all(b"fine\n\xC3").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_build_index_invalid.txt").unwrap()).unwrap();
+    assert!(matches!(r.build_index(), Err(Error::InvalidUtf8 { .. })));
+}
+
+#[test]
+pub fn test_long_line_scanning() {
+    let mut file = File::create("test_long_line_scanning.txt").unwrap();
+    // Long enough that scanning has to stop partway through lines and characters
+    let text = "é".repeat(40_000)
+        + "a😀".repeat(30_000).as_str()
+        + "\r\nshort\r\n"
+        + "x".repeat(70_000).as_str();
+    write!(file, "{}", text).unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::builder()
+        .line_endings(LineEndings::crlf())
+        .checkpoint_interval(None)
+        .open_path("test_long_line_scanning.txt")
+        .unwrap();
+
+    let chars: Vec<char> = text.chars().collect();
+    for &index in &[100_001, 39_999, 40_000, 100_002, 100_003, 100_010, 170_000] {
+        assert_eq!(r.unicode_at(index).unwrap(), chars[index]);
+    }
+    assert_eq!(r.line_at(1).unwrap(), "short");
+    assert_eq!(r.line_count().unwrap(), 3);
+    assert_eq!(r.char_count().unwrap(), chars.len());
+    assert_eq!(
+        r.char_index_at_byte(text.len() - 1).unwrap().char_position,
+        chars.len() - 1
+    );
+}
+
+#[test]
+pub fn test_chunked_decoding() {
+    let mut file = File::create("test_chunked_decoding.txt").unwrap();
+    file.write_all("héllo\u{2028}wörld\r\n".as_bytes()).unwrap();
+    file.write_all("x".repeat(200_000).as_bytes()).unwrap();
+    file.write_all(b"\xFF").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::builder()
+        .line_endings(LineEndings::unicode())
+        .open_path("test_chunked_decoding.txt")
+        .unwrap();
+
+    // The bad byte at the end doesn't get in the way of anything before it
+    assert_eq!(r.unicode_at(7).unwrap(), 'ö');
+    assert_eq!(r.line_at(1).unwrap(), "wörld");
+    assert_eq!(r.unicode_at(150_000).unwrap(), 'x');
+    assert!(matches!(r.char_count(), Err(Error::InvalidUtf8 { .. })));
+}
+
+#[test]
+pub fn test_lossy_decoding() {
+    let bytes = b"ok \xFF\xFE line\nsecond \xE2\x80 x\n\xF0\x9F\x98\x80\x80end";
+    let mut file = File::create("test_lossy_decoding.txt").unwrap();
+    file.write_all(bytes).unwrap();
+    file.flush().unwrap();
+
+    let mut r = MappedFile::builder()
+        .decode_policy(DecodePolicy::Lossy)
+        .checkpoint_interval(Some(5))
+        .open_path("test_lossy_decoding.txt")
+        .unwrap();
+
+    let expected: Vec<char> = String::from_utf8_lossy(bytes).chars().collect();
+    for &index in &[18, 3, 23, 0, 26, 4, 22] {
+        assert_eq!(r.unicode_at(index).unwrap(), expected[index]);
+    }
+    assert_eq!(r.char_count().unwrap(), expected.len());
+    assert_eq!(r.line_count().unwrap(), 3);
+    assert_eq!(r.line_string(1).unwrap(), "second \u{FFFD} x");
+    assert_eq!(r.chars_from(0).collect::<Vec<_>>(), expected);
+    let mut backwards: Vec<char> = r.chars_rev_from(expected.len()).collect();
+    backwards.reverse();
+    assert_eq!(backwards, expected);
+
+    // Borrowed text can't hold the replacements
+    assert!(matches!(r.line_at(0), Err(Error::InvalidUtf8 { .. })));
+
+    // Being strict again means the first line can't even be indexed
+    r.set_decode_policy(DecodePolicy::Strict);
+    assert!(matches!(r.unicode_at(0), Err(Error::InvalidUtf8 { .. })));
+}
+
+#[test]
+pub fn test_validate() {
+    let mut file = File::create("test_validate.txt").unwrap();
+    file.write_all(b"fine\nbr\xFFke\xE2\x80n\n\n\xC3").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_validate.txt").unwrap()).unwrap();
+    let regions = match r.validate() {
+        Err(Error::InvalidRegions(regions)) => regions,
+        result => panic!("expected invalid regions, got {:?}", result),
+    };
+    assert_eq!(
+        regions,
+        vec![
+            InvalidRegion {
+                bytes: 7..8,
+                line: 1,
+                char_position: 7,
+            },
+            InvalidRegion {
+                bytes: 10..12,
+                line: 1,
+                char_position: 10,
+            },
+            InvalidRegion {
+                bytes: 15..16,
+                line: 3,
+                char_position: 14,
+            },
+        ]
+    );
+
+    let mut file = File::create("test_validate_valid.txt").unwrap();
+    write!(file, "ünïcödé\r\nfine").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_validate_valid.txt").unwrap()).unwrap();
+    assert!(r.validate().is_ok());
+
+    // A file that can't be read is an error rather than a region of bad bytes
+    let r = MappedFile::builder()
+        .guarded(true)
+        .open(File::open("test_validate_valid.txt").unwrap())
+        .unwrap();
+    File::options()
+        .write(true)
+        .open("test_validate_valid.txt")
+        .unwrap()
+        .set_len(3)
+        .unwrap();
+    assert!(matches!(r.validate(), Err(Error::Truncated)));
+}
+
+#[test]
+pub fn test_errors() -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = File::create("test_errors.txt")?;
+    file.write_all(b"one\ntw\xFFo\n")?;
+    file.flush()?;
+
+    // Everything goes through ? into a boxed error
+    let r = MappedFile::new(File::open("test_errors.txt")?)?;
+    assert_eq!(r.unicode_at(2)?, 'e');
+
+    let error = r.line_at(1).unwrap_err();
+    match &error {
+        Error::InvalidUtf8 {
+            byte_offset,
+            char_index,
+            context,
+        } => {
+            assert_eq!(*byte_offset, 6);
+            assert_eq!(*char_index, 6);
+            assert_eq!(context, b"tw\xFFo\n");
+        }
+        _ => panic!("expected invalid utf8, got {:?}", error),
+    }
+    assert_eq!(
+        error.to_string(),
+        "invalid utf8 at byte 6 (character 6) near \"tw\\xffo\\n\""
+    );
+
+    let error = MappedFile::new(File::open("test_errors.txt")?)?
+        .position_of(0, 9)
+        .unwrap_err();
+    assert!(matches!(error, Error::OutOfBounds { index: 9, len: 3 }));
+    assert_eq!(error.to_string(), "index 9 is out of bounds for length 3");
+
+    let error = match MappedFile::builder().open_path("test_errors_missing.txt") {
+        Err(error) => error,
+        Ok(_) => panic!("opened a missing file"),
+    };
+    assert!(matches!(error, Error::Io(_)));
+    assert!(std::error::Error::source(&error).is_some());
+
+    Ok(())
+}
+
+#[test]
+pub fn test_shared_lookups() {
+    let mut file = File::create("test_shared_lookups.txt").unwrap();
+    write!(file, "first\nsecond ñ\nthird").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_shared_lookups.txt").unwrap()).unwrap();
+
+    // A line borrowed from the file doesn't stop other lookups growing the cache
+    let first = r.line_at(0).unwrap();
+    let shared = &r;
+    assert_eq!(shared.unicode_at(13).unwrap(), 'ñ');
+    assert_eq!(r.line_at(2).unwrap(), "third");
+    assert_eq!(first, "first");
+
+    assert_eq!(shared.line_ending_positions().unwrap().len(), 3);
+    assert_eq!(r.line_col_of(13).unwrap(), (1, 7));
+}
+
+#[test]
+pub fn test_shared_between_threads() {
+    let mut file = File::create("test_shared_between_threads.txt").unwrap();
+    for line in 0..2000 {
+        writeln!(file, "line {} ñ€😀", line).unwrap();
+    }
+    file.flush().unwrap();
+
+    // A small interval so the threads race to record checkpoints as well as lines
+    let r = MappedFile::builder()
+        .checkpoint_interval(Some(100))
+        .open_path("test_shared_between_threads.txt")
+        .unwrap();
+    let expected = std::fs::read_to_string("test_shared_between_threads.txt").unwrap();
+    let expected: Vec<char> = expected.chars().collect();
+
+    std::thread::scope(|scope| {
+        for thread in 0..4 {
+            let (r, expected) = (&r, &expected);
+            scope.spawn(move || {
+                for line in (thread..2000).step_by(7) {
+                    assert_eq!(r.line_at(line).unwrap(), format!("line {} ñ€😀", line));
+                }
+                for index in (thread * 13..expected.len()).step_by(97) {
+                    assert_eq!(r.unicode_at(index).unwrap(), expected[index]);
+                }
+            });
+        }
+    });
+
+    // Every line start and checkpoint is recorded exactly once
+    let stats = r.build_index().unwrap();
+    assert_eq!(stats.lines, 2001);
+    assert_eq!(stats.checkpoints, expected.len() / 100);
+    assert_eq!(r.line_ending_positions().unwrap().len(), 2001);
+}
+
+#[test]
+pub fn test_handles() {
+    let mut file = File::create("test_handles.txt").unwrap();
+    write!(file, "ab\nñ€\n😀").unwrap();
+    file.flush().unwrap();
+
+    let r = std::sync::Arc::new(MappedFile::new(File::open("test_handles.txt").unwrap()).unwrap());
+
+    // Each handle reads on from its own cursor
+    let mut first = r.handle();
+    let mut second = r.handle();
+    assert_eq!(first.next(), Some('a'));
+    assert_eq!(first.next(), Some('b'));
+    assert_eq!(second.next(), Some('a'));
+    assert_eq!(first.position(), 2);
+
+    let mut copy = first.clone();
+    assert_eq!(copy.by_ref().collect::<String>(), "\nñ€\n😀");
+    assert_eq!(copy.position(), 7);
+    assert_eq!(first.next(), Some('\n'));
+
+    second.seek(4).unwrap();
+    assert_eq!(second.collect::<String>(), "€\n😀");
+    assert!(matches!(first.seek(8), Err(Error::OutOfBounds { .. })));
+
+    // Handles share the one index and can be sent to other threads
+    let handle = r.handle();
+    std::thread::spawn(move || assert_eq!(handle.line_at(2).unwrap(), "😀"))
+        .join()
+        .unwrap();
+    assert_eq!(first.line_ending_positions().unwrap().len(), 3);
+}
+
+#[test]
+pub fn test_saved_index() {
+    let mut file = File::create("test_saved_index.txt").unwrap();
+    write!(file, "one\r\ntwo ñ\rthree\n😀 four").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::builder()
+        .line_endings(LineEndings::unicode())
+        .checkpoint_interval(Some(4))
+        .open_path("test_saved_index.txt")
+        .unwrap();
+    assert_eq!(r.line_at(1).unwrap(), "two ñ");
+    r.save_index("test_saved_index.idx").unwrap();
+
+    // The settings and what was indexed so far come back, and the rest can still be indexed
+    let r = MappedFile::open_with_index(
+        File::open("test_saved_index.txt").unwrap(),
+        "test_saved_index.idx",
+    )
+    .unwrap();
+    assert_eq!(r.line_endings(), &LineEndings::unicode());
+    assert_eq!(r.checkpoint_interval(), Some(4));
+    assert_eq!(r.line_ending_positions().unwrap().len(), 3);
+    assert_eq!(r.line_at(3).unwrap(), "😀 four");
+    assert_eq!(r.unicode_at(9).unwrap(), 'ñ');
+
+    let stats = r.build_index().unwrap();
+    r.save_index("test_saved_index.idx").unwrap();
+    let r = MappedFile::open_with_index(
+        File::open("test_saved_index.txt").unwrap(),
+        "test_saved_index.idx",
+    )
+    .unwrap();
+    assert_eq!(r.build_index().unwrap(), stats);
+
+    // Anything that isn't an index is refused
+    let result = MappedFile::open_with_index(
+        File::open("test_saved_index.txt").unwrap(),
+        "test_saved_index.txt",
+    );
+    assert!(matches!(result, Err(Error::Io(_))));
+}
+
+#[test]
+pub fn test_stale_index() {
+    let mut file = File::create("test_stale_index.txt").unwrap();
+    write!(file, "one\ntwo\nthree").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_stale_index.txt").unwrap()).unwrap();
+    r.build_index().unwrap();
+    r.save_index("test_stale_index.idx").unwrap();
+    drop(r);
+
+    let reopen = || {
+        MappedFile::open_with_index(
+            File::open("test_stale_index.txt").unwrap(),
+            "test_stale_index.idx",
+        )
+    };
+    assert_eq!(reopen().unwrap().line_at(2).unwrap(), "three");
+
+    // Changing a byte without changing the length is caught by the checksum
+    std::fs::write("test_stale_index.txt", "one\ntwo\nthreE").unwrap();
+    let error = match reopen() {
+        Err(error) => error,
+        Ok(_) => panic!("used a stale index"),
+    };
+    assert!(matches!(error, Error::StaleIndex(_)));
+    assert_eq!(
+        error.to_string(),
+        "the saved index doesn't match the file: the file's contents have changed"
+    );
+
+    std::fs::write("test_stale_index.txt", "one\ntwo\nthree\n").unwrap();
+    assert!(matches!(
+        reopen(),
+        Err(Error::StaleIndex("the file's length has changed"))
+    ));
+
+    // A change is caught anywhere in a big file, not just near the start or end
+    let mut text = String::new();
+    for line in 0..200000 {
+        text.push_str(&format!("line {}\n", line));
+    }
+    std::fs::write("test_stale_index.txt", &text).unwrap();
+    let r = MappedFile::new(File::open("test_stale_index.txt").unwrap()).unwrap();
+    r.save_index("test_stale_index.idx").unwrap();
+    drop(r);
+    assert!(reopen().is_ok());
+    let mut changed = text.into_bytes();
+    let at = changed.len() / 64 + 5000;
+    changed[at] = if changed[at] == b'\n' { b' ' } else { b'\n' };
+    std::fs::write("test_stale_index.txt", changed).unwrap();
+    assert!(matches!(
+        reopen(),
+        Err(Error::StaleIndex("the file's contents have changed"))
+    ));
+
+    // A newer layout is refused rather than misread
+    let mut saved = std::fs::read("test_stale_index.idx").unwrap();
+    saved[8] = 99;
+    std::fs::write("test_stale_index.idx", saved).unwrap();
+    assert!(matches!(
+        reopen(),
+        Err(Error::StaleIndex("saved by a different version"))
+    ));
+}
+
+/// Encodes the positions as they're laid out in a saved index
+fn saved_positions(positions: &[(u64, u64)]) -> Vec<u8> {
+    positions
+        .iter()
+        .flat_map(|&(byte, char)| [byte.to_le_bytes(), char.to_le_bytes()])
+        .flatten()
+        .collect()
+}
+
+#[test]
+pub fn test_corrupted_index() {
+    let mut file = File::create("test_corrupted_index.txt").unwrap();
+    write!(file, "one\ntwo\nthree").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_corrupted_index.txt").unwrap()).unwrap();
+    r.build_index().unwrap();
+    r.save_index("test_corrupted_index.idx").unwrap();
+    let saved = std::fs::read("test_corrupted_index.idx").unwrap();
+
+    // Swaps some of the saved positions for others and tries to open the file with the result
+    let reopen_with = |from: &[(u64, u64)], to: &[(u64, u64)]| {
+        let (from, to) = (saved_positions(from), saved_positions(to));
+        let at = saved
+            .windows(from.len())
+            .position(|window| window == from)
+            .unwrap();
+        let mut corrupted = saved.clone();
+        corrupted[at..at + from.len()].copy_from_slice(&to);
+        std::fs::write("test_corrupted_index.idx", corrupted).unwrap();
+        MappedFile::open_with_index(
+            File::open("test_corrupted_index.txt").unwrap(),
+            "test_corrupted_index.idx",
+        )
+    };
+    let is_invalid = |result: Result<MappedFile, Error>| match result {
+        Err(Error::Io(e)) => e.kind() == std::io::ErrorKind::InvalidData,
+        _ => false,
+    };
+
+    assert_eq!(
+        reopen_with(&[(4, 4)], &[(4, 4)])
+            .unwrap()
+            .line_at(2)
+            .unwrap(),
+        "three"
+    );
+    // Line starts out of order
+    assert!(is_invalid(reopen_with(
+        &[(4, 4), (8, 8)],
+        &[(8, 8), (4, 4)]
+    )));
+    // A line start past the end of the file
+    assert!(is_invalid(reopen_with(&[(8, 8)], &[(80, 8)])));
+    // More characters than there are bytes for
+    assert!(is_invalid(reopen_with(&[(4, 4)], &[(4, 6)])));
+    // An end that isn't the end of the file
+    assert!(is_invalid(reopen_with(&[(13, 13)], &[(12, 13)])));
+    // The start of the file missing
+    assert!(is_invalid(reopen_with(
+        &[(0, 0), (4, 4)],
+        &[(4, 4), (8, 8)]
+    )));
+
+    // Cut short anywhere
+    for len in [20, 60, saved.len() - 1] {
+        std::fs::write("test_corrupted_index.idx", &saved[..len]).unwrap();
+        assert!(is_invalid(MappedFile::open_with_index(
+            File::open("test_corrupted_index.txt").unwrap(),
+            "test_corrupted_index.idx",
+        )));
+    }
+}
+
+#[test]
+pub fn test_compact_index() {
+    let mut file = File::create("test_compact_index.txt").unwrap();
+    let mut expected = String::new();
+    for line in 0..1000 {
+        // Lines of different lengths so the deltas aren't all the same
+        expected.push_str(&"ñ".repeat(line % 7));
+        expected.push_str(&format!("{}\n", "x".repeat(line % 300)));
+    }
+    write!(file, "{}", expected).unwrap();
+    file.flush().unwrap();
+
+    let open = |compact| {
+        MappedFile::builder()
+            .compact_index(compact)
+            .open_path("test_compact_index.txt")
+            .unwrap()
+    };
+    let compact = open(true);
+    let plain = open(false);
+    assert!(compact.compact_index());
+    assert!(!plain.compact_index());
+
+    let chars: Vec<char> = expected.chars().collect();
+    for index in (0..chars.len()).step_by(101) {
+        assert_eq!(compact.unicode_at(index).unwrap(), chars[index]);
+        assert_eq!(
+            compact.line_col_of(index).unwrap(),
+            plain.line_col_of(index).unwrap()
+        );
+    }
+    // Either side of where one block of line starts ends and the next begins
+    for line in [0, 63, 64, 65, 127, 128, 999, 1000] {
+        assert_eq!(compact.line_at(line).unwrap(), plain.line_at(line).unwrap());
+    }
+    assert_eq!(
+        compact.line_ending_positions().unwrap().len(),
+        plain.line_ending_positions().unwrap().len()
+    );
+
+    // Switching layouts keeps what has been cached
+    let mut r = open(true);
+    r.line_at(200).unwrap();
+    r.set_compact_index(false);
+    assert_eq!(r.line_ending_positions().unwrap().len(), 202);
+    assert_eq!(
+        r.char_index_at_byte(expected.len()).unwrap().char_position,
+        chars.len()
+    );
+    assert_eq!(r.build_index().unwrap(), compact.build_index().unwrap());
+}
+
+#[test]
+pub fn test_char_map() {
+    let mut file = File::create("test_char_map.txt").unwrap();
+    // One long line so there's nothing for the line cache to go on
+    let mut expected = String::new();
+    for i in 0..3000 {
+        expected.push(['a', 'ñ', '€', '😀'][i % 4]);
+    }
+    expected.push_str("\nend");
+    write!(file, "{}", expected).unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::builder()
+        .char_map(true)
+        .checkpoint_interval(None)
+        .open_path("test_char_map.txt")
+        .unwrap();
+    assert!(r.has_char_map());
+    assert_eq!(r.char_count().unwrap(), 3004);
+    // Nothing has had to be scanned yet
+    assert_eq!(r.line_ending_positions().unwrap().len(), 1);
+
+    let chars: Vec<char> = expected.chars().collect();
+    for index in (0..chars.len()).rev().step_by(37) {
+        assert_eq!(r.unicode_at(index).unwrap(), chars[index]);
+    }
+    let (byte, _) = expected.char_indices().nth(2001).unwrap();
+    assert_eq!(r.byte_offset_of(2001).unwrap(), byte);
+    assert_eq!(r.char_index_at_byte(byte + 1).unwrap().char_position, 2001);
+    assert_eq!(r.byte_offset_of(3004).unwrap(), expected.len());
+    assert!(matches!(
+        r.unicode_at(3005),
+        Err(Error::OutOfBounds {
+            index: 3005,
+            len: 3004
+        })
+    ));
+
+    // Lines are still found when they're needed
+    assert_eq!(r.line_col_of(3003).unwrap(), (1, 2));
+    assert_eq!(r.line_at(1).unwrap(), "end");
+
+    let mut file = File::create("test_char_map_invalid.txt").unwrap();
+    file.write_all(b"ok\xFF").unwrap();
+    file.flush().unwrap();
+    let r = MappedFile::new(File::open("test_char_map_invalid.txt").unwrap()).unwrap();
+    assert!(matches!(
+        r.build_char_map(),
+        Err(Error::InvalidUtf8 { byte_offset: 2, .. })
+    ));
+    assert!(!r.has_char_map());
+
+    // Rewriting the file underneath the map is an error rather than a panic
+    std::fs::write("test_char_map.txt", &expected).unwrap();
+    let r = MappedFile::builder()
+        .char_map(true)
+        .open_path("test_char_map.txt")
+        .unwrap();
+    std::fs::write("test_char_map.txt", vec![0x80; expected.len()]).unwrap();
+    assert!(matches!(r.unicode_at(2001), Err(Error::FileChanged)));
+}
+
+#[test]
+pub fn test_page_directory() {
+    let mut file = File::create("test_page_directory.txt").unwrap();
+    // Lots of short lines, then one line spanning a few pages, then short lines again
+    let mut expected = String::new();
+    for line in 0..100_000 {
+        expected.push_str(&format!("{}\n", line % 10));
+    }
+    expected.push_str(&"ñ".repeat(1_500_000));
+    expected.push('\n');
+    for line in 0..100_000 {
+        expected.push_str(&format!("{}€\n", line % 10));
+    }
+    write!(file, "{}", expected).unwrap();
+    file.flush().unwrap();
+
+    for compact in [true, false] {
+        let r = MappedFile::builder()
+            .compact_index(compact)
+            .eager(true)
+            .open_path("test_page_directory.txt")
+            .unwrap();
+
+        assert_eq!(r.line_at(99_999).unwrap(), "9");
+        assert_eq!(r.line_len(100_000).unwrap(), 1_500_000);
+        assert_eq!(r.line_at(100_001).unwrap(), "0€");
+        assert_eq!(r.line_at(200_000).unwrap(), "9€");
+
+        let long_line = 200_000;
+        assert_eq!(
+            r.line_col_of(long_line + 1_234_567).unwrap(),
+            (100_000, 1_234_567)
+        );
+        let after = long_line + 1_500_001;
+        assert_eq!(
+            r.line_col_of(after + 3 * 12_345 + 1).unwrap(),
+            (100_001 + 12_345, 1)
+        );
+        assert_eq!(r.unicode_at(after + 3 * 12_345 + 1).unwrap(), '€');
+
+        let byte = expected.len() - 4;
+        assert_eq!(
+            r.char_index_at_byte(byte).unwrap().char_position,
+            expected.chars().count() - 2
+        );
+    }
+}
+
+#[test]
+pub fn test_index_memory_limit() {
+    let mut file = File::create("test_index_memory_limit.txt").unwrap();
+    // Enough \r\n lines to cover a few pages of line starts
+    let text: String = (0..600_000)
+        .map(|line| format!("{:06}\r\n", line))
+        .collect();
+    write!(file, "{}", text).unwrap();
+    file.flush().unwrap();
+
+    for compact in [true, false] {
+        let r = MappedFile::builder()
+            .line_endings(LineEndings::crlf())
+            .compact_index(compact)
+            .index_memory_limit(Some(4096))
+            .eager(true)
+            .open_path("test_index_memory_limit.txt")
+            .unwrap();
+        assert_eq!(r.index_memory_limit(), Some(4096));
+
+        // Early lines were evicted while indexing and have to be scanned again
+        assert_eq!(r.line_at(3).unwrap(), "000003");
+        assert_eq!(r.line_at(599_999).unwrap(), "599999");
+        for line in [131_071, 131_072, 262_144, 300_000, 0] {
+            assert_eq!(r.line_at(line).unwrap(), format!("{:06}", line));
+            assert_eq!(r.line_col_of(line * 8 + 7).unwrap(), (line, 7));
+            assert_eq!(
+                r.unicode_at(line * 8 + 5).unwrap(),
+                ((line % 10) as u8 + b'0') as char
+            );
+        }
+        assert_eq!(r.byte_offset_of(450_000 * 8 + 2).unwrap(), 450_000 * 8 + 2);
+        assert_eq!(r.line_count().unwrap(), 600_001);
+        assert_eq!(r.line_ending_positions().unwrap().len(), 600_001);
+    }
+
+    // Lifting the limit keeps whatever is cached and stops evicting
+    let mut r = MappedFile::builder()
+        .line_endings(LineEndings::crlf())
+        .index_memory_limit(Some(0))
+        .open_path("test_index_memory_limit.txt")
+        .unwrap();
+    assert_eq!(r.line_at(500_000).unwrap(), "500000");
+    r.set_index_memory_limit(None);
+    assert_eq!(r.index_memory_limit(), None);
+    assert_eq!(r.line_at(1).unwrap(), "000001");
+}
+
+#[test]
+pub fn test_unicode_at_many() {
+    let mut file = File::create("test_unicode_at_many.txt").unwrap();
+    let text = "héllo\nwörld\n€uro ✓\n".repeat(500);
+    write!(file, "{}", text).unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_unicode_at_many.txt").unwrap()).unwrap();
+    let chars: Vec<char> = text.chars().collect();
+
+    // Out of order, repeated, far apart and out of bounds
+    let indices = [9_000, 3, 3, 0, 8_999, 20_000, 4_500, 4_501, 1, chars.len()];
+    let found = r.unicode_at_many(&indices);
+    assert_eq!(found.len(), indices.len());
+    for (&index, found) in indices.iter().zip(found) {
+        match chars.get(index) {
+            Some(&expected) => assert_eq!(found.unwrap(), expected),
+            None => assert!(matches!(found, Err(Error::OutOfBounds { .. }))),
+        }
+    }
+    assert!(r.unicode_at_many(&[]).is_empty());
+}
+
+#[test]
+pub fn test_string_range() {
+    let mut file = File::create("test_string_range.txt").unwrap();
+    write!(file, "héllo\nwörld").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_string_range.txt").unwrap()).unwrap();
+
+    assert_eq!(r.string_range(0..6).unwrap(), "héllo\n");
+    assert_eq!(r.string_range(6..11).unwrap(), "wörld");
+    assert_eq!(r.string_range(2..2).unwrap(), "");
+    assert!(matches!(
+        r.string_range(6..12),
+        Err(Error::OutOfBounds { .. })
+    ));
+
+    let mut out = String::from("> ");
+    r.write_range_to(1..5, &mut out).unwrap();
+    assert_eq!(out, "> éllo");
+
+    // Lossy files have nothing to borrow the replacements from, but can still be copied
+    let mut r = InMemoryFile::from_bytes(b"w\xFFrld".to_vec());
+    assert!(matches!(
+        r.string_range(0..5),
+        Err(Error::InvalidUtf8 { .. })
+    ));
+    r.set_decode_policy(DecodePolicy::Lossy);
+    assert_eq!(r.string_range(0..5).unwrap(), "w\u{FFFD}rld");
+}
+
+#[test]
+pub fn test_chars_around() {
+    let mut file = File::create("test_chars_around.txt").unwrap();
+    write!(file, "let x = «wörld»;\nnext").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_chars_around.txt").unwrap()).unwrap();
+
+    assert_eq!(r.chars_around(9, 3, 2).unwrap(), ("= «wör".to_string(), 3));
+    // Cut short at either end of the file
+    assert_eq!(r.chars_around(1, 5, 1).unwrap(), ("let".to_string(), 1));
+    assert_eq!(r.chars_around(19, 2, 10).unwrap(), ("next".to_string(), 2));
+    assert_eq!(r.chars_around(4, 0, 0).unwrap(), ("x".to_string(), 0));
+    assert!(matches!(
+        r.chars_around(21, 2, 2),
+        Err(Error::OutOfBounds { .. })
+    ));
+}
+
+#[test]
+pub fn test_cursor() {
+    let mut file = File::create("test_cursor.txt").unwrap();
+    write!(file, "ab\nñ€\n😀").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_cursor.txt").unwrap()).unwrap();
+
+    let mut cursor = r.cursor();
+    assert_eq!(cursor.prev(), None);
+    assert_eq!(cursor.peek(), Some('a'));
+    assert_eq!(cursor.next(), Some('a'));
+    assert_eq!(cursor.next(), Some('b'));
+    assert_eq!(cursor.prev(), Some('b'));
+    assert_eq!(cursor.position().char_position, 1);
+
+    cursor.seek_to_line_col(1, 1).unwrap();
+    assert_eq!(cursor.position().byte_position, 5);
+    assert_eq!(cursor.peek(), Some('€'));
+    assert_eq!(cursor.prev(), Some('ñ'));
+    assert_eq!(cursor.prev(), Some('\n'));
+    assert_eq!(cursor.position().char_position, 2);
+
+    cursor.seek_to_char(7).unwrap();
+    assert_eq!(cursor.peek(), None);
+    assert_eq!(cursor.prev(), Some('😀'));
+    assert_eq!(cursor.clone().collect::<String>(), "😀");
+    assert_eq!(cursor.position().char_position, 6);
+
+    assert!(matches!(
+        cursor.seek_to_char(8),
+        Err(Error::OutOfBounds { .. })
+    ));
+    assert!(matches!(
+        cursor.seek_to_line_col(1, 3),
+        Err(Error::OutOfBounds { .. })
+    ));
+    assert_eq!(cursor.position().char_position, 6);
+}
+
+#[test]
+pub fn test_index_operator() {
+    let mut file = File::create("test_index_operator.txt").unwrap();
+    write!(file, "añ\n€😀").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_index_operator.txt").unwrap()).unwrap();
+
+    assert_eq!(&r[1], "ñ");
+    assert_eq!(&r[0], "a");
+    assert_eq!(&r[4], "😀");
+    assert_eq!(r.get(3), Some('€'));
+    assert_eq!(r.get(5), None);
+    assert_eq!(r.get(50), None);
+
+    let mut r = InMemoryFile::from_bytes(b"a\xFFb".to_vec());
+    assert_eq!(r.get(1), None);
+    r.set_decode_policy(DecodePolicy::Lossy);
+    assert_eq!(&r[1], "\u{FFFD}");
+    assert_eq!(&r[2], "b");
+    assert_eq!(r.get(1), Some('\u{FFFD}'));
+}
+
+#[test]
+#[should_panic(expected = "out of bounds")]
+pub fn test_index_operator_out_of_bounds() {
+    let r = MappedFile::from("añ");
+    let _ = &r[2];
+}
+
+#[test]
+pub fn test_into_iterator() {
+    let mut file = File::create("test_into_iterator.txt").unwrap();
+    write!(file, "añ\n€😀").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_into_iterator.txt").unwrap()).unwrap();
+
+    let mut seen = String::new();
+    for c in &r {
+        seen.push(c);
+    }
+    assert_eq!(seen, "añ\n€😀");
+    assert_eq!((&r).into_iter().filter(|c| !c.is_ascii()).count(), 3);
+
+    let owned: Result<String, Error> = r.into_iter().collect();
+    assert_eq!(owned.unwrap(), "añ\n€😀");
+
+    // An owned file gives an error at invalid utf8 instead of just stopping
+    let r = InMemoryFile::from_bytes(b"ab\xFFc".to_vec());
+    assert_eq!((&r).into_iter().collect::<String>(), "ab");
+    let mut chars = r.into_iter();
+    assert_eq!(chars.next().unwrap().unwrap(), 'a');
+    assert_eq!(chars.next().unwrap().unwrap(), 'b');
+    assert!(matches!(
+        chars.next(),
+        Some(Err(Error::InvalidUtf8 { byte_offset: 2, .. }))
+    ));
+    assert!(chars.next().is_none());
+
+    let mut r = chars.into_inner();
+    r.set_decode_policy(DecodePolicy::Lossy);
+    let lossy: Result<String, Error> = r.into_iter().collect();
+    assert_eq!(lossy.unwrap(), "ab\u{FFFD}c");
+}
+
+#[test]
+pub fn test_compare() {
+    let mut file = File::create("test_compare.txt").unwrap();
+    write!(file, "añ\n€😀").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_compare.txt").unwrap()).unwrap();
+
+    assert!(r == "añ\n€😀");
+    assert!(r == *"añ\n€😀");
+    let owned = "añ\n€😀".to_string();
+    assert!(r == owned);
+    assert!("añ\n€😀" == r);
+    assert!(r != "añ\n€");
+    assert!(r != "añ\n€😀!");
+    assert!(r != "");
+
+    // Files are compared by their text whatever they're read from
+    let same = InMemoryFile::from(owned);
+    let shorter = MappedFile::from("añ\n€");
+    let empty = MappedFile::from("");
+    assert!(r == same);
+    assert!(r != shorter);
+    assert!(empty == MappedFile::from_bytes(Vec::new()));
+
+    // Invalid utf8 isn't equal to anything, even itself, unless decoded lossily
+    let mut invalid = InMemoryFile::from_bytes(b"a\xFF".to_vec());
+    assert!(invalid != "a");
+    let copy = InMemoryFile::from_bytes(b"a\xFF".to_vec());
+    assert!(invalid != copy);
+    invalid.set_decode_policy(DecodePolicy::Lossy);
+    assert!(invalid == "a\u{FFFD}");
+    assert!(invalid != "a");
+}
+
+#[test]
+pub fn test_index_stats() {
+    let mut file = File::create("test_index_stats.txt").unwrap();
+    write!(file, "{}", "añ€\n".repeat(10_000)).unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_index_stats.txt").unwrap()).unwrap();
+
+    let stats = r.index_stats();
+    assert_eq!(stats.bytes, 70_000);
+    assert_eq!(stats.indexed_bytes, 0);
+    assert_eq!(stats.lines, 1);
+    assert!(!stats.complete);
+
+    r.line_at(100).unwrap();
+    let stats = r.index_stats();
+    assert!(stats.lines > 100);
+    assert_eq!(stats.indexed_bytes, (stats.lines - 1) * 7);
+    assert_eq!(stats.indexed_chars, (stats.lines - 1) * 4);
+    assert!(stats.line_memory > 0);
+    assert!(!stats.complete);
+
+    r.build_index().unwrap();
+    let stats = r.index_stats();
+    assert_eq!(stats.indexed_bytes, 70_000);
+    assert_eq!(stats.indexed_chars, 40_000);
+    assert_eq!(stats.lines, 10_001);
+    assert!(stats.complete);
+    assert!(!stats.char_map);
+
+    assert_eq!(
+        format!("{:?}", r),
+        "MappedFile { bytes: 70000, source: \"mmap\", lines: 10001, checkpoints: 0, \
+         indexed_bytes: 70000, complete: true }"
+    );
+    assert!(format!("{:?}", MappedFile::from("añ")).contains("source: \"memory\""));
+}
+
+#[test]
+pub fn test_reader() {
+    let mut file = File::create("test_reader.txt").unwrap();
+    write!(file, "héllo\nwörld").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_reader.txt").unwrap()).unwrap();
+
+    let mut text = String::new();
+    r.reader(6..11).unwrap().read_to_string(&mut text).unwrap();
+    assert_eq!(text, "wörld");
+
+    // Seeking is in bytes within the range
+    let mut reader = r.reader(1..8).unwrap();
+    assert_eq!(reader.seek(SeekFrom::End(-3)).unwrap(), 6);
+    let mut end = Vec::new();
+    reader.read_to_end(&mut end).unwrap();
+    assert_eq!(end, "wö".as_bytes());
+    reader.seek(SeekFrom::Start(2)).unwrap();
+    let mut buf = [0; 3];
+    reader.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"llo");
+
+    let mut copied = Vec::new();
+    std::io::copy(&mut r.reader(0..11).unwrap(), &mut copied).unwrap();
+    assert_eq!(copied, "héllo\nwörld".as_bytes());
+    assert!(matches!(r.reader(6..12), Err(Error::OutOfBounds { .. })));
+
+    let mut r = InMemoryFile::from_bytes(b"a\xFFb".to_vec());
+    assert!(matches!(r.reader(0..3), Err(Error::InvalidUtf8 { .. })));
+    r.set_decode_policy(DecodePolicy::Lossy);
+    let mut text = String::new();
+    r.reader(0..3).unwrap().read_to_string(&mut text).unwrap();
+    assert_eq!(text, "a\u{FFFD}b");
+}
+
+#[test]
+pub fn test_buf_read() {
+    let mut file = File::create("test_buf_read.txt").unwrap();
+    write!(file, "first\nsécond\r\n\nlast").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_buf_read.txt").unwrap()).unwrap();
+
+    let lines: Vec<String> = r
+        .file_reader()
+        .unwrap()
+        .lines()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(lines, ["first", "sécond", "", "last"]);
+    // Nothing has to be indexed to read the whole file
+    assert_eq!(r.index_stats().lines, 1);
+
+    let mut reader = r.reader(6..14).unwrap();
+    let mut line = String::new();
+    assert_eq!(reader.read_line(&mut line).unwrap(), 9);
+    assert_eq!(line, "sécond\r\n");
+    line.clear();
+    assert_eq!(reader.read_line(&mut line).unwrap(), 0);
+
+    let r = InMemoryFile::from_bytes(b"a\n\xFF".to_vec());
+    assert!(matches!(
+        r.file_reader(),
+        Err(Error::InvalidUtf8 { byte_offset: 2, .. })
+    ));
+}
+
+#[test]
+pub fn test_lines() {
+    let mut file = File::create("test_lines.txt").unwrap();
+    write!(file, "first\nsécond\n\nlast").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_lines.txt").unwrap()).unwrap();
+
+    let lines: Vec<LineRef> = r.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[1].line, 1);
+    assert_eq!(lines[1].text, "sécond");
+    assert_eq!(lines[1].start.char_position, 6);
+    assert_eq!(lines[2].text, "");
+    assert_eq!(lines[2].start.byte_position, 14);
+    assert_eq!(lines[3].text, "last");
+    assert_eq!(lines[3].start.char_position, 14);
+
+    let r = InMemoryFile::from_bytes(b"a\nb\xFF\nc".to_vec());
+    assert_eq!(r.lines().map(|line| line.text).collect::<Vec<_>>(), ["a"]);
+}
+
+#[test]
+pub fn test_lines_range() {
+    let mut file = File::create("test_lines_range.txt").unwrap();
+    write!(file, "first\nsécond\n\nlast").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_lines_range.txt").unwrap()).unwrap();
+
+    assert_eq!(r.lines_range(1..3).unwrap(), "sécond\n\n");
+    assert_eq!(r.lines_range(0..1).unwrap(), "first\n");
+    assert_eq!(r.lines_range(2..4).unwrap(), "\nlast");
+    assert_eq!(r.lines_range(0..4).unwrap(), "first\nsécond\n\nlast");
+    assert_eq!(r.lines_range(4..4).unwrap(), "");
+    assert!(matches!(
+        r.lines_range(3..5),
+        Err(Error::OutOfBounds { .. })
+    ));
+    assert!(matches!(
+        r.lines_range(5..5),
+        Err(Error::OutOfBounds { .. })
+    ));
+}
+
+#[test]
+pub fn test_newline_positions() {
+    let mut file = File::create("test_newline_positions.txt").unwrap();
+    write!(file, "{}", "añ€\n".repeat(10_000)).unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_newline_positions.txt").unwrap()).unwrap();
+
+    let first: Vec<CharPosition> = r.newline_positions().take(3).collect();
+    assert_eq!(first[0].byte_position, 7);
+    assert_eq!(first[2].char_position, 12);
+    // Only what was needed has been indexed
+    assert!(!r.index_stats().complete);
+
+    let all: Vec<CharPosition> = r.newline_positions().collect();
+    assert_eq!(all.len(), 10_000);
+    assert_eq!(all[9_999].byte_position, 70_000);
+    assert!(r.index_stats().complete);
+
+    assert_eq!(
+        MappedFile::from("no newline").newline_positions().count(),
+        0
+    );
+}
+
+#[test]
+pub fn test_shrink_index() {
+    let mut file = File::create("test_shrink_index.txt").unwrap();
+    write!(file, "{}", "añ€\n".repeat(10_000)).unwrap();
+    file.flush().unwrap();
+
+    let mut r = MappedFile::new(File::open("test_shrink_index.txt").unwrap()).unwrap();
+    r.set_checkpoint_interval(Some(100));
+
+    r.build_index().unwrap();
+    let full = r.index_memory_bytes();
+    assert!(full > 0);
+
+    r.shrink_index_to(100);
+    let stats = r.index_stats();
+    assert_eq!(stats.lines, 100);
+    assert_eq!(stats.indexed_chars, 99 * 4);
+    assert!(stats.checkpoints <= 4);
+    assert!(!stats.complete);
+    assert!(r.index_memory_bytes() < full);
+
+    // The rest is found again when it's needed
+    assert_eq!(r.line_at(5_000).unwrap(), "añ€");
+    assert_eq!(r.unicode_at(39_999).unwrap(), '\n');
+    assert_eq!(r.char_count().unwrap(), 40_000);
+
+    r.build_char_map().unwrap();
+    let with_map = r.index_memory_bytes();
+    r.clear_index();
+    assert!(!r.has_char_map());
+    assert_eq!(r.index_stats().lines, 1);
+    assert!(r.index_memory_bytes() < with_map);
+    assert_eq!(r.line_at(9_999).unwrap(), "añ€");
+
+    // Shrinking past the end does nothing
+    r.build_index().unwrap();
+    r.shrink_index_to(20_000);
+    assert!(r.index_stats().complete);
+}
+
+#[test]
+pub fn test_stale_file() {
+    let mut file = File::create("test_stale_file.txt").unwrap();
+    write!(file, "héllo\nwörld\n").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_stale_file.txt").unwrap()).unwrap();
+    let checked = MappedFile::builder()
+        .check_stale(true)
+        .open_path("test_stale_file.txt")
+        .unwrap();
+    let buffered = MappedFile::from_source(
+        BufferedSource::new(File::open("test_stale_file.txt").unwrap()).unwrap(),
+    );
+
+    assert!(!r.is_stale().unwrap());
+    assert!(checked.check_stale());
+    assert_eq!(checked.unicode_at(1).unwrap(), 'é');
+    assert!(!MappedFile::from("text").is_stale().unwrap());
+
+    // Growing the file changes its length, whatever the modification time's resolution
+    write!(file, "more").unwrap();
+    file.flush().unwrap();
+
+    assert!(r.is_stale().unwrap());
+    assert!(buffered.is_stale().unwrap());
+    assert!(matches!(checked.unicode_at(1), Err(Error::FileChanged)));
+    assert!(matches!(checked.line_at(1), Err(Error::FileChanged)));
+    assert!(matches!(
+        checked.char_index_at_byte(1),
+        Err(Error::FileChanged)
+    ));
+    // Without checking, lookups carry on with what was mapped
+    assert_eq!(r.line_at(1).unwrap(), "wörld");
+}
+
+#[test]
+pub fn test_reload() {
+    let mut file = File::create("test_reload.txt").unwrap();
+    write!(file, "first\nlog\n").unwrap();
+    file.flush().unwrap();
+
+    let mut r = MappedFile::open_path("test_reload.txt").unwrap();
+    assert_eq!(r.build_index().unwrap().lines, 3);
+
+    // Rotating the log replaces the file at the path with a new one
+    std::fs::rename("test_reload.txt", "test_reload_rotated.txt").unwrap();
+    let mut file = File::create("test_reload.txt").unwrap();
+    writeln!(file, "sécond").unwrap();
+    file.flush().unwrap();
+
+    assert_eq!(r.line_at(1).unwrap(), "log");
+    r.reload().unwrap();
+    assert_eq!(r.map.path(), Some(std::path::Path::new("test_reload.txt")));
+    assert_eq!(r.line_at(0).unwrap(), "sécond");
+    assert_eq!(r.line_count().unwrap(), 2);
+    assert!(!r.is_stale().unwrap());
+
+    // Without a path the same file is mapped again
+    let mut r = MappedFile::new(File::open("test_reload.txt").unwrap()).unwrap();
+    r.build_char_map().unwrap();
+    write!(file, "third").unwrap();
+    file.flush().unwrap();
+    assert!(r.is_stale().unwrap());
+
+    r.reload().unwrap();
+    assert!(!r.is_stale().unwrap());
+    assert!(!r.has_char_map());
+    assert_eq!(r.line_at(1).unwrap(), "third");
+    assert_eq!(r.char_count().unwrap(), 12);
+}
+
+#[test]
+pub fn test_refresh() {
+    let mut file = File::create("test_refresh.txt").unwrap();
+    write!(file, "first\nsec").unwrap();
+    file.flush().unwrap();
+
+    let mut r = MappedFile::new(File::open("test_refresh.txt").unwrap()).unwrap();
+    r.set_checkpoint_interval(Some(2));
+    assert_eq!(
+        r.refresh().unwrap(),
+        Appended {
+            chars: 9..9,
+            lines: 2..2
+        }
+    );
+
+    // Half a character is only counted once the rest of it arrives
+    file.write_all(b"ond\nth\xC3").unwrap();
+    file.flush().unwrap();
+    r.set_decode_policy(DecodePolicy::Lossy);
+    let appended = r.refresh().unwrap();
+    assert_eq!(appended.chars, 9..16);
+    assert_eq!(appended.lines, 1..3);
+    assert_eq!(r.line_at(1).unwrap(), "second");
+
+    file.write_all(b"\xAFrd\n").unwrap();
+    file.flush().unwrap();
+    let appended = r.refresh().unwrap();
+    assert_eq!(appended.chars, 15..19);
+    assert_eq!(appended.lines, 2..4);
+    assert_eq!(r.line_at(2).unwrap(), "thïrd");
+    assert_eq!(r.unicode_at(15).unwrap(), 'ï');
+    assert_eq!(r.line_count().unwrap(), 4);
+
+    // The whole file is indexed just as if it had been opened now
+    let fresh = MappedFile::new(File::open("test_refresh.txt").unwrap()).unwrap();
+    let byte_positions = |file: &MappedFile| {
+        file.line_ending_positions()
+            .unwrap()
+            .iter()
+            .map(|position| position.byte_position)
+            .collect::<Vec<_>>()
+    };
+    fresh.build_index().unwrap();
+    assert_eq!(byte_positions(&r), byte_positions(&fresh));
+
+    // Growing without a newline carries on the last line
+    write!(file, "fourth").unwrap();
+    file.flush().unwrap();
+    assert_eq!(
+        r.refresh().unwrap(),
+        Appended {
+            chars: 19..25,
+            lines: 3..4
+        }
+    );
+
+    file.set_len(3).unwrap();
+    assert!(matches!(r.refresh(), Err(Error::FileChanged)));
+}
+
+#[test]
+pub fn test_snapshot() {
+    let mut file = File::create("test_snapshot.txt").unwrap();
+    write!(file, "ünë\ntwo\nthree").unwrap();
+    file.flush().unwrap();
+
+    let mut r = MappedFile::new(File::open("test_snapshot.txt").unwrap()).unwrap();
+    assert_eq!(r.line_at(1).unwrap(), "two");
+
+    let snapshot = r.snapshot().unwrap();
+    assert_eq!(snapshot.index_stats().lines, r.index_stats().lines);
+    assert_eq!(snapshot.map.source().len(), r.map.len());
+
+    // Writing to the file doesn't change what the snapshot reads, even once the file is mapped again
+    write!(file, "\nfour").unwrap();
+    file.flush().unwrap();
+    assert_eq!(r.refresh().unwrap().lines, 2..4);
+    assert_eq!(r.line_at(3).unwrap(), "four");
+    assert_eq!(snapshot.char_count().unwrap(), 13);
+    assert_eq!(snapshot.line_count().unwrap(), 3);
+    assert_eq!(snapshot.str_range(0..3).unwrap(), "ünë");
+    assert!(snapshot.unicode_at(13).is_err());
+    assert!(snapshot == "ünë\ntwo\nthree");
+
+    // What the snapshot indexes stays with the snapshot
+    assert!(snapshot.index_stats().complete);
+    assert_eq!(r.char_count().unwrap(), 18);
+
+    // The snapshot keeps the old mapping even after the file has gone
+    drop(r);
+    assert_eq!(snapshot.line_at(2).unwrap(), "three");
+}
+
+#[test]
+pub fn test_guarded() {
+    let mut file = File::create("test_guarded.txt").unwrap();
+    write!(file, "one\n{}\nthree", "x".repeat(10000)).unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::builder()
+        .guarded(true)
+        .open(File::open("test_guarded.txt").unwrap())
+        .unwrap();
+    assert!(r.map.is_guarded());
+    assert_eq!(r.line_at(0).unwrap(), "one");
+
+    // Reading what was truncated away fails instead of crashing
+    file.set_len(3).unwrap();
+    assert!(matches!(r.line_at(2), Err(Error::Truncated)));
+    assert!(matches!(r.unicode_at(5000), Err(Error::Truncated)));
+}
+
+#[test]
+pub fn test_private_copy() {
+    let mut file = File::create("test_private_copy.txt").unwrap();
+    write!(file, "one\ntwo\nthree").unwrap();
+    file.flush().unwrap();
+
+    let mut r = MappedFile::builder()
+        .private_copy(true)
+        .open_path("test_private_copy.txt")
+        .unwrap();
+    assert_eq!(r.backend(), Backend::Copy);
+
+    // Neither writing over the file nor truncating it changes the copy
+    file.seek(SeekFrom::Start(0)).unwrap();
+    write!(file, "ONE").unwrap();
+    file.flush().unwrap();
+    file.set_len(5).unwrap();
+    assert_eq!(r.line_at(0).unwrap(), "one");
+    assert_eq!(r.line_at(2).unwrap(), "three");
+
+    // Until it's reloaded on purpose
+    r.reload().unwrap();
+    assert_eq!(r.backend(), Backend::Copy);
+    assert_eq!(r.str_range(0..5).unwrap(), "ONE\nt");
+}
+
+#[test]
+pub fn test_private_copy_dont_need() {
+    let mut file = File::create("test_private_copy_dont_need.txt").unwrap();
+    write!(
+        file,
+        "hello
+world"
+    )
+    .unwrap();
+    file.flush().unwrap();
+
+    // The copy is the only place the text is kept, so it mustn't be dropped however it's asked for
+    let builders = [
+        MappedFile::builder().private_copy(true),
+        MappedFile::builder().huge_pages(true),
+        MappedFile::builder()
+            .private_copy(true)
+            .access(Access::DontNeed),
+    ];
+    for builder in builders {
+        let r = builder
+            .open_path("test_private_copy_dont_need.txt")
+            .unwrap();
+        assert_eq!(r.line_at(1).unwrap(), "world");
+        r.advise(Access::DontNeed).unwrap();
+        assert_eq!(r.bytes_range(0..5).unwrap(), b"hello");
+        assert_eq!(r.line_at(1).unwrap(), "world");
+        assert_eq!(r.unicode_at(6).unwrap(), 'w');
+    }
+}
+
+#[test]
+pub fn test_advise() {
+    let mut file = File::create("test_advise.txt").unwrap();
+    write!(file, "one\ntwo\n").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_advise.txt").unwrap()).unwrap();
+    for access in [
+        Access::Random,
+        Access::Sequential,
+        Access::WillNeed,
+        Access::DontNeed,
+        Access::Normal,
+    ] {
+        r.advise(access).unwrap();
+        assert_eq!(r.line_at(1).unwrap(), "two");
+    }
+
+    // There's nothing to advise about a file that isn't mapped
+    File::create("test_advise_empty.txt").unwrap();
+    let empty = MappedFile::new(File::open("test_advise_empty.txt").unwrap()).unwrap();
+    empty.advise(Access::Random).unwrap();
+}
+
+#[test]
+pub fn test_prefetch() {
+    let mut file = File::create("test_prefetch.txt").unwrap();
+    for line in 0..10000 {
+        writeln!(file, "line {}", line).unwrap();
+    }
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_prefetch.txt").unwrap()).unwrap();
+    r.prefetch(20000..60000).unwrap();
+    // Ranges past the end are cut off
+    r.prefetch(60000..usize::MAX).unwrap();
+    r.prefetch(usize::MAX - 1..usize::MAX).unwrap();
+    assert_eq!(r.line_at(5000).unwrap(), "line 5000");
+}
+
+#[test]
+pub fn test_huge_pages() {
+    let mut file = File::create("test_huge_pages.txt").unwrap();
+    write!(file, "one\ntwö\nthree").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::builder()
+        .huge_pages(true)
+        .open_path("test_huge_pages.txt")
+        .unwrap();
+    assert_eq!(r.backend(), Backend::Copy);
+    // Whether huge pages could be had depends on the system, but the file reads the same either way
+    let _ = r.huge_pages();
+    assert_eq!(r.map.len(), 14);
+    assert_eq!(r.line_at(1).unwrap(), "twö");
+    assert_eq!(r.char_count().unwrap(), 13);
+
+    let plain = MappedFile::new(File::open("test_huge_pages.txt").unwrap()).unwrap();
+    assert_eq!(plain.huge_pages(), HugePages::No);
+}
+
+#[test]
+pub fn test_lock() {
+    let mut file = File::create("test_lock.txt").unwrap();
+    write!(file, "one\ntwo\nthree").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::builder()
+        .lock(true)
+        .open(File::open("test_lock.txt").unwrap())
+        .unwrap();
+    assert_eq!(r.line_at(2).unwrap(), "three");
+    r.unlock().unwrap();
+
+    r.lock_range(4..8).unwrap();
+    assert!(matches!(
+        r.lock_range(4..100),
+        Err(Error::OutOfBounds {
+            index: 100,
+            len: 13
+        })
+    ));
+    r.unlock().unwrap();
+}
+
+#[test]
+pub fn test_view() {
+    let r = MappedFile::from("header\nrëcord one\nrecord two\nfooter\n");
+
+    // The two records, counted from the start of the view
+    let view = r.view(7..29).unwrap();
+    assert_eq!(view.map.char_range(), 7..29);
+    assert_eq!(view.map.byte_range(), 7..30);
+    assert_eq!(view.char_count().unwrap(), 22);
+    assert_eq!(view.line_count().unwrap(), 3);
+    assert_eq!(view.line_at(0).unwrap(), "rëcord one");
+    assert_eq!(view.line_at(1).unwrap(), "record two");
+    assert_eq!(view.line_at(2).unwrap(), "");
+    assert_eq!(view.unicode_at(1).unwrap(), 'ë');
+    assert_eq!(view.line_col_of(13).unwrap(), (1, 2));
+    assert!(view == "rëcord one\nrecord two\n");
+    assert!(view.unicode_at(22).is_err());
+
+    // Views of views are counted from their own start
+    let inner = view.view(11..17).unwrap();
+    assert!(inner == "record");
+    assert_eq!(inner.map.char_range(), 11..17);
+
+    assert!(r.view(30..40).is_err());
+}
+
+#[test]
+pub fn test_find_char() {
+    let r = MappedFile::from("a\tb€c\td€\n\t");
+
+    let tab = r.find_char('\t', 0).unwrap().unwrap();
+    assert_eq!((tab.char_position, tab.byte_position), (1, 1));
+    let tab = r.find_char('\t', 2).unwrap().unwrap();
+    assert_eq!((tab.char_position, tab.byte_position), (5, 7));
+    let euro = r.find_char('€', 4).unwrap().unwrap();
+    assert_eq!((euro.char_position, euro.byte_position), (7, 9));
+    assert!(r.find_char('x', 0).unwrap().is_none());
+    assert!(r.find_char('\t', 10).unwrap().is_none());
+    assert!(r.find_char('\t', 11).is_err());
+
+    let tabs: Vec<usize> = r.positions_of('\t').map(|p| p.char_position).collect();
+    assert_eq!(tabs, [1, 5, 9]);
+    let euros: Vec<usize> = r.positions_of('€').map(|p| p.byte_position).collect();
+    assert_eq!(euros, [3, 9]);
+
+    // Lossily decoded files count replacements as one character each
+    let mut r = InMemoryFile::from_bytes(b"\xFF\xFEx".to_vec());
+    assert!(r.find_char('x', 0).is_err());
+    r.set_decode_policy(DecodePolicy::Lossy);
+    assert_eq!(r.find_char('x', 0).unwrap().unwrap().char_position, 2);
+}
+
+#[test]
+pub fn test_find() {
+    let r = MappedFile::from("the cät sat on the mät, the end");
+
+    let found = r.find("the", 0).unwrap().unwrap();
+    assert_eq!((found.char_position, found.byte_position), (0, 0));
+    let found = r.find("the", 1).unwrap().unwrap();
+    assert_eq!((found.char_position, found.byte_position), (15, 16));
+    let found = r.find("ät,", 0).unwrap().unwrap();
+    assert_eq!((found.char_position, found.byte_position), (20, 21));
+    assert!(r.find("dog", 0).unwrap().is_none());
+    assert_eq!(r.find("", 4).unwrap().unwrap().char_position, 4);
+    assert!(r.find("the", 40).is_err());
+
+    let all: Vec<usize> = r.find_all("the").map(|p| p.char_position).collect();
+    assert_eq!(all, [0, 15, 24]);
+    let all: Vec<usize> = r.find_all("ät").map(|p| p.char_position).collect();
+    assert_eq!(all, [5, 20]);
+    // Matches don't overlap
+    let r = MappedFile::from("ääää");
+    let all: Vec<usize> = r.find_all("ää").map(|p| p.char_position).collect();
+    assert_eq!(all, [0, 2]);
+    let all: Vec<usize> = r.find_all("").map(|p| p.char_position).collect();
+    assert_eq!(all, [0, 1, 2, 3, 4]);
+
+    // Matches across the stretches the file is searched in
+    let text = format!("{}needle{}needle", "ü".repeat(70000), "x".repeat(70000));
+    let r = MappedFile::from(text.as_str());
+    let all: Vec<usize> = r.find_all("needle").map(|p| p.char_position).collect();
+    assert_eq!(all, [70000, 140006]);
+}
+
+#[test]
+pub fn test_rfind() {
+    let r = MappedFile::from("{ä {b} {ç}\n}");
+
+    let close = r.rfind("}", 12).unwrap().unwrap();
+    assert_eq!((close.char_position, close.byte_position), (11, 13));
+    // Each match's index finds the one before it
+    let open = r.rfind_char('{', 11).unwrap().unwrap();
+    assert_eq!((open.char_position, open.byte_position), (7, 8));
+    let open = r.rfind_char('{', open.char_position).unwrap().unwrap();
+    assert_eq!(open.char_position, 3);
+    let open = r.rfind_char('{', open.char_position).unwrap().unwrap();
+    assert_eq!(open.char_position, 0);
+    assert!(r.rfind_char('{', 0).unwrap().is_none());
+
+    // The match has to end before the index
+    assert_eq!(r.rfind("{ç}", 11).unwrap().unwrap().char_position, 7);
+    assert!(r.rfind("{ç}", 9).unwrap().is_none());
+    assert_eq!(r.rfind("", 5).unwrap().unwrap().char_position, 5);
+    assert!(r.rfind("}", 13).is_err());
+
+    // Matches across the stretches the file is searched in
+    let text = format!("needle{}needle{}", "ü".repeat(70000), "x".repeat(70000));
+    let r = MappedFile::from(text.as_str());
+    let last = r.rfind("needle", 140012).unwrap().unwrap();
+    assert_eq!((last.char_position, last.byte_position), (70006, 140006));
+    let first = r.rfind("needle", last.char_position).unwrap().unwrap();
+    assert_eq!(first.char_position, 0);
+}
+
+/// Collects the character ranges of every match for some text
+fn match_spans<S: TextSource>(
+    r: &MappedFile<S>,
+    needle: &str,
+    case: CaseSensitivity,
+) -> Vec<(usize, usize)> {
+    r.find_all_with(needle, case)
+        .map(|m| (m.start.char_position, m.end.char_position))
+        .collect()
+}
+
+#[test]
+pub fn test_find_ignoring_case() {
+    let r = MappedFile::from("Die STRASSE, die Straße, die strasse");
+
+    assert_eq!(
+        match_spans(&r, "strasse", CaseSensitivity::Sensitive),
+        [(29, 36)]
+    );
+    assert_eq!(
+        match_spans(&r, "strasse", CaseSensitivity::Simple),
+        [(4, 11), (29, 36)]
+    );
+    assert_eq!(
+        match_spans(&r, "strasse", CaseSensitivity::Full),
+        [(4, 11), (17, 23), (29, 36)]
+    );
+    assert_eq!(
+        match_spans(&r, "STRAẞE", CaseSensitivity::Full),
+        [(4, 11), (17, 23), (29, 36)]
+    );
+    assert_eq!(
+        match_spans(&r, "STRAẞE", CaseSensitivity::Simple),
+        [(17, 23)]
+    );
+    // A match can't stop partway through what a character folds to
+    assert_eq!(
+        match_spans(&r, "stras", CaseSensitivity::Full),
+        [(4, 9), (29, 34)]
+    );
+
+    let found = r
+        .find_with("DIE", 1, CaseSensitivity::Simple)
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        (found.start.char_position, found.start.byte_position),
+        (13, 13)
+    );
+    let options = SearchOptions::new().case(CaseSensitivity::Full);
+    let found = r.find_with("straße", 0, options).unwrap().unwrap();
+    assert_eq!(
+        (found.start.byte_position, found.end.byte_position),
+        (4, 11)
+    );
+    let found = r
+        .find_with("Straße", 0, CaseSensitivity::Sensitive)
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        (found.start.char_position, found.end.char_position),
+        (17, 23)
+    );
+    assert!(r
+        .find_with("dog", 0, CaseSensitivity::Full)
+        .unwrap()
+        .is_none());
+    assert!(r.find_with("die", 40, CaseSensitivity::Full).is_err());
+
+    // Other scripts fold too, like final sigma
+    let r = MappedFile::from("ΣΊΣΥΦΟΣ σίσυφος");
+    assert_eq!(
+        match_spans(&r, "σίσυφοσ", CaseSensitivity::Simple),
+        [(0, 7), (8, 15)]
+    );
+
+    let r = InMemoryFile::from_bytes(b"abc\xFFABC".to_vec());
+    assert!(r.find_with("x", 0, CaseSensitivity::Simple).is_err());
+}
+
+/// Collects the text of some lines
+fn texts<'a>(lines: &[LineRef<'a>]) -> Vec<&'a str> {
+    lines.iter().map(|l| l.text).collect()
+}
+
+#[test]
+pub fn test_grep() {
+    let r =
+        MappedFile::from("start\nerror: one\nfine\nfine\nerror: two error\nfine\nerror: thrée\n");
+    let found: Vec<_> = r.grep("error", 1, 1).collect();
+    assert_eq!(found.len(), 3);
+
+    assert_eq!(found[0].line.line, 1);
+    assert_eq!(found[0].line.text, "error: one");
+    assert_eq!(
+        (
+            found[0].position.char_position,
+            found[0].position.byte_position
+        ),
+        (6, 6)
+    );
+    assert_eq!(texts(&found[0].before), ["start"]);
+    assert_eq!(texts(&found[0].after), ["fine"]);
+
+    // A line with two matches is only given once
+    assert_eq!(found[1].line.line, 4);
+    assert_eq!(found[1].line.start.char_position, 27);
+    assert_eq!(texts(&found[1].before), ["fine"]);
+
+    // Context stops at the ends of the file, which ends with an empty line
+    assert_eq!(found[2].line.text, "error: thrée");
+    assert_eq!(texts(&found[2].after), [""]);
+    let found: Vec<_> = r.grep("start", 3, 0).collect();
+    assert_eq!(found.len(), 1);
+    assert!(found[0].before.is_empty() && found[0].after.is_empty());
+
+    let lines: Vec<usize> = r.grep("fine", 0, 0).map(|m| m.line.line).collect();
+    assert_eq!(lines, [2, 3, 5]);
+    assert_eq!(r.grep("missing", 2, 2).count(), 0);
+}
+
+#[test]
+pub fn test_bisect_line() {
+    let words = [
+        "aardvark", "apple", "apples", "banana", "band", "bandana", "cherry", "zebra", "çà", "été",
+    ];
+    for terminator in ["\n", "\r\n"] {
+        let text = words.join(terminator) + terminator;
+        let r = MappedFile::from(text.as_str());
+        let keys = [
+            "a", "apple", "ban", "band", "c", "z", "zebra", "é", "ç", "b", "bandanas", "d", "zz",
+            "0",
+        ];
+        for key in keys {
+            let expected = words.iter().position(|word| word.starts_with(key));
+            assert_eq!(r.bisect_line(key).unwrap(), expected, "{key}");
+        }
+        assert_eq!(r.bisect_line("").unwrap(), Some(0));
+    }
+
+    // Enough lines that most of them are skipped over
+    let text: String = (0..20000).map(|n| format!("{n:06}\n")).collect();
+    let r = MappedFile::from(text.as_str());
+    assert_eq!(r.bisect_line("012345").unwrap(), Some(12345));
+    assert_eq!(r.bisect_line("0199").unwrap(), Some(19900));
+    assert_eq!(r.bisect_line("00000").unwrap(), Some(0));
+    assert_eq!(r.bisect_line("019999").unwrap(), Some(19999));
+    assert_eq!(r.bisect_line("02").unwrap(), None);
+    assert_eq!(MappedFile::from("").bisect_line("a").unwrap(), None);
+
+    // Lines are split with the file's own line endings
+    let mut r = MappedFile::from("apple\u{2028}banana\rcherry\r\ndate\u{85}fig");
+    r.set_line_endings(LineEndings::unicode());
+    let keys = [
+        ("a", Some(0)),
+        ("banana", Some(1)),
+        ("cherry", Some(2)),
+        ("cherry\r", None),
+        ("d", Some(3)),
+        ("date\u{85}", None),
+        ("fig", Some(4)),
+        ("e", None),
+    ];
+    for (key, expected) in keys {
+        assert_eq!(r.bisect_line(key).unwrap(), expected, "{key}");
+    }
+}
+
+#[test]
+pub fn test_stats() {
+    let r = MappedFile::from("Hello wörld\r\n  two  words \n\nthe last line");
+    let stats = r.stats().unwrap();
+    assert_eq!(
+        stats,
+        FileStats {
+            bytes: 42,
+            chars: 41,
+            lines: 4,
+            words: 7,
+            max_line_len: 13,
+        }
+    );
+    assert_eq!(stats.chars, r.char_count().unwrap());
+    assert_eq!(stats.lines, r.line_count().unwrap());
+    // Cached after the first time
+    assert_eq!(r.stats().unwrap(), stats);
+
+    // A \r\n split across the stretches the file is read in is still one terminator
+    let text = format!("{}\r\n{}", "x".repeat(65535), "ü".repeat(10));
+    let mut r = MappedFile::from(text.as_str());
+    r.set_line_endings(LineEndings::crlf());
+    let stats = r.stats().unwrap();
+    assert_eq!((stats.chars, stats.lines, stats.words), (65547, 2, 2));
+    assert_eq!(stats.max_line_len, 65535);
+    // Changing the line endings counts again
+    r.set_line_endings(LineEndings::lf());
+    assert_eq!(r.stats().unwrap().max_line_len, 65536);
+
+    assert_eq!(
+        MappedFile::from("").stats().unwrap(),
+        FileStats {
+            lines: 1,
+            ..FileStats::default()
+        }
+    );
+    let r = InMemoryFile::from_bytes(b"ok\n\xFF".to_vec());
+    assert!(r.stats().is_err());
+}
+
+#[test]
+pub fn test_char_histogram() {
+    let r = MappedFile::from("abracadabra\tüü\u{7}");
+    let counts = r.char_histogram(0..15).unwrap();
+    let expected = [
+        ('\u{7}', 1),
+        ('\t', 1),
+        ('a', 5),
+        ('b', 2),
+        ('c', 1),
+        ('d', 1),
+        ('r', 2),
+        ('ü', 2),
+    ];
+    assert_eq!(counts.into_iter().collect::<Vec<_>>(), expected);
+
+    let counts = r.char_histogram(11..14).unwrap();
+    assert_eq!(
+        counts.into_iter().collect::<Vec<_>>(),
+        [('\t', 1), ('ü', 2)]
+    );
+    assert!(r.char_histogram(3..3).unwrap().is_empty());
+    assert!(r.char_histogram(0..16).is_err());
+
+    // Invalid bytes count as replacement characters when decoding lossily
+    let mut r = InMemoryFile::from_bytes(b"a\xFFb\xFE".to_vec());
+    assert!(r.char_histogram(0..2).is_err());
+    r.set_decode_policy(DecodePolicy::Lossy);
+    assert_eq!(
+        r.char_histogram(0..4).unwrap()[&char::REPLACEMENT_CHARACTER],
+        2
+    );
+}
+
+#[test]
+pub fn test_ascii_prefix() {
+    let mut text = String::new();
+    for i in 0..20000 {
+        text.push_str(&format!("line {}\n", i));
+    }
+    let ascii_len = text.len();
+    text.push_str("naïve ending");
+
+    let r = InMemoryFile::from(text.clone());
+    assert_eq!(r.ascii_prefix_len(), 0);
+    assert!(!r.is_ascii().unwrap());
+    assert_eq!(r.ascii_prefix_len(), ascii_len + 2);
+
+    // Lookups in the prefix don't scan, past it they carry on as usual
+    assert_eq!(r.unicode_at(ascii_len - 2).unwrap(), '9');
+    assert_eq!(r.byte_offset_of(1000).unwrap(), 1000);
+    assert_eq!(r.char_index_at_byte(5000).unwrap().char_position, 5000);
+    assert_eq!(r.line_ending_positions().unwrap().len(), 1);
+    assert_eq!(r.unicode_at(ascii_len + 3).unwrap(), 'v');
+    assert_eq!(r.byte_offset_of(ascii_len + 3).unwrap(), ascii_len + 4);
+    assert_eq!(r.char_count().unwrap(), text.chars().count());
+    assert_eq!(r.line_count().unwrap(), 20001);
+
+    // Scanning for lines finds the prefix too
+    let r = InMemoryFile::from(String::from("plain\nascii\n"));
+    assert_eq!(r.line_count().unwrap(), 3);
+    assert_eq!(r.ascii_prefix_len(), 12);
+    assert!(r.is_ascii().unwrap());
+    assert_eq!(r.char_count().unwrap(), 12);
+    assert_eq!(r.str_range(6..11).unwrap(), "ascii");
+    r.clear_index();
+    assert_eq!(r.ascii_prefix_len(), 0);
+}
+
+#[test]
+pub fn test_binary_units() {
+    let bytes = b"head\xFF\xFE\x80 ok\n\xE2\x80n\xC3".to_vec();
+    let mut r = InMemoryFile::from_bytes(bytes);
+    r.set_decode_policy(DecodePolicy::Bytes);
+
+    // Every invalid byte is a unit of its own
+    assert_eq!(r.char_count().unwrap(), 15);
+    assert_eq!(r.line_count().unwrap(), 2);
+    assert_eq!(r.unit_at(3).unwrap(), Unit::Char('d'));
+    assert_eq!(r.unit_at(5).unwrap(), Unit::Byte(0xFE));
+    assert_eq!(r.unit_at(12).unwrap(), Unit::Byte(0x80));
+    assert_eq!(r.unit_at(14).unwrap(), Unit::Byte(0xC3));
+    assert_eq!(r.unicode_at(6).unwrap(), char::REPLACEMENT_CHARACTER);
+    assert_eq!(r.byte_offset_of(13).unwrap(), 13);
+    assert_eq!(
+        r.units_range(10..14).unwrap(),
+        [
+            Unit::Char('\n'),
+            Unit::Byte(0xE2),
+            Unit::Byte(0x80),
+            Unit::Char('n')
+        ]
+    );
+    assert_eq!(r.line_string(1).unwrap(), "\u{FFFD}\u{FFFD}n\u{FFFD}");
+    assert_eq!(r.chars_rev_from(15).count(), 15);
+    assert_eq!(
+        r.unit_at(15).unwrap_err().to_string(),
+        r.unicode_at(15).unwrap_err().to_string()
+    );
+
+    // Decoding lossily, a whole invalid sequence is one unit
+    r.set_decode_policy(DecodePolicy::Lossy);
+    assert_eq!(r.char_count().unwrap(), 14);
+    assert_eq!(r.unit_at(4).unwrap(), Unit::Byte(0xFF));
+    assert_eq!(r.unit_at(11).unwrap(), Unit::Byte(0xE2));
+    assert_eq!(
+        Unit::Byte(0xE2).to_char_lossy(),
+        char::REPLACEMENT_CHARACTER
+    );
+    assert_eq!(Unit::Char('x').as_char(), Some('x'));
+
+    r.set_decode_policy(DecodePolicy::Strict);
+    assert_eq!(r.unit_at(0).ok(), None);
+}
+
+#[test]
+pub fn test_bytes_range() {
+    let r = InMemoryFile::from_bytes(b"h\xC3\xA9llo \xFF".to_vec());
+    assert_eq!(r.bytes_range(1..3).unwrap(), "é".as_bytes());
+    assert_eq!(r.bytes_range(0..8).unwrap(), b"h\xC3\xA9llo \xFF");
+    assert_eq!(r.bytes_range(8..8).unwrap(), b"");
+    assert_eq!(r.byte_at(2).unwrap(), 0xA9);
+    assert_eq!(r.byte_at(7).unwrap(), 0xFF);
+    assert!(matches!(
+        r.bytes_range(4..9),
+        Err(Error::OutOfBounds { index: 9, len: 8 })
+    ));
+    assert!(matches!(
+        r.byte_at(8),
+        Err(Error::OutOfBounds { index: 8, len: 8 })
+    ));
+}
+
+#[test]
+pub fn test_char_with_position() {
+    let r = InMemoryFile::from(String::from("añb\n😀c"));
+    let (c, position) = r.char_with_position(4).unwrap();
+    assert_eq!(c, '😀');
+    assert_eq!(position.char_position, 4);
+    assert_eq!(position.byte_position, 5);
+    assert_eq!(r.str_range(4..6).unwrap(), "😀c");
+
+    let (c, position) = r.char_with_position(5).unwrap();
+    assert_eq!((c, position.byte_position), ('c', 9));
+    assert!(matches!(
+        r.char_with_position(6),
+        Err(Error::OutOfBounds { index: 6, len: 6 })
+    ));
+}
+
+#[test]
+pub fn test_char_and_len() {
+    let mut r = InMemoryFile::from_bytes("aé€😀\u{FFFD}".bytes().chain(*b"\xE2\x82").collect());
+    assert!(matches!(r.char_and_len(0), Err(Error::InvalidUtf8 { .. })));
+
+    // A replacement is as long as the bytes it stands in for, not the utf8 for U+FFFD
+    r.set_decode_policy(DecodePolicy::Lossy);
+    for (index, expected) in [('a', 1), ('é', 2), ('€', 3), ('😀', 4), ('\u{FFFD}', 3)]
+        .into_iter()
+        .enumerate()
+    {
+        assert_eq!(r.char_and_len(index).unwrap(), expected);
+    }
+    assert_eq!(r.char_and_len(5).unwrap(), ('\u{FFFD}', 2));
+    assert!(matches!(
+        r.char_and_len(6),
+        Err(Error::OutOfBounds { index: 6, len: 6 })
+    ));
+}
+
+#[test]
+pub fn test_char_after_and_before_byte() {
+    let r = InMemoryFile::from(String::from("a€b😀"));
+    assert_eq!(r.char_after_byte(0).unwrap(), Some(('a', 0)));
+    assert_eq!(r.char_after_byte(1).unwrap(), Some(('€', 1)));
+    // Offsets in the middle of a character snap to whichever side of it is asked for
+    assert_eq!(r.char_after_byte(2).unwrap(), Some(('b', 4)));
+    assert_eq!(r.char_after_byte(6).unwrap(), None);
+    assert_eq!(r.char_after_byte(9).unwrap(), None);
+    assert_eq!(r.char_before_byte(9).unwrap(), Some(('😀', 5)));
+    assert_eq!(r.char_before_byte(7).unwrap(), Some(('b', 4)));
+    assert_eq!(r.char_before_byte(4).unwrap(), Some(('€', 1)));
+    assert_eq!(r.char_before_byte(2).unwrap(), Some(('a', 0)));
+    assert_eq!(r.char_before_byte(0).unwrap(), None);
+    assert!(matches!(
+        r.char_after_byte(10),
+        Err(Error::OutOfBounds { index: 10, len: 9 })
+    ));
+    // Nothing had to be indexed
+    assert_eq!(r.index_stats().indexed_bytes, 0);
+
+    let mut r = InMemoryFile::from_bytes(b"ok\xFFx".to_vec());
+    assert_eq!(r.char_after_byte(3).unwrap(), Some(('x', 3)));
+    assert!(matches!(
+        r.char_after_byte(2),
+        Err(Error::InvalidUtf8 { byte_offset: 2, .. })
+    ));
+    assert!(matches!(
+        r.char_before_byte(3),
+        Err(Error::InvalidUtf8 { byte_offset: 2, .. })
+    ));
+    r.set_decode_policy(DecodePolicy::Lossy);
+    assert_eq!(r.char_before_byte(3).unwrap(), Some(('\u{FFFD}', 2)));
+}
+
+#[test]
+pub fn test_visual_column() {
+    let r = InMemoryFile::from(String::from("first\n\tif x\t{\nab\tc\t"));
+    assert_eq!(r.visual_column(1, 0, 4).unwrap(), 0);
+    assert_eq!(r.visual_column(1, 1, 4).unwrap(), 4);
+    assert_eq!(r.visual_column(1, 5, 4).unwrap(), 8);
+    assert_eq!(r.visual_column(1, 6, 8).unwrap(), 16);
+    assert_eq!(r.visual_column(2, 3, 4).unwrap(), 4);
+    assert_eq!(r.visual_column(2, 5, 4).unwrap(), 8);
+    assert_eq!(r.visual_column(0, 3, 4).unwrap(), 3);
+    assert_eq!(r.visual_column(2, 3, 0).unwrap(), 2);
+    assert!(matches!(
+        r.visual_column(2, 6, 4),
+        Err(Error::OutOfBounds { index: 6, len: 5 })
+    ));
+    assert!(r.visual_column(3, 0, 4).is_err());
+
+    // Going back, every visual column inside a tab is the tab
+    for visual in 0..4 {
+        assert_eq!(r.column_at_visual(1, visual, 4).unwrap(), 0);
+    }
+    assert_eq!(r.column_at_visual(1, 4, 4).unwrap(), 1);
+    assert_eq!(r.column_at_visual(1, 7, 4).unwrap(), 4);
+    assert_eq!(r.column_at_visual(1, 9, 4).unwrap(), 5);
+    assert_eq!(r.column_at_visual(1, 12, 4).unwrap(), 6);
+    assert_eq!(r.column_at_visual(2, 8, 4).unwrap(), 5);
+    assert!(matches!(
+        r.column_at_visual(2, 9, 4),
+        Err(Error::OutOfBounds { index: 9, len: 8 })
+    ));
+    for column in 0..=5 {
+        let visual = r.visual_column(2, column, 4).unwrap();
+        assert_eq!(r.column_at_visual(2, visual, 4).unwrap(), column);
+    }
+}
@@ -0,0 +1,33 @@
+#![cfg(feature = "regex")]
+
+use random_access_unicode::*;
+use regex::Regex;
+
+#[test]
+pub fn test_regex_find_iter() {
+    let r = MappedFile::from("née 1999, créée 2024\nfin 7");
+    let digits = Regex::new(r"\d+").unwrap();
+
+    let found: Vec<_> = r
+        .regex_find_iter(&digits, 0..26)
+        .unwrap()
+        .map(|m| (m.char_range(), m.start.byte_position, m.text))
+        .collect();
+    assert_eq!(
+        found,
+        [(4..8, 5, "1999"), (16..20, 19, "2024"), (25..26, 28, "7")]
+    );
+
+    // Positions are still counted from the start of the file
+    let words = Regex::new(r"\w+").unwrap();
+    let found: Vec<_> = r
+        .regex_find_iter(&words, 10..21)
+        .unwrap()
+        .map(|m| (m.char_range(), m.text))
+        .collect();
+    assert_eq!(found, [(10..15, "créée"), (16..20, "2024")]);
+
+    assert!(r.regex_find_iter(&digits, 0..27).is_err());
+    let invalid = InMemoryFile::from_bytes(b"12\xFF34".to_vec());
+    assert!(invalid.regex_find_iter(&digits, 0..4).is_err());
+}
@@ -0,0 +1,60 @@
+#![cfg(feature = "segmentation")]
+
+use std::fs::File;
+use std::io::Write;
+
+use random_access_unicode::*;
+
+#[test]
+pub fn test_words() {
+    let mut file = File::create("test_words.txt").unwrap();
+    write!(file, "Hello, wörld!\ncan't stop").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_words.txt").unwrap()).unwrap();
+
+    assert_eq!(r.word_at(16).unwrap(), (14..19, "can't"));
+    assert_eq!(r.word_at(2).unwrap(), (0..5, "Hello"));
+    assert_eq!(r.word_at(6).unwrap(), (6..7, " "));
+    assert_eq!(r.word_at(10).unwrap(), (7..12, "wörld"));
+    assert_eq!(r.word_at(23).unwrap(), (20..24, "stop"));
+    assert!(matches!(r.word_at(24), Err(Error::OutOfBounds { .. })));
+
+    assert_eq!(
+        r.words().collect::<Result<Vec<_>, _>>().unwrap(),
+        vec![
+            (0..5, "Hello"),
+            (7..12, "wörld"),
+            (14..19, "can't"),
+            (20..24, "stop")
+        ]
+    );
+
+    // The words before a line that isn't valid utf8 still come out, then the error
+    let r = MappedFile::from_bytes(b"good words\nbad \xFF line\nnever".to_vec());
+    let words: Vec<_> = r.words().collect();
+    assert_eq!(words.len(), 3);
+    assert_eq!(words[1].as_ref().unwrap(), &(5..10, "words"));
+    assert!(matches!(words[2], Err(Error::InvalidUtf8 { .. })));
+}
+
+#[test]
+pub fn test_sentences() {
+    let mut file = File::create("test_sentences.txt").unwrap();
+    write!(file, "One. Two?\nThree! Four\nFive.").unwrap();
+    file.flush().unwrap();
+
+    let r = MappedFile::new(File::open("test_sentences.txt").unwrap()).unwrap();
+
+    assert_eq!(r.sentence_at(7).unwrap(), (5..10, "Two?\n"));
+    assert_eq!(r.sentence_at(0).unwrap(), (0..5, "One. "));
+    assert_eq!(r.sentence_at(25).unwrap(), (22..27, "Five."));
+    assert!(matches!(r.sentence_at(27), Err(Error::OutOfBounds { .. })));
+
+    assert_eq!(
+        r.sentences_in_lines(1..2).unwrap().collect::<Vec<_>>(),
+        vec![(10..17, "Three! "), (17..22, "Four\n")]
+    );
+    assert_eq!(r.sentences_in_lines(2..2).unwrap().count(), 0);
+    assert!(r.sentences_in_lines(3..4).is_err());
+}
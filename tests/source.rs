@@ -0,0 +1,122 @@
+use std::fs::File;
+use std::io::Write;
+
+use random_access_unicode::*;
+
+/// Runs the same lookups over any source
+fn check<S: TextSource>(r: MappedFile<S>) {
+    assert_eq!(r.unicode_at(5).unwrap(), '😀');
+    assert_eq!(r.line_at(1).unwrap(), "€😀 straddles");
+    assert_eq!(r.str_range(1..3).unwrap(), "ñb");
+    assert_eq!(r.chars_rev_from(3).collect::<String>(), "bña");
+    assert_eq!(r.char_index_at_byte(9).unwrap().char_position, 5);
+    assert_eq!(r.char_count().unwrap(), 23);
+    assert_eq!(r.line_count().unwrap(), 3);
+}
+
+#[test]
+pub fn test_sources() {
+    let text = "añb\n€😀 straddles\nblocks";
+
+    let mut file = File::create("test_sources.txt").unwrap();
+    write!(file, "{}", text).unwrap();
+    file.flush().unwrap();
+
+    check(MappedFile::new(File::open("test_sources.txt").unwrap()).unwrap());
+    check(MappedFile::from_source(text));
+    check(MappedFile::from_source(text.as_bytes().to_vec()));
+
+    // Tiny blocks so characters and lines cross them
+    let source =
+        BufferedSource::with_block_size(File::open("test_sources.txt").unwrap(), 3).unwrap();
+    check(MappedFile::from_source(source));
+
+    let source = Windowed::new(File::open("test_sources.txt").unwrap(), 0).unwrap();
+    check(MappedFile::from_source(source));
+}
+
+#[test]
+pub fn test_windowed() {
+    let mut file = File::create("test_windowed.txt").unwrap();
+    for line in 0..20000 {
+        writeln!(file, "línea {} €😀", line).unwrap();
+    }
+    file.flush().unwrap();
+
+    // Characters straddle the edges of the smallest windows, and jumping around maps different parts
+    let source = Windowed::new(File::open("test_windowed.txt").unwrap(), 0).unwrap();
+    let mut r = MappedFile::from_source(source);
+    assert_eq!(r.line_at(19999).unwrap(), "línea 19999 €😀");
+    assert_eq!(r.line_at(3).unwrap(), "línea 3 €😀");
+    assert_eq!(r.unicode_at(9).unwrap(), '😀');
+    assert_eq!(r.str_range(0..7).unwrap(), "línea 0");
+    assert!(matches!(r.line_at(20001), Err(Error::OutOfBounds { .. })));
+
+    // Windows lines were borrowed from stay mapped until they're released
+    assert!(r.map.mapped_windows() > 1);
+    r.release();
+    assert_eq!(r.map.mapped_windows(), 1);
+
+    // Scanning only ever needs the window it's in
+    r.clear_index();
+    assert_eq!(r.line_count().unwrap(), 20001);
+    assert_eq!(r.map.mapped_windows(), 1);
+
+    let m = MappedFile::new(File::open("test_windowed.txt").unwrap()).unwrap();
+    assert_eq!(r.char_count().unwrap(), m.char_count().unwrap());
+    for line in [0, 4567, 20000] {
+        assert_eq!(r.line_at(line).unwrap(), m.line_at(line).unwrap());
+    }
+}
+
+#[test]
+pub fn test_windowed_scans() {
+    let mut file = File::create("test_windowed_scans.txt").unwrap();
+    for line in 0..100000 {
+        writeln!(file, "línea {} €😀", line).unwrap();
+    }
+    file.flush().unwrap();
+
+    let source = Windowed::new(File::open("test_windowed_scans.txt").unwrap(), 0).unwrap();
+    let r = MappedFile::from_source(source);
+    let m = MappedFile::new(File::open("test_windowed_scans.txt").unwrap()).unwrap();
+
+    // Scans over the whole file only ever keep the window they're in
+    assert_eq!(r.stats().unwrap(), m.stats().unwrap());
+    assert_eq!(r.map.mapped_windows(), 1);
+    assert_eq!(r.find_all("😀\nl").count(), 99999);
+    assert_eq!(r.map.mapped_windows(), 1);
+    assert_eq!(
+        r.rfind("línea 0 ", r.char_count().unwrap())
+            .unwrap()
+            .unwrap()
+            .char_position,
+        0
+    );
+    assert_eq!(r.map.mapped_windows(), 1);
+    assert!(r.validate().is_ok());
+    assert_eq!(r.map.mapped_windows(), 1);
+
+    let len = r.char_count().unwrap();
+    assert_eq!(
+        r.char_histogram(0..len).unwrap(),
+        m.char_histogram(0..len).unwrap()
+    );
+    assert_eq!(r.map.mapped_windows(), 1);
+    assert_eq!(r.bisect_line("línea 99999").unwrap(), Some(99999));
+    assert_eq!(r.map.mapped_windows(), 1);
+}
+
+#[test]
+pub fn test_in_memory() {
+    let r = InMemoryFile::from(String::from("Hello\nwörld!"));
+    assert_eq!(r.unicode_at(7).unwrap(), 'ö');
+    assert_eq!(r.line_at(1).unwrap(), "wörld!");
+
+    let r = MappedFile::from_bytes(vec![b'a', 0xC3, 0xB1]);
+    assert_eq!(r.unicode_at(1).unwrap(), 'ñ');
+    assert!(matches!(r.unicode_at(2), Err(Error::OutOfBounds { .. })));
+
+    let r = MappedFile::from("borrowed");
+    assert_eq!(r.str_range(0..6).unwrap(), "borrow");
+}
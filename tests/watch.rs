@@ -0,0 +1,54 @@
+#![cfg(feature = "watch")]
+
+use std::fs::File;
+use std::io::Write;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use random_access_unicode::*;
+
+/// Waits for the watcher to pick up the next change
+fn next_change(changes: &mpsc::Receiver<Change>) -> Change {
+    changes.recv_timeout(Duration::from_secs(10)).unwrap()
+}
+
+#[test]
+pub fn test_watch() {
+    let mut file = File::create("test_watch.txt").unwrap();
+    writeln!(file, "first").unwrap();
+    file.flush().unwrap();
+
+    let (sender, changes) = mpsc::channel();
+    let watched = MappedFile::open_path("test_watch.txt")
+        .unwrap()
+        .watch(move |change| sender.send(change).unwrap())
+        .unwrap();
+    assert_eq!(watched.read().line_at(0).unwrap(), "first");
+
+    write!(file, "sécond\nthird").unwrap();
+    file.flush().unwrap();
+    match next_change(&changes) {
+        Change::Appended(appended) => assert_eq!(appended.lines, 1..3),
+        change => panic!("expected lines to be appended, got {:?}", change),
+    }
+    assert_eq!(watched.read().line_at(2).unwrap(), "third");
+
+    file.set_len(3).unwrap();
+    assert!(matches!(next_change(&changes), Change::Truncated));
+    assert_eq!(watched.read().line_at(0).unwrap(), "fir");
+
+    // Rotating the log puts a new file at the path
+    std::fs::rename("test_watch.txt", "test_watch_rotated.txt").unwrap();
+    let mut file = File::create("test_watch.txt").unwrap();
+    write!(file, "new").unwrap();
+    file.flush().unwrap();
+    // The new file might be picked up before or after it's written to
+    assert!(matches!(next_change(&changes), Change::Replaced));
+    while watched.read().line_at(0).unwrap() != "new" {
+        assert!(matches!(next_change(&changes), Change::Appended(_)));
+    }
+
+    // Without a path there's nothing to watch
+    let unnamed = MappedFile::new(File::open("test_watch.txt").unwrap()).unwrap();
+    assert!(unnamed.watch(|_| {}).is_err());
+}
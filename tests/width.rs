@@ -0,0 +1,33 @@
+#![cfg(feature = "width")]
+
+use random_access_unicode::*;
+
+#[test]
+pub fn test_display_width() {
+    let r = MappedFile::from("日本語 text\ne\u{301}\u{7}x\n");
+    assert_eq!(r.display_width(0..3).unwrap(), 6);
+    assert_eq!(r.display_width(0..8).unwrap(), 11);
+    // Combining marks and control characters take up no columns
+    assert_eq!(r.display_width(9..13).unwrap(), 2);
+    assert!(r.display_width(0..15).is_err());
+}
+
+#[test]
+pub fn test_char_at_display_col() {
+    let r = MappedFile::from("日本語 text\ne\u{301}\u{7}x\n");
+    let index = |line, column| r.char_at_display_col(line, column).unwrap().char_position;
+    assert_eq!(index(0, 0), 0);
+    // Both columns of a wide character are that character
+    assert_eq!(index(0, 3), 1);
+    assert_eq!(index(0, 4), 2);
+    assert_eq!(index(0, 6), 3);
+    assert_eq!(index(0, 10), 7);
+    assert_eq!(index(1, 1), 12);
+    let end = r.char_at_display_col(0, 11).unwrap();
+    assert_eq!((end.char_position, end.byte_position), (8, 14));
+    assert!(matches!(
+        r.char_at_display_col(0, 12),
+        Err(Error::OutOfBounds { index: 12, len: 11 })
+    ));
+    assert!(r.char_at_display_col(3, 0).is_err());
+}